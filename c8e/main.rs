@@ -2,6 +2,7 @@
 // Crate Imports
 //
 extern crate c8lib;
+#[macro_use]
 extern crate clap;
 #[macro_use]
 extern crate slog;
@@ -11,23 +12,23 @@ extern crate slog_term;
 //
 // Rust Core Imports
 //
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 
 //
 // Third Party Imports
 //
-use clap::{Arg, App};
+use clap::{Arg, App, AppSettings, SubCommand};
 use slog::DrainExt;
 
 
 fn main() {
-    let log = slog::Logger::root(slog_term::streamer().full().build().fuse(),
-                                 o!("c8e_version" => env!("CARGO_PKG_VERSION")));
-
     let matches = App::new("Chip 8 Emulator")
         .version("0.1.0")
         .author("Scott Schroeder <scottschroeder@sent.com>")
         .about("c8e is pronounced 'Sadie'")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(Arg::with_name("rom_path")
             .short("r")
             .long("rom")
@@ -37,26 +38,387 @@ fn main() {
             .required(true))
         .arg(Arg::with_name("debugger")
             .short("d")
-            .help("Run this ROM with the console debugger"))
+            .help("Run this ROM with the console debugger, F12 in the window pauses"))
         .arg(Arg::with_name("disassemble")
             .short("p")
-            .help("Dump the instructions for this ROM"))
+            .help("Disassemble this ROM, following its code from 0x200"))
+        .arg(Arg::with_name("memory")
+            .long("memory")
+            .value_name("POLICY")
+            .possible_values(&["wrap", "fault", "ignore"])
+            .default_value("fault")
+            .help("What to do when the ROM accesses memory outside of RAM"))
+        .arg(Arg::with_name("machine")
+            .long("machine")
+            .value_name("MACHINE")
+            .possible_values(&["chip8", "schip", "xochip"])
+            .default_value("chip8")
+            .help("The instruction set this ROM was written for"))
+        .arg(Arg::with_name("quirks")
+            .long("quirks")
+            .value_name("PRESET")
+            .possible_values(&["default", "vip", "chip48", "schip", "xochip"])
+            .help("Match the behavior of a specific CHIP-8 interpreter [default: from the \
+                   machine]"))
+        .arg(Arg::with_name("seed")
+            .long("seed")
+            .value_name("SEED")
+            .help("Seed the random number generator, to replay a previous run"))
+        .arg(Arg::with_name("ipf")
+            .long("ipf")
+            .value_name("N")
+            .default_value("8")
+            .help("How many instructions to run in each 60Hz frame"))
+        .arg(Arg::with_name("break")
+            .long("break")
+            .value_name("WHERE")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Drop into the debugger at an address, an opcode like Draw, or 'if EXPR'"))
+        .arg(Arg::with_name("watch")
+            .long("watch")
+            .value_name("RANGE")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Drop into the debugger after a write to 0x300, 0x300-0x30f or 0x300+16"))
+        .arg(Arg::with_name("gdb")
+            .long("gdb")
+            .value_name("PORT")
+            .help("Wait for gdb to connect on localhost:PORT and let it control the ROM"))
+        .arg(Arg::with_name("dap")
+            .long("dap")
+            .value_name("stdio|PORT")
+            .help("Let an editor debug the ROM over the Debug Adapter Protocol"))
+        .arg(Arg::with_name("listing")
+            .long("listing")
+            .value_name("FILE")
+            .help("Assembler listing, so the debug adapter can show source lines"))
+        .arg(Arg::with_name("trace")
+            .long("trace")
+            .value_name("FILE")
+            .help("Record every instruction run to FILE"))
+        .arg(Arg::with_name("trace_format")
+            .long("trace-format")
+            .value_name("FORMAT")
+            .possible_values(&["jsonl", "binary"])
+            .default_value("jsonl")
+            .help("Write the trace as JSON lines or compact binary records"))
+        .arg(Arg::with_name("trace_start")
+            .long("trace-start")
+            .value_name("WHEN")
+            .requires("trace")
+            .help("Start tracing at a cycle number, or the first time pc=ADDR runs"))
+        .arg(Arg::with_name("trace_stop")
+            .long("trace-stop")
+            .value_name("WHEN")
+            .requires("trace")
+            .help("Stop tracing at a cycle number, or the first time pc=ADDR runs"))
+        .arg(Arg::with_name("record_coverage")
+            .long("coverage")
+            .value_name("FILE")
+            .help("Record which addresses are run, read, written and drawn to FILE"))
+        .subcommand(SubCommand::with_name("trace-diff")
+            .about("Run the ROM headless against a recorded trace, stopping where they differ")
+            .arg(Arg::with_name("reference")
+                .value_name("TRACE")
+                .required(true)
+                .help("A trace from --trace, or JSON lines in the same format")))
+        .subcommand(SubCommand::with_name("cfg")
+            .about("Write the ROM's basic blocks and call graph")
+            .arg(Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .possible_values(&["dot", "json"])
+                .default_value("dot")
+                .help("Graphviz DOT or JSON"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Where to write the graph [default: stdout]")))
+        .subcommand(SubCommand::with_name("coverage")
+            .about("Disassemble the ROM using what --coverage saw it do, and say how much was used")
+            .arg(Arg::with_name("maps")
+                .value_name("MAP")
+                .required(true)
+                .multiple(true)
+                .help("Coverage maps from --coverage, runs are combined"))
+            .arg(Arg::with_name("summary")
+                .long("summary")
+                .help("Only print how much of the ROM was used")))
+        .subcommand(SubCommand::with_name("asm")
+            .about("Assemble source in the disassembly syntax into a ROM")
+            .arg(Arg::with_name("source")
+                .value_name("SOURCE")
+                .required(true)
+                .help("Instructions like 'LOAD V0 0x07', with labels, constants and db/dw data"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Where to write the ROM [default: SOURCE with a .ch8 extension]"))
+            .arg(Arg::with_name("listing")
+                .long("listing")
+                .value_name("FILE")
+                .help("Also write a listing, for the debug adapter's --listing")))
+        .subcommand(SubCommand::with_name("octo")
+            .about("Assemble an Octo program and run it")
+            .arg(Arg::with_name("source")
+                .value_name("SOURCE")
+                .required(true)
+                .help("Octo source, usually a .8o file"))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Also write the ROM to FILE"))
+            .arg(Arg::with_name("listing")
+                .long("listing")
+                .value_name("FILE")
+                .help("Also write a listing, for the debug adapter's --listing")))
         .get_matches();
 
-    let rom_path = matches.value_of("rom_path").unwrap(); //Required arg
-    let mut chip8 = c8lib::Chip8::init(Some(log));
-    let rom_bytes = chip8.load_rom(PathBuf::from(rom_path)).unwrap();
+    if let Some(asm) = matches.subcommand_matches("asm") {
+        let source = Path::new(asm.value_of("source").unwrap()); //Required arg
+        let output = asm.value_of("output")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| source.with_extension("ch8"));
+        let listing = asm.value_of("listing").map(Path::new);
+        if overwrites(source, Some(&output)) || overwrites(source, listing) {
+            println!("Error: {} would be overwritten, use -o FILE", source.display());
+            std::process::exit(1);
+        }
+        let assembled = assemble(source, c8lib::assemble).and_then(|program| {
+            write_program(&program, Some(&output), listing)
+        });
+        if let Err(e) = assembled {
+            println!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    if matches.is_present("debugger") {
-        chip8.set_debug(true)
+    // The debug adapter, graphs and listings may need stdout to themselves
+    let quiet = matches.is_present("cfg") || matches.is_present("coverage");
+    let log = if matches.value_of("dap") == Some("stdio") || quiet {
+        slog::Logger::root(slog_term::streamer().stderr().full().build().fuse(),
+                           o!("c8e_version" => env!("CARGO_PKG_VERSION")))
     } else {
-        chip8.set_debug(false)
+        slog::Logger::root(slog_term::streamer().full().build().fuse(),
+                           o!("c8e_version" => env!("CARGO_PKG_VERSION")))
+    };
+
+    let mut config = c8lib::Config::default();
+    config.memory_policy = value_t_or_exit!(matches, "memory", c8lib::MemoryPolicy);
+    config.machine = value_t_or_exit!(matches, "machine", c8lib::Machine);
+    config.quirks = if matches.is_present("quirks") {
+        value_t_or_exit!(matches, "quirks", c8lib::Quirks)
+    } else {
+        config.machine.quirks()
+    };
+    if matches.is_present("seed") {
+        config.seed = Some(value_t_or_exit!(matches, "seed", u64));
+    }
+    let mut chip8 = c8lib::Chip8::with_config(Some(log), config);
+    chip8.set_cycles_per_frame(value_t_or_exit!(matches, "ipf", u64));
+    if matches.is_present("break") {
+        for breakpoint in values_t_or_exit!(matches, "break", c8lib::Breakpoint) {
+            chip8.add_breakpoint(breakpoint);
+        }
+    }
+    if matches.is_present("watch") {
+        for watchpoint in values_t_or_exit!(matches, "watch", c8lib::Watchpoint) {
+            chip8.add_watchpoint(watchpoint);
+        }
     }
-    if matches.is_present("disassemble") {
-        chip8.disassemble(rom_bytes);
+    if let Some(path) = matches.value_of("trace") {
+        let format = value_t_or_exit!(matches, "trace_format", c8lib::TraceFormat);
+        let mut tracer = c8lib::Tracer::create(Path::new(path), format).unwrap_or_else(|e| {
+            println!("Error: {}", e);
+            std::process::exit(1);
+        });
+        if matches.is_present("trace_start") {
+            tracer.start_at(value_t_or_exit!(matches, "trace_start", c8lib::TracePoint));
+        }
+        if matches.is_present("trace_stop") {
+            tracer.stop_at(value_t_or_exit!(matches, "trace_stop", c8lib::TracePoint));
+        }
+        chip8.trace(tracer);
+    }
+    if matches.is_present("record_coverage") {
+        chip8.record_coverage();
+    }
+    let rom_bytes = if let Some(octo) = matches.subcommand_matches("octo") {
+        let source = Path::new(octo.value_of("source").unwrap()); //Required arg
+        let output = octo.value_of("output").map(Path::new);
+        let listing = octo.value_of("listing").map(Path::new);
+        if overwrites(source, output) || overwrites(source, listing) {
+            println!("Error: {} would be overwritten", source.display());
+            std::process::exit(1);
+        }
+        let loaded = assemble(source, c8lib::assemble_octo).and_then(|program| {
+            write_program(&program, output, listing)?;
+            chip8.load_bytes(program.bytes())
+        });
+        loaded.unwrap_or_else(|e| {
+            println!("Error: {}", e);
+            std::process::exit(1);
+        })
     } else {
-        chip8.run();
+        // Only optional for subcommands that don't run a ROM
+        let rom_path = matches.value_of("rom_path").unwrap_or_else(|| {
+            println!("Error: a ROM is needed, use -r FILE\n\n{}", matches.usage());
+            std::process::exit(1);
+        });
+        chip8.load_rom(PathBuf::from(rom_path)).unwrap()
+    };
+
+    if let Some(diff) = matches.subcommand_matches("trace-diff") {
+        let reference = Path::new(diff.value_of("reference").unwrap()); //Required arg
+        let result = c8lib::TraceReader::open(reference)
+            .and_then(|reference| c8lib::diff_trace(&mut chip8, reference));
+        match result {
+            Ok((matched, None)) => println!("Matched all {} instructions", matched),
+            Ok((matched, Some(divergence))) => {
+                println!("Matched {} instructions", matched);
+                print!("{}", divergence);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                println!("Error after {} instructions: {}", chip8.cycles(), e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(cfg) = matches.subcommand_matches("cfg") {
+        let format = value_t_or_exit!(cfg, "format", c8lib::GraphFormat);
+        let disassembly = c8lib::Disassembly::new(&loaded_rom(&chip8, rom_bytes));
+        let graph = c8lib::ControlFlow::new(&disassembly).write(format);
+        let written = match cfg.value_of("output") {
+            Some(output) => {
+                File::create(output).and_then(|mut file| file.write_all(graph.as_bytes()))
+            }
+            None => {
+                print!("{}", graph);
+                Ok(())
+            }
+        };
+        if let Err(e) = written {
+            println!("Error: {}", e);
+            std::process::exit(1);
+        }
+    } else if let Some(coverage) = matches.subcommand_matches("coverage") {
+        let mut merged = c8lib::Coverage::new(0);
+        for map in coverage.values_of("maps").unwrap() { //Required arg
+            let map = c8lib::Coverage::load(Path::new(map)).unwrap_or_else(|e| {
+                println!("Error: {}: {}", map, e);
+                std::process::exit(1);
+            });
+            merged.merge(&map);
+        }
+        let rom = loaded_rom(&chip8, rom_bytes);
+        let disassembly = c8lib::Disassembly::with_coverage(&rom, &merged);
+        let summary = disassembly.summary().unwrap(); //Made with a coverage map
+        let name = matches.value_of("rom_path").unwrap_or("ROM");
+        if coverage.is_present("summary") {
+            print!("{}: {}", name, summary);
+        } else {
+            print!("{}", disassembly.source());
+            for line in format!("{}: {}", name, summary).lines() {
+                println!("; {}", line);
+            }
+        }
+    } else if matches.is_present("disassemble") {
+        print!("{}", c8lib::Disassembly::new(&loaded_rom(&chip8, rom_bytes)).source());
+    } else {
+        let mut window = c8lib::WindowFrontend::new().unwrap();
+        let halt = if matches.is_present("dap") {
+            let mut adapter = c8lib::DebugAdapter::new();
+            let served = match matches.value_of("listing") {
+                Some(listing) => adapter.load_listing(Path::new(listing)),
+                None => Ok(()),
+            };
+            let served = served.and_then(|_| match matches.value_of("dap") {
+                Some("stdio") => adapter.serve_stdio(&mut chip8, &mut window),
+                _ => {
+                    let port = value_t_or_exit!(matches, "dap", u16);
+                    adapter.serve_tcp(("127.0.0.1", port), &mut chip8, &mut window)
+                }
+            });
+            served.map(|_| c8lib::Halt::Closed)
+        } else if matches.is_present("gdb") {
+            let port = value_t_or_exit!(matches, "gdb", u16);
+            let served = c8lib::GdbServer::bind(("127.0.0.1", port)).and_then(|server| {
+                println!("Waiting for gdb on {}", server.local_addr()?);
+                server.serve(&mut chip8, &mut window)
+            });
+            served.map(|_| c8lib::Halt::Closed)
+        } else if matches.is_present("debugger") {
+            Ok(c8lib::Halt::Interrupted)
+        } else {
+            chip8.run(&mut window)
+        };
+        let result = match halt {
+            Ok(c8lib::Halt::Breakpoint(_)) |
+            Ok(c8lib::Halt::Watchpoint(_)) |
+            Ok(c8lib::Halt::Interrupted) => c8lib::Debugger::new().run(&mut chip8, &mut window),
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        };
+        let result = result.and_then(|_| match chip8.stop_trace() {
+            Some(tracer) => tracer.finish(),
+            None => Ok(()),
+        });
+        let result = result.and_then(|_| match (matches.value_of("record_coverage"),
+                                                chip8.stop_coverage()) {
+            (Some(path), Some(coverage)) => coverage.save(Path::new(path)),
+            _ => Ok(()),
+        });
+        if let Err(e) = result {
+            println!("Error: {}", e);
+            std::process::exit(1);
+        }
     }
     //println!("{:?}", chip8);
     //mem_dump(&chip8.rom[..], 0);
 }
+
+fn assemble<F>(source: &Path, assembler: F) -> c8lib::Result<c8lib::Program>
+    where F: Fn(&str, &str) -> c8lib::Result<c8lib::Program>
+{
+    let mut text = String::new();
+    File::open(source)?.read_to_string(&mut text)?;
+    let name = source.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+    assembler(&text, &name)
+}
+
+fn write_program(program: &c8lib::Program,
+                 output: Option<&Path>,
+                 listing: Option<&Path>)
+                 -> c8lib::Result<()> {
+    if let Some(output) = output {
+        File::create(output)?.write_all(program.bytes())?;
+    }
+    if let Some(listing) = listing {
+        File::create(listing)?.write_all(program.listing().as_bytes())?;
+    }
+    Ok(())
+}
+
+// The ROM as loaded into memory, for the disassembler
+fn loaded_rom(chip8: &c8lib::Chip8, rom_bytes: usize) -> Vec<u8> {
+    (0..rom_bytes)
+        .map(|i| chip8.read_memory((0x200 + i) as c8lib::MemAddr).unwrap())
+        .collect()
+}
+
+// Would writing to `path` replace `source`?
+fn overwrites(source: &Path, path: Option<&Path>) -> bool {
+    match path {
+        Some(path) if path == source => true,
+        Some(path) => {
+            let path = std::fs::canonicalize(path).ok();
+            path.is_some() && path == std::fs::canonicalize(source).ok()
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,115 @@
+//
+// This Crate Imports
+//
+use cpu::{Opcode, Reg};
+use emulator::MemAddr;
+use super::Parsed;
+
+/// An operand with its value worked out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arg {
+    Reg(Reg),
+    Index,
+    V0Plus(i64),
+    Value(i64),
+}
+
+/// Every mnemonic `Opcode` is displayed with
+const MNEMONICS: [&'static str; 44] =
+    ["CLS", "RETURN", "SCROLL.DOWN", "SCROLL.UP", "SCROLL.RIGHT", "SCROLL.LEFT", "EXIT", "LORES",
+     "HIRES", "SYSADDR", "JUMP", "CALL", "SKIP.EQ", "SKIP.NEQ", "REG.SAVE", "REG.RESTORE", "LOAD",
+     "MATH.ADD", "BIT.OR", "BIT.AND", "BIT.XOR", "MATH.SUB", "BIT.SHR", "MATH.SUBN", "BIT.SHL",
+     "RAND", "DRAW", "SKIP.KEY", "SKIP.NKEY", "LOAD.LONG", "PLANE", "AUDIO", "DELAY.GET",
+     "KEY.GET", "DELAY.SET", "SOUND.SET", "SPRITE", "SPRITE.BIG", "BCD", "PITCH", "REG.DUMP",
+     "REG.LOAD", "FLAGS.SAVE", "FLAGS.LOAD"];
+
+/// How many bytes an instruction takes, before its operands are known
+pub fn size(mnemonic: &str) -> u16 {
+    if mnemonic == "LOAD.LONG" { 4 } else { 2 }
+}
+
+fn ranged(value: i64, min: i64, max: i64) -> Parsed<i64> {
+    if value < min || value > max {
+        return Err(format!("{} is out of range, it must fit in 0x{:x}", value, max));
+    }
+    Ok(value)
+}
+
+pub fn nibble(value: i64) -> Parsed<u8> {
+    ranged(value, 0, 0xF).map(|n| n as u8)
+}
+
+/// Negative bytes are allowed, e.g. `MATH.ADD V0 -1`
+pub fn byte(value: i64) -> Parsed<u8> {
+    ranged(value, -0x80, 0xFF).map(|n| n as u8)
+}
+
+pub fn addr(value: i64) -> Parsed<MemAddr> {
+    ranged(value, 0, 0xFFF).map(|n| n as MemAddr)
+}
+
+pub fn word(value: i64) -> Parsed<u16> {
+    ranged(value, -0x8000, 0xFFFF).map(|n| n as u16)
+}
+
+/// Find the instruction that is displayed as `mnemonic` with `args`
+pub fn opcode(mnemonic: &str, args: &[Arg]) -> Parsed<Opcode> {
+    use self::Arg::*;
+    Ok(match (mnemonic, args) {
+        ("CLS", &[]) => Opcode::ClearScreen,
+        ("RETURN", &[]) => Opcode::Return,
+        ("SCROLL.DOWN", &[Value(n)]) => Opcode::ScrollDown(nibble(n)?),
+        ("SCROLL.UP", &[Value(n)]) => Opcode::ScrollUp(nibble(n)?),
+        ("SCROLL.RIGHT", &[]) => Opcode::ScrollRight,
+        ("SCROLL.LEFT", &[]) => Opcode::ScrollLeft,
+        ("EXIT", &[]) => Opcode::Exit,
+        ("LORES", &[]) => Opcode::LowRes,
+        ("HIRES", &[]) => Opcode::HighRes,
+        ("SYSADDR", &[Value(a)]) => Opcode::SysAddr(addr(a)?),
+        ("JUMP", &[Value(a)]) => Opcode::JumpAddr(addr(a)?),
+        ("CALL", &[Value(a)]) => Opcode::CallAddr(addr(a)?),
+        ("SKIP.EQ", &[Reg(x), Value(n)]) => Opcode::SkipEqByte(x, byte(n)?),
+        ("SKIP.NEQ", &[Reg(x), Value(n)]) => Opcode::SkipNEqByte(x, byte(n)?),
+        ("SKIP.EQ", &[Reg(x), Reg(y)]) => Opcode::SkipEqReg(x, y),
+        ("REG.SAVE", &[Reg(x), Reg(y)]) => Opcode::RegRangeSave(x, y),
+        ("REG.RESTORE", &[Reg(x), Reg(y)]) => Opcode::RegRangeLoad(x, y),
+        ("LOAD", &[Reg(x), Value(n)]) => Opcode::LoadByte(x, byte(n)?),
+        ("MATH.ADD", &[Reg(x), Value(n)]) => Opcode::AddByte(x, byte(n)?),
+        ("LOAD", &[Reg(x), Reg(y)]) => Opcode::LoadReg(x, y),
+        ("BIT.OR", &[Reg(x), Reg(y)]) => Opcode::BitOr(x, y),
+        ("BIT.AND", &[Reg(x), Reg(y)]) => Opcode::BitAnd(x, y),
+        ("BIT.XOR", &[Reg(x), Reg(y)]) => Opcode::BitXor(x, y),
+        ("MATH.ADD", &[Reg(x), Reg(y)]) => Opcode::MathAdd(x, y),
+        ("MATH.SUB", &[Reg(x), Reg(y)]) => Opcode::MathSub(x, y),
+        ("BIT.SHR", &[Reg(x), Reg(y)]) => Opcode::ShiftRight(x, y),
+        ("MATH.SUBN", &[Reg(x), Reg(y)]) => Opcode::MathSubN(x, y),
+        ("BIT.SHL", &[Reg(x), Reg(y)]) => Opcode::ShiftLeft(x, y),
+        ("SKIP.NEQ", &[Reg(x), Reg(y)]) => Opcode::SkipNEqReg(x, y),
+        ("LOAD", &[Index, Value(a)]) => Opcode::MemLoad(addr(a)?),
+        ("LOAD", &[Index, V0Plus(a)]) => Opcode::JumpAddV0(addr(a)?),
+        ("RAND", &[Reg(x), Value(n)]) => Opcode::Rand(x, byte(n)?),
+        ("DRAW", &[Reg(x), Reg(y), Value(n)]) => Opcode::Draw(x, y, nibble(n)?),
+        ("SKIP.KEY", &[Reg(x)]) => Opcode::KeyEqSkip(x),
+        ("SKIP.NKEY", &[Reg(x)]) => Opcode::KeyNEqSkip(x),
+        ("LOAD.LONG", &[Index, Value(a)]) => Opcode::LongMemLoad(word(a)?),
+        ("PLANE", &[Value(n)]) => Opcode::PlaneSelect(nibble(n)?),
+        ("AUDIO", &[]) => Opcode::AudioLoad,
+        ("DELAY.GET", &[Reg(x)]) => Opcode::DelayGet(x),
+        ("KEY.GET", &[Reg(x)]) => Opcode::KeyGet(x),
+        ("DELAY.SET", &[Reg(x)]) => Opcode::DelaySet(x),
+        ("SOUND.SET", &[Reg(x)]) => Opcode::SoundSet(x),
+        ("MATH.ADD", &[Index, Reg(x)]) => Opcode::MemAdd(x),
+        ("SPRITE", &[Reg(x)]) => Opcode::MemSprite(x),
+        ("SPRITE.BIG", &[Reg(x)]) => Opcode::BigSprite(x),
+        ("BCD", &[Reg(x)]) => Opcode::BCD(x),
+        ("PITCH", &[Reg(x)]) => Opcode::Pitch(x),
+        ("REG.DUMP", &[Reg(x)]) => Opcode::RegDump(x),
+        ("REG.LOAD", &[Reg(x)]) => Opcode::RegLoad(x),
+        ("FLAGS.SAVE", &[Reg(x)]) => Opcode::FlagSave(x),
+        ("FLAGS.LOAD", &[Reg(x)]) => Opcode::FlagLoad(x),
+        _ if MNEMONICS.contains(&mnemonic) => {
+            return Err(format!("Wrong operands for {}", mnemonic))
+        }
+        _ => return Err(format!("Unknown instruction '{}'", mnemonic)),
+    })
+}
@@ -0,0 +1,280 @@
+//
+// Rust Core Imports
+//
+use std::collections::HashMap;
+use std::fmt::Write;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use cpu::{decode, LONG_MEM_LOAD};
+use emulator::{MemAddr, PROGRAM_START};
+
+//
+// Declare sub modules
+//
+mod instruction;
+mod octo;
+mod parse;
+
+use self::instruction::{byte, word};
+use self::parse::{Line, Statement, parse_line};
+
+//
+// Public Exports
+//
+pub use self::octo::assemble_octo;
+
+// The end of XO-CHIP memory
+const MEM_END: i64 = 0x10000;
+
+type Parsed<T> = ::std::result::Result<T, String>;
+
+// A line of source, and what it assembled to
+#[derive(Debug, Clone)]
+struct Listed {
+    line: usize,
+    text: String,
+    addr: Option<MemAddr>,
+    bytes: Vec<u8>,
+}
+
+/// A ROM built by `assemble`, and where each byte of it came from
+#[derive(Debug, Clone)]
+pub struct Program {
+    name: String,
+    bytes: Vec<u8>,
+    lines: Vec<Listed>,
+}
+
+impl Program {
+    /// The ROM, to be loaded at 0x200
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// A listing the debug adapter can read, e.g.
+    /// `0x0200 game.asm:12  60 07  LOAD V0 0x07`
+    ///
+    /// Lines that don't produce any bytes are copied as they are.
+    pub fn listing(&self) -> String {
+        let mut listing = String::new();
+        for listed in &self.lines {
+            match listed.addr {
+                Some(addr) if !listed.bytes.is_empty() => {
+                    let bytes: Vec<String> = listed.bytes
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect();
+                    let row = format!("0x{:04x} {}:{}  {:11}  {}",
+                                      addr,
+                                      self.name,
+                                      listed.line,
+                                      bytes.join(" "),
+                                      listed.text.trim());
+                    let _ = writeln!(listing, "{}", row.trim_end());
+                }
+                _ => {
+                    let _ = writeln!(listing, "{}", listed.text);
+                }
+            }
+        }
+        listing
+    }
+}
+
+fn located(name: &str, line: usize, reason: String) -> Error {
+    ErrorKind::BadAssembly(name.to_string(), line, reason).into()
+}
+
+fn define(symbols: &mut HashMap<String, i64>, name: &str, value: i64) -> Parsed<()> {
+    if symbols.insert(name.to_string(), value).is_some() {
+        return Err(format!("'{}' is already defined", name));
+    }
+    Ok(())
+}
+
+fn origin(to: i64, addr: i64) -> Parsed<i64> {
+    if to < addr {
+        return Err(format!("Can't go back to 0x{:04x} from 0x{:04x}", to, addr));
+    }
+    Ok(to)
+}
+
+/// Assemble source in the syntax `Opcode` is displayed in
+///
+/// As well as instructions there are `label:` definitions, `NAME = VALUE`
+/// constants, `db` and `dw` data, `org ADDR` to skip ahead, and `;` comments.
+/// Values can add and subtract numbers, labels and constants, e.g.
+/// `LOAD VI sprites+5`. `name` is the file name used in errors and the listing.
+pub fn assemble(source: &str, name: &str) -> Result<Program> {
+    let lines = source.lines()
+        .enumerate()
+        .map(|(n, text)| parse_line(text).map_err(|e| located(name, n + 1, e)))
+        .collect::<Result<Vec<Line>>>()?;
+
+    // Find where every label is
+    let mut symbols = HashMap::new();
+    let mut addr = PROGRAM_START as i64;
+    for (n, line) in lines.iter().enumerate() {
+        let at = |e| located(name, n + 1, e);
+        if let Some(ref label) = line.label {
+            define(&mut symbols, label, addr).map_err(&at)?;
+        }
+        match line.statement {
+            Some(Statement::Instruction(ref mnemonic, _)) => {
+                addr += instruction::size(mnemonic) as i64
+            }
+            Some(Statement::Bytes(ref values)) => addr += values.len() as i64,
+            Some(Statement::Words(ref values)) => addr += 2 * values.len() as i64,
+            Some(Statement::Org(ref to)) => {
+                addr = to.eval(&symbols).and_then(|to| origin(to, addr)).map_err(&at)?
+            }
+            Some(Statement::Const(ref constant, ref value)) => {
+                let value = value.eval(&symbols).map_err(&at)?;
+                define(&mut symbols, constant, value).map_err(&at)?;
+            }
+            None => {}
+        }
+        if addr > MEM_END {
+            return Err(at("The program doesn't fit in memory".to_string()));
+        }
+    }
+
+    // And then fill them in
+    let mut bytes = Vec::new();
+    let mut listed = Vec::new();
+    for (n, (line, text)) in lines.into_iter().zip(source.lines()).enumerate() {
+        let at = |e| located(name, n + 1, e);
+        let addr = (PROGRAM_START + bytes.len()) as MemAddr;
+        let emitted = match line.statement {
+            Some(Statement::Instruction(mnemonic, operands)) => {
+                operands.iter()
+                    .map(|operand| operand.eval(&symbols))
+                    .collect::<Parsed<Vec<_>>>()
+                    .and_then(|args| instruction::opcode(&mnemonic, &args))
+                    .map(|opcode| opcode.encode())
+                    .map_err(&at)?
+            }
+            Some(Statement::Bytes(values)) => {
+                values.iter()
+                    .map(|value| value.eval(&symbols).and_then(byte))
+                    .collect::<Parsed<Vec<u8>>>()
+                    .map_err(&at)?
+            }
+            Some(Statement::Words(values)) => {
+                let words = values.iter()
+                    .map(|value| value.eval(&symbols).and_then(word))
+                    .collect::<Parsed<Vec<u16>>>()
+                    .map_err(&at)?;
+                words.iter().flat_map(|w| vec![(w >> 8) as u8, *w as u8]).collect()
+            }
+            Some(Statement::Org(to)) => {
+                let to = to.eval(&symbols).map_err(&at)? as usize;
+                bytes.resize(to - PROGRAM_START, 0);
+                Vec::new()
+            }
+            Some(Statement::Const(..)) | None => Vec::new(),
+        };
+        bytes.extend_from_slice(&emitted);
+        listed.push(Listed {
+            line: n + 1,
+            text: text.to_string(),
+            addr: Some(addr),
+            bytes: emitted,
+        });
+    }
+    Ok(Program {
+        name: name.to_string(),
+        bytes: bytes,
+        lines: listed,
+    })
+}
+
+/// Source for `rom` that `assemble` turns back into exactly the same bytes
+///
+/// Each halfword that decodes is shown as an instruction, anything else
+/// as `dw`, or `db` for an odd byte at the end.
+pub fn disassemble(rom: &[u8]) -> String {
+    let mut source = String::new();
+    let mut at = 0;
+    while at < rom.len() {
+        if at + 1 == rom.len() {
+            let _ = writeln!(source, "    db 0x{:02x}", rom[at]);
+            break;
+        }
+        let halfword = |at: usize| (rom[at] as u16) << 8 | rom[at + 1] as u16;
+        let instr = halfword(at);
+        let opcode = if at + 3 < rom.len() {
+            decode(instr, halfword(at + 2)).ok()
+        } else if instr != LONG_MEM_LOAD {
+            decode(instr, 0).ok()
+        } else {
+            None
+        };
+        match opcode {
+            Some(opcode) => {
+                let _ = writeln!(source, "    {}", opcode);
+                at += opcode.len() as usize;
+            }
+            None => {
+                let _ = writeln!(source, "    dw 0x{:04x}", instr);
+                at += 2;
+            }
+        }
+    }
+    source
+}
+
+#[test]
+fn assemble_program() {
+    let source = "; Draw a digit\n\
+                  DIGIT = 7\n\
+                  start:\n    \
+                      LOAD V0 DIGIT\n    \
+                      SPRITE V0\n    \
+                      LOAD VI sprite\n\
+                  loop: JUMP loop\n\
+                  org 0x210\n\
+                  sprite: db 0b11110000, 0x90, -1\n    \
+                      dw start, sprite+1\n";
+    let program = assemble(source, "digit.asm").unwrap();
+    assert_eq!(program.bytes(),
+               &[0x60, 0x07, 0xf0, 0x29, 0xa2, 0x10, 0x12, 0x06, 0, 0, 0, 0, 0, 0, 0, 0, 0xf0,
+                 0x90, 0xff, 0x02, 0x00, 0x02, 0x11][..]);
+    let listing = program.listing();
+    assert!(listing.contains("0x0206 digit.asm:7  12 06        loop: JUMP loop\n"));
+    assert!(listing.contains("0x0210 digit.asm:9  f0 90 ff     sprite: db"));
+    assert!(listing.contains("\nstart:\n"));
+
+    let error = |source| assemble(source, "bad.asm").unwrap_err().to_string();
+    assert_eq!(error("CLS\nJUMP nowhere"), "bad.asm:2: Unknown label 'nowhere'");
+    assert_eq!(error("LOAD V0 0x100"),
+               "bad.asm:1: 256 is out of range, it must fit in 0xff");
+    assert_eq!(error("DRAW V0 0x05"), "bad.asm:1: Wrong operands for DRAW");
+    assert_eq!(error("MOV V0 V1"), "bad.asm:1: Unknown instruction 'MOV'");
+    assert_eq!(error("a: CLS\na: CLS"), "bad.asm:2: 'a' is already defined");
+    assert_eq!(error("org 0x100"),
+               "bad.asm:1: Can't go back to 0x0100 from 0x0200");
+}
+
+#[test]
+fn disassembly_round_trip() {
+    // Every halfword, a memory's worth at a time
+    for chunk in 0..16u32 {
+        let mut rom = Vec::new();
+        for instr in chunk << 12..(chunk + 1) << 12 {
+            rom.push((instr >> 8) as u8);
+            rom.push(instr as u8);
+        }
+        let program = assemble(&disassemble(&rom), "all.asm").unwrap();
+        assert!(program.bytes() == &rom[..]);
+    }
+    // Ends with the first half of a LOAD.LONG, then an odd byte
+    let rom = [0xb1, 0x23, 0xf0, 0x00, 0x12, 0x34, 0xf0, 0x00, 0xab];
+    let source = disassemble(&rom);
+    assert_eq!(source,
+               "    LOAD VI V0+0x0123\n    LOAD.LONG VI 0x1234\n    dw 0xf000\n    db 0xab\n");
+    assert_eq!(assemble(&source, "end.asm").unwrap().bytes(), &rom[..]);
+}
@@ -0,0 +1,153 @@
+//
+// Rust Core Imports
+//
+use std::f64::consts;
+
+//
+// This Crate Imports
+//
+use asm::Parsed;
+
+const UNARY: [&'static str; 13] = ["-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs",
+                                   "sqrt", "sign", "ceil", "floor"];
+
+/// Parse a number the way Octo does: decimal, `0x` hex or `0b` binary, with
+/// an optional `-`, or a decimal fraction
+pub fn number(token: &str) -> Option<f64> {
+    let (negated, digits) = match token.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, token),
+    };
+    let value = if digits.starts_with("0x") || digits.starts_with("0X") {
+        i64::from_str_radix(&digits[2..], 16).ok().map(|n| n as f64)
+    } else if digits.starts_with("0b") || digits.starts_with("0B") {
+        i64::from_str_radix(&digits[2..], 2).ok().map(|n| n as f64)
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    };
+    value.map(|n| if negated { -n } else { n })
+}
+
+// Octo's expressions have no precedence, each operator applies to everything
+// to the right of it
+struct Calc<'a> {
+    tokens: &'a [String],
+    pos: usize,
+    value: &'a dyn Fn(&str) -> Option<f64>,
+}
+
+impl<'a> Calc<'a> {
+    fn next(&mut self) -> Parsed<&'a str> {
+        let token = self.tokens.get(self.pos).ok_or_else(|| "Incomplete expression".to_string())?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expr(&mut self) -> Parsed<f64> {
+        let token = self.next()?;
+        if UNARY.contains(&token) {
+            let x = self.expr()?;
+            return Ok(match token {
+                "-" => -x,
+                "~" => !(x as i64) as f64,
+                "!" => if x == 0.0 { 1.0 } else { 0.0 },
+                "sin" => x.sin(),
+                "cos" => x.cos(),
+                "tan" => x.tan(),
+                "exp" => x.exp(),
+                "log" => x.ln(),
+                "abs" => x.abs(),
+                "sqrt" => x.sqrt(),
+                "sign" => if x == 0.0 { 0.0 } else { x.signum() },
+                "ceil" => x.ceil(),
+                _ => x.floor(),
+            });
+        }
+        let x = self.terminal(token)?;
+        let op = match self.tokens.get(self.pos) {
+            None => return Ok(x),
+            Some(op) if op == ")" => return Ok(x),
+            Some(op) => op.as_str(),
+        };
+        self.pos += 1;
+        let y = self.expr()?;
+        let (a, b) = (x as i64, y as i64);
+        let truth = |t: bool| if t { 1.0 } else { 0.0 };
+        Ok(match op {
+            "+" => x + y,
+            "-" => x - y,
+            "*" => x * y,
+            "/" => x / y,
+            "%" => if b == 0 { 0.0 } else { (a % b) as f64 },
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.wrapping_shl(b as u32) as f64,
+            ">>" => a.wrapping_shr(b as u32) as f64,
+            "pow" => x.powf(y),
+            "min" => x.min(y),
+            "max" => x.max(y),
+            "<" => truth(x < y),
+            ">" => truth(x > y),
+            "<=" => truth(x <= y),
+            ">=" => truth(x >= y),
+            "==" => truth(x == y),
+            "!=" => truth(x != y),
+            _ => return Err(format!("Unknown operator '{}'", op)),
+        })
+    }
+
+    fn terminal(&mut self, token: &str) -> Parsed<f64> {
+        if token == "(" {
+            let x = self.expr()?;
+            if self.next()? != ")" {
+                return Err("Expected ')'".to_string());
+            }
+            return Ok(x);
+        }
+        match token {
+            "PI" => Ok(consts::PI),
+            "E" => Ok(consts::E),
+            _ => {
+                number(token)
+                    .or_else(|| (self.value)(token))
+                    .ok_or_else(|| format!("Unknown name '{}'", token))
+            }
+        }
+    }
+}
+
+/// Work out a `:calc` expression, `value` looks up constants and labels
+pub fn eval(tokens: &[String], value: &dyn Fn(&str) -> Option<f64>) -> Parsed<f64> {
+    let mut calc = Calc {
+        tokens: tokens,
+        pos: 0,
+        value: value,
+    };
+    let x = calc.expr()?;
+    if calc.pos != tokens.len() {
+        return Err(format!("Unexpected '{}'", tokens[calc.pos]));
+    }
+    Ok(x)
+}
+
+#[test]
+fn calc_expressions() {
+    let calc = |text: &str| {
+        let tokens: Vec<String> = text.split_whitespace().map(String::from).collect();
+        eval(&tokens, &|name| if name == "HERE" { Some(512.0) } else { None })
+    };
+    // Right to left, so this is 10 - 1
+    assert_eq!(calc("10 - 3 - 2"), Ok(9.0));
+    assert_eq!(calc("( 10 - 3 ) - 2"), Ok(5.0));
+    assert_eq!(calc("HERE >> 8 & 0xF"), Ok(2.0));
+    assert_eq!(calc("- 0b10 * 3"), Ok(-6.0));
+    assert_eq!(calc("floor 7 / 2"), Ok(3.0));
+    assert_eq!(calc("2 pow 8"), Ok(256.0));
+    assert!(calc("1 +").is_err());
+    assert!(calc("nope").is_err());
+    assert_eq!(number("-0x10"), Some(-16.0));
+    assert_eq!(number("v0"), None);
+}
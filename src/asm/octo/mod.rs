@@ -0,0 +1,845 @@
+//
+// Rust Core Imports
+//
+use std::collections::{HashMap, VecDeque};
+
+//
+// This Crate Imports
+//
+use errors::*;
+use cpu::{Reg, reg};
+use emulator::PROGRAM_START;
+use super::{Listed, Parsed, Program, located, MEM_END};
+use super::instruction::{nibble, byte, addr, word};
+
+//
+// Declare sub modules
+//
+mod calc;
+
+// Words that can't be used as names
+const KEYWORDS: [&'static str; 52] =
+    [":", ":next", ":const", ":alias", ":unpack", ":org", ":byte", ":calc", ":macro", ":call",
+     ":breakpoint", ":monitor", ";", "return", "clear", "bcd", "save", "load", "sprite", "jump",
+     "jump0", "native", "delay", "buzzer", "pitch", "i", "if", "then", "begin", "else", "end",
+     "loop", "while", "again", "scroll-down", "scroll-up", "scroll-right", "scroll-left", "exit",
+     "lores", "hires", "saveflags", "loadflags", "plane", "audio", "key", "-key", "hex",
+     "bighex", "random", "long", ":="];
+
+// How a reference to a label that isn't defined yet is filled in later
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Fixup {
+    // The low 12 bits of the instruction
+    Addr,
+    // The halfword after `i := long`
+    Long,
+    // The pair of `vX := NN` from `:unpack`
+    Unpack,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<String>,
+    calls: usize,
+}
+
+// A comparison in an `if` or a `while`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Compare {
+    Eq,
+    Neq,
+    Key,
+    NotKey,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl Compare {
+    fn negate(self) -> Compare {
+        match self {
+            Compare::Eq => Compare::Neq,
+            Compare::Neq => Compare::Eq,
+            Compare::Key => Compare::NotKey,
+            Compare::NotKey => Compare::Key,
+            Compare::Lt => Compare::Ge,
+            Compare::Ge => Compare::Lt,
+            Compare::Gt => Compare::Le,
+            Compare::Le => Compare::Gt,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Reg(Reg),
+    Byte(u8),
+}
+
+// Something emitted, for the listing
+struct Emitted {
+    addr: usize,
+    len: usize,
+    line: usize,
+    code: bool,
+}
+
+struct Octo {
+    tokens: VecDeque<(String, usize)>,
+    line: usize,
+    rom: Vec<u8>,
+    here: usize,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, Reg>,
+    macros: HashMap<String, Macro>,
+    // (label, address, fixup, line)
+    fixups: Vec<(String, usize, Fixup, usize)>,
+    // (address, line) of each open `loop`
+    loops: Vec<(usize, usize)>,
+    // The jumps out of each `while`, with `None` marking the start of a loop
+    whiles: Vec<Option<usize>>,
+    // (jump address, line, after an `else`) of each open `begin`
+    branches: Vec<(usize, usize, bool)>,
+    has_main: bool,
+    emitted: Vec<Emitted>,
+}
+
+impl Octo {
+    fn new(source: &str) -> Self {
+        let mut tokens = VecDeque::new();
+        for (n, line) in source.lines().enumerate() {
+            for token in line.split_whitespace().take_while(|t| !t.starts_with('#')) {
+                tokens.push_back((token.to_string(), n + 1));
+            }
+        }
+        let mut aliases = HashMap::new();
+        aliases.insert("unpack-hi".to_string(), Reg::V0);
+        aliases.insert("unpack-lo".to_string(), Reg::V1);
+        aliases.insert("compare-temp".to_string(), Reg::VF);
+        Octo {
+            tokens: tokens,
+            line: 0,
+            // The first two bytes are a jump to main, unless main comes first
+            rom: vec![0, 0],
+            here: PROGRAM_START + 2,
+            constants: HashMap::new(),
+            aliases: aliases,
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            loops: Vec::new(),
+            whiles: Vec::new(),
+            branches: Vec::new(),
+            has_main: true,
+            emitted: Vec::new(),
+        }
+    }
+
+    fn next(&mut self) -> Parsed<String> {
+        match self.tokens.pop_front() {
+            Some((token, line)) => {
+                self.line = line;
+                Ok(token)
+            }
+            None => Err("Unexpected end of file".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|(token, _)| token.as_str())
+    }
+
+    fn expect(&mut self, wanted: &str) -> Parsed<()> {
+        let token = self.next()?;
+        if token != wanted {
+            return Err(format!("Expected '{}', found '{}'", wanted, token));
+        }
+        Ok(())
+    }
+
+    fn register_named(&self, token: &str) -> Option<Reg> {
+        if let Some(&x) = self.aliases.get(token) {
+            return Some(x);
+        }
+        if token.len() == 2 && (token.starts_with('v') || token.starts_with('V')) {
+            u8::from_str_radix(&token[1..], 16).ok().and_then(|x| reg(x).ok())
+        } else {
+            None
+        }
+    }
+
+    fn is_register(&self) -> bool {
+        self.peek().and_then(|token| self.register_named(token)).is_some()
+    }
+
+    fn register(&mut self) -> Parsed<Reg> {
+        let token = self.next()?;
+        self.register_named(&token).ok_or_else(|| format!("Expected a register, found '{}'", token))
+    }
+
+    fn name(&mut self) -> Parsed<String> {
+        let name = self.next()?;
+        if calc::number(&name).is_some() || self.register_named(&name).is_some() ||
+           KEYWORDS.contains(&name.as_str()) {
+            return Err(format!("'{}' can't be used as a name", name));
+        }
+        Ok(name)
+    }
+
+    fn lookup(&self, token: &str) -> Option<f64> {
+        calc::number(token).or_else(|| self.constants.get(token).cloned())
+    }
+
+    // A number or a name that is already defined
+    fn value(&mut self) -> Parsed<i64> {
+        let token = self.next()?;
+        self.lookup(&token)
+            .map(|value| value as i64)
+            .ok_or_else(|| format!("Unknown name '{}'", token))
+    }
+
+    // An address, which can be a label that is defined later
+    fn address(&mut self, token: String, fixup: Fixup, at: usize) -> Parsed<u16> {
+        match self.lookup(&token) {
+            Some(value) if fixup == Fixup::Long => word(value as i64),
+            Some(value) => addr(value as i64),
+            None => {
+                if self.register_named(&token).is_some() || KEYWORDS.contains(&token.as_str()) {
+                    return Err(format!("Expected an address, found '{}'", token));
+                }
+                self.fixups.push((token, at, fixup, self.line));
+                Ok(0)
+            }
+        }
+    }
+
+    // The tokens between a pair of braces
+    fn braced(&mut self) -> Parsed<Vec<String>> {
+        self.expect("{")?;
+        let mut tokens = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                depth += 1;
+            } else if token == "}" {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(tokens);
+                }
+            }
+            tokens.push(token);
+        }
+    }
+
+    fn calc(&mut self) -> Parsed<f64> {
+        let tokens = self.braced()?;
+        let here = self.here as f64;
+        let constants = &self.constants;
+        calc::eval(&tokens,
+                   &|name| if name == "HERE" { Some(here) } else { constants.get(name).cloned() })
+    }
+
+    fn define(&mut self, name: String, value: f64) -> Parsed<()> {
+        if self.constants.contains_key(&name) {
+            return Err(format!("'{}' is already defined", name));
+        }
+        self.constants.insert(name.clone(), value);
+        let (resolved, pending) = self.fixups.drain(..).partition(|fixup| fixup.0 == name);
+        self.fixups = pending;
+        for (_, at, fixup, _) in resolved {
+            self.patch(at, fixup, value as i64)?;
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, bytes: &[u8], code: bool) -> Parsed<()> {
+        if self.here + bytes.len() > MEM_END as usize {
+            return Err("The program doesn't fit in memory".to_string());
+        }
+        let start = self.here - PROGRAM_START;
+        if self.rom.len() < start + bytes.len() {
+            self.rom.resize(start + bytes.len(), 0);
+        }
+        self.rom[start..start + bytes.len()].copy_from_slice(bytes);
+        self.emitted.push(Emitted {
+            addr: self.here,
+            len: bytes.len(),
+            line: self.line,
+            code: code,
+        });
+        self.here += bytes.len();
+        Ok(())
+    }
+
+    fn inst(&mut self, instr: u16) -> Parsed<()> {
+        self.emit(&[(instr >> 8) as u8, instr as u8], true)
+    }
+
+    fn patch(&mut self, at: usize, fixup: Fixup, value: i64) -> Parsed<()> {
+        let i = at - PROGRAM_START;
+        match fixup {
+            Fixup::Addr => {
+                let value = addr(value)?;
+                self.rom[i] = self.rom[i] & 0xF0 | (value >> 8) as u8;
+                self.rom[i + 1] = value as u8;
+            }
+            Fixup::Long => {
+                let value = word(value)?;
+                self.rom[i + 2] = (value >> 8) as u8;
+                self.rom[i + 3] = value as u8;
+            }
+            Fixup::Unpack => {
+                let value = addr(value)?;
+                self.rom[i + 1] |= (value >> 8) as u8;
+                self.rom[i + 3] = value as u8;
+            }
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Parsed<(Reg, Compare, Option<Operand>)> {
+        let x = self.register()?;
+        let op = self.next()?;
+        let compare = match op.as_str() {
+            "key" => return Ok((x, Compare::Key, None)),
+            "-key" => return Ok((x, Compare::NotKey, None)),
+            "==" => Compare::Eq,
+            "!=" => Compare::Neq,
+            "<" => Compare::Lt,
+            ">" => Compare::Gt,
+            "<=" => Compare::Le,
+            ">=" => Compare::Ge,
+            _ => return Err(format!("Unknown comparison '{}'", op)),
+        };
+        let operand = if self.is_register() {
+            Operand::Reg(self.register()?)
+        } else {
+            Operand::Byte(self.value().and_then(byte)?)
+        };
+        Ok((x, compare, Some(operand)))
+    }
+
+    // Skip the next instruction unless the comparison is true, the ordered
+    // comparisons subtract in the `compare-temp` register and test VF
+    fn skip_unless(&mut self, x: Reg, compare: Compare, operand: Option<Operand>) -> Parsed<()> {
+        let x = (x as u16) << 8;
+        match (compare, operand) {
+            (Compare::Key, _) => self.inst(0xE0A1 | x),
+            (Compare::NotKey, _) => self.inst(0xE09E | x),
+            (Compare::Eq, Some(Operand::Reg(y))) => self.inst(0x9000 | x | (y as u16) << 4),
+            (Compare::Eq, Some(Operand::Byte(n))) => self.inst(0x4000 | x | n as u16),
+            (Compare::Neq, Some(Operand::Reg(y))) => self.inst(0x5000 | x | (y as u16) << 4),
+            (Compare::Neq, Some(Operand::Byte(n))) => self.inst(0x3000 | x | n as u16),
+            (_, Some(operand)) => {
+                let (sub, skip) = match compare {
+                    Compare::Gt => (0x5, 0x4F00),
+                    Compare::Lt => (0x7, 0x4F00),
+                    Compare::Ge => (0x7, 0x3F00),
+                    _ => (0x5, 0x3F00),
+                };
+                let temp = (self.aliases["compare-temp"] as u16) << 8;
+                match operand {
+                    Operand::Reg(y) => self.inst(0x8000 | temp | (y as u16) << 4)?,
+                    Operand::Byte(n) => self.inst(0x6000 | temp | n as u16)?,
+                }
+                self.inst(0x8000 | temp | x >> 4 | sub)?;
+                self.inst(skip)
+            }
+            (_, None) => unreachable!(),
+        }
+    }
+
+    fn statement(&mut self, token: String) -> Parsed<()> {
+        match token.as_str() {
+            ":" => {
+                let label = self.name()?;
+                if label == "main" && self.has_main && self.here == PROGRAM_START + 2 {
+                    self.has_main = false;
+                    self.rom.clear();
+                    self.here = PROGRAM_START;
+                }
+                let here = self.here as f64;
+                self.define(label, here)
+            }
+            ":next" => {
+                let label = self.name()?;
+                let here = self.here as f64 + 1.0;
+                self.define(label, here)
+            }
+            ":const" => {
+                let name = self.name()?;
+                let token = self.next()?;
+                let value = self.lookup(&token).ok_or_else(|| format!("Unknown name '{}'", token))?;
+                self.define(name, value)
+            }
+            ":alias" => {
+                // An alias can be pointed at another register
+                let name = match self.peek() {
+                    Some(token) if self.aliases.contains_key(token) => self.next()?,
+                    _ => self.name()?,
+                };
+                let x = self.register()?;
+                self.aliases.insert(name, x);
+                Ok(())
+            }
+            ":unpack" => {
+                let high = self.value().and_then(nibble)? as u16;
+                let (hi, lo) = (self.aliases["unpack-hi"] as u16, self.aliases["unpack-lo"] as u16);
+                let (token, at) = (self.next()?, self.here);
+                let value = self.address(token, Fixup::Unpack, at)?;
+                self.inst(0x6000 | hi << 8 | high << 4 | value >> 8)?;
+                self.inst(0x6000 | lo << 8 | value & 0xFF)
+            }
+            ":org" => {
+                let to = self.value()?;
+                if to < PROGRAM_START as i64 || to >= MEM_END {
+                    return Err(format!("Can't put code at 0x{:x}", to));
+                }
+                self.here = to as usize;
+                Ok(())
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.calc()? as i64
+                } else {
+                    self.value()?
+                };
+                self.emit(&[byte(value)?], false)
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                // Unlike :const, a :calc can be redone, e.g. in a macro
+                self.constants.remove(&name);
+                self.define(name, value)
+            }
+            ":macro" => {
+                let name = self.name()?;
+                let mut args = Vec::new();
+                while self.peek().is_some_and(|token| token != "{") {
+                    args.push(self.next()?);
+                }
+                let body = self.braced()?;
+                self.macros.insert(name,
+                                   Macro {
+                                       args: args,
+                                       body: body,
+                                       calls: 0,
+                                   });
+                Ok(())
+            }
+            ":call" => {
+                let (token, at) = (self.next()?, self.here);
+                let target = self.address(token, Fixup::Addr, at)?;
+                self.inst(0x2000 | target)
+            }
+            ":breakpoint" => self.next().map(|_| ()),
+            ":monitor" => self.next().and_then(|_| self.next()).map(|_| ()),
+            ";" | "return" => self.inst(0x00EE),
+            "clear" => self.inst(0x00E0),
+            "scroll-down" => {
+                let n = self.value().and_then(nibble)?;
+                self.inst(0x00C0 | n as u16)
+            }
+            "scroll-up" => {
+                let n = self.value().and_then(nibble)?;
+                self.inst(0x00D0 | n as u16)
+            }
+            "scroll-right" => self.inst(0x00FB),
+            "scroll-left" => self.inst(0x00FC),
+            "exit" => self.inst(0x00FD),
+            "lores" => self.inst(0x00FE),
+            "hires" => self.inst(0x00FF),
+            "audio" => self.inst(0xF002),
+            "plane" => {
+                let n = self.value().and_then(nibble)?;
+                self.inst(0xF001 | (n as u16) << 8)
+            }
+            "bcd" | "saveflags" | "loadflags" => {
+                let x = (self.register()? as u16) << 8;
+                self.inst(match token.as_str() {
+                    "bcd" => 0xF033 | x,
+                    "saveflags" => 0xF075 | x,
+                    _ => 0xF085 | x,
+                })
+            }
+            "save" | "load" => {
+                let x = (self.register()? as u16) << 8;
+                let save = token == "save";
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = (self.register()? as u16) << 4;
+                    self.inst(0x5000 | x | y | if save { 0x2 } else { 0x3 })
+                } else {
+                    self.inst(x | if save { 0xF055 } else { 0xF065 })
+                }
+            }
+            "sprite" => {
+                let x = (self.register()? as u16) << 8;
+                let y = (self.register()? as u16) << 4;
+                let n = self.value().and_then(nibble)? as u16;
+                self.inst(0xD000 | x | y | n)
+            }
+            "jump" | "jump0" | "native" => {
+                let (target, at) = (self.next()?, self.here);
+                let target = self.address(target, Fixup::Addr, at)?;
+                self.inst(target |
+                          match token.as_str() {
+                    "jump" => 0x1000,
+                    "jump0" => 0xB000,
+                    _ => 0x0000,
+                })
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = (self.register()? as u16) << 8;
+                self.inst(x |
+                          match token.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                })
+            }
+            "i" => self.index(),
+            "if" => {
+                let (x, compare, operand) = self.condition()?;
+                let word = self.next()?;
+                match word.as_str() {
+                    "then" => self.skip_unless(x, compare, operand),
+                    "begin" => {
+                        self.skip_unless(x, compare.negate(), operand)?;
+                        self.branches.push((self.here, self.line, false));
+                        self.inst(0x1000)
+                    }
+                    _ => Err(format!("Expected 'then' or 'begin', found '{}'", word)),
+                }
+            }
+            "else" => {
+                let at = match self.branches.pop() {
+                    Some((at, _, false)) => at,
+                    _ => return Err("This 'else' does not have a matching 'begin'".to_string()),
+                };
+                let after = self.here as i64 + 2;
+                self.patch(at, Fixup::Addr, after)?;
+                self.branches.push((self.here, self.line, true));
+                self.inst(0x1000)
+            }
+            "end" => {
+                let at = self.branches
+                    .pop()
+                    .ok_or_else(|| "This 'end' does not have a matching 'begin'".to_string())?
+                    .0;
+                let here = self.here as i64;
+                self.patch(at, Fixup::Addr, here)
+            }
+            "loop" => {
+                self.loops.push((self.here, self.line));
+                self.whiles.push(None);
+                Ok(())
+            }
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err("This 'while' is not inside a loop".to_string());
+                }
+                let (x, compare, operand) = self.condition()?;
+                self.skip_unless(x, compare.negate(), operand)?;
+                self.whiles.push(Some(self.here));
+                self.inst(0x1000)
+            }
+            "again" => {
+                let start = self.loops
+                    .pop()
+                    .ok_or_else(|| "This 'again' does not have a matching 'loop'".to_string())?
+                    .0;
+                self.inst(0x1000 | addr(start as i64)?)?;
+                let here = self.here as i64;
+                while let Some(Some(at)) = self.whiles.pop() {
+                    self.patch(at, Fixup::Addr, here)?;
+                }
+                Ok(())
+            }
+            _ if self.register_named(&token).is_some() => self.assign(&token),
+            _ if self.macros.contains_key(&token) => self.expand(&token),
+            _ if calc::number(&token).is_some() => {
+                let value = calc::number(&token).unwrap() as i64;
+                self.emit(&[byte(value)?], false)
+            }
+            _ => {
+                // Anything else is a subroutine call
+                let at = self.here;
+                let target = self.address(token, Fixup::Addr, at)?;
+                self.inst(0x2000 | target)
+            }
+        }
+    }
+
+    fn index(&mut self) -> Parsed<()> {
+        let op = self.next()?;
+        match op.as_str() {
+            "+=" => {
+                let x = (self.register()? as u16) << 8;
+                self.inst(0xF01E | x)
+            }
+            ":=" => {
+                match self.peek() {
+                    Some("long") => {
+                        self.next()?;
+                        let (token, at) = (self.next()?, self.here);
+                        let target = self.address(token, Fixup::Long, at)?;
+                        self.emit(&[0xF0, 0x00, (target >> 8) as u8, target as u8], true)
+                    }
+                    Some("hex") => {
+                        self.next()?;
+                        let x = (self.register()? as u16) << 8;
+                        self.inst(0xF029 | x)
+                    }
+                    Some("bighex") => {
+                        self.next()?;
+                        let x = (self.register()? as u16) << 8;
+                        self.inst(0xF030 | x)
+                    }
+                    _ => {
+                        let (token, at) = (self.next()?, self.here);
+                        let target = self.address(token, Fixup::Addr, at)?;
+                        self.inst(0xA000 | target)
+                    }
+                }
+            }
+            _ => Err(format!("Unknown operator 'i {}'", op)),
+        }
+    }
+
+    fn assign(&mut self, token: &str) -> Parsed<()> {
+        let x = (self.register_named(token).unwrap() as u16) << 8;
+        let op = self.next()?;
+        if op == ":=" && !self.is_register() {
+            let source = self.peek().map(String::from);
+            return match source.as_deref() {
+                Some("random") => {
+                    self.next()?;
+                    let mask = self.value().and_then(byte)?;
+                    self.inst(0xC000 | x | mask as u16)
+                }
+                Some("key") => {
+                    self.next()?;
+                    self.inst(0xF00A | x)
+                }
+                Some("delay") => {
+                    self.next()?;
+                    self.inst(0xF007 | x)
+                }
+                _ => {
+                    let n = self.value().and_then(byte)?;
+                    self.inst(0x6000 | x | n as u16)
+                }
+            };
+        }
+        let math = match op.as_str() {
+            ":=" => 0x0,
+            "|=" => 0x1,
+            "&=" => 0x2,
+            "^=" => 0x3,
+            "+=" => 0x4,
+            "-=" => 0x5,
+            ">>=" => 0x6,
+            "=-" => 0x7,
+            "<<=" => 0xE,
+            _ => return Err(format!("Unknown operator '{}'", op)),
+        };
+        if self.is_register() {
+            let y = (self.register()? as u16) << 4;
+            return self.inst(0x8000 | x | y | math);
+        }
+        let n = self.value().and_then(byte)?;
+        match op.as_str() {
+            "+=" => self.inst(0x7000 | x | n as u16),
+            "-=" => self.inst(0x7000 | x | n.wrapping_neg() as u16),
+            _ => Err(format!("'{}' needs a register", op)),
+        }
+    }
+
+    fn expand(&mut self, name: &str) -> Parsed<()> {
+        let (args, body, calls) = {
+            let m = self.macros.get_mut(name).unwrap();
+            m.calls += 1;
+            (m.args.clone(), m.body.clone(), m.calls - 1)
+        };
+        let mut values = Vec::new();
+        for _ in 0..args.len() {
+            values.push(self.next()?);
+        }
+        for token in body.into_iter().rev() {
+            let token = match args.iter().position(|arg| *arg == token) {
+                Some(i) => values[i].clone(),
+                None if token == "CALLS" => calls.to_string(),
+                None => token,
+            };
+            self.tokens.push_front((token, self.line));
+        }
+        Ok(())
+    }
+
+    // Check everything was closed and defined, and jump to main
+    fn finish(&mut self) -> ::std::result::Result<(), (usize, String)> {
+        if let Some(&(_, line)) = self.loops.last() {
+            return Err((line, "This 'loop' does not have a matching 'again'".to_string()));
+        }
+        if let Some(&(_, line, _)) = self.branches.last() {
+            return Err((line, "This 'begin' does not have a matching 'end'".to_string()));
+        }
+        if let Some(&(ref name, _, _, line)) = self.fixups.first() {
+            return Err((line, format!("Unknown name '{}'", name)));
+        }
+        if self.has_main {
+            let main = match self.constants.get("main") {
+                Some(&main) => main as i64,
+                None => {
+                    return Err((self.line,
+                                "The program does not define a subroutine called 'main'"
+                                    .to_string()))
+                }
+            };
+            self.rom[0] = 0x10;
+            self.patch(PROGRAM_START, Fixup::Addr, main).map_err(|e| (self.line, e))?;
+        }
+        Ok(())
+    }
+
+    // One row for each instruction, or run of data, on each line
+    fn listing(&self, source: &str) -> Vec<Listed> {
+        let mut listed = Vec::new();
+        for (n, text) in source.lines().enumerate() {
+            let line = n + 1;
+            let mut rows: Vec<(usize, usize, bool)> = Vec::new();
+            for emitted in self.emitted.iter().filter(|e| e.line == line) {
+                match rows.last_mut() {
+                    Some(&mut (addr, ref mut len, false)) if !emitted.code &&
+                                                              addr + *len == emitted.addr => {
+                        *len += emitted.len;
+                        continue;
+                    }
+                    _ => {}
+                }
+                rows.push((emitted.addr, emitted.len, emitted.code));
+            }
+            if rows.is_empty() {
+                listed.push(Listed {
+                    line: line,
+                    text: text.to_string(),
+                    addr: None,
+                    bytes: Vec::new(),
+                });
+            }
+            for (i, (addr, len, _)) in rows.into_iter().enumerate() {
+                let start = addr - PROGRAM_START;
+                listed.push(Listed {
+                    line: line,
+                    text: if i == 0 { text.to_string() } else { String::new() },
+                    addr: Some(addr as u16),
+                    bytes: self.rom[start..start + len].to_vec(),
+                });
+            }
+        }
+        listed
+    }
+}
+
+/// Assemble Octo source, the language most CHIP-8 programs are written in
+///
+/// Programs start with a jump to `: main`, unless it is the first thing
+/// defined, which gives the same bytes as Octo itself. `name` is the file
+/// name used in errors and the listing.
+pub fn assemble_octo(source: &str, name: &str) -> Result<Program> {
+    let mut octo = Octo::new(source);
+    while let Ok(token) = octo.next() {
+        octo.statement(token).map_err(|e| located(name, octo.line, e))?;
+    }
+    octo.finish().map_err(|(line, e)| located(name, line, e))?;
+    Ok(Program {
+        name: name.to_string(),
+        lines: octo.listing(source),
+        bytes: octo.rom,
+    })
+}
+
+#[cfg(test)]
+fn octo(source: &str) -> Result<Vec<u8>> {
+    assemble_octo(source, "test.8o").map(|program| program.bytes().to_vec())
+}
+
+#[test]
+fn octo_main_first() {
+    let source = ": main\n\
+                  \tv0 := 5\n\
+                  \ti := digit # defined below\n\
+                  \tloop\n\
+                  \t\tsprite v0 v1 5\n\
+                  \t\tv0 += 1\n\
+                  \t\tif v0 == 10 then v0 := 0\n\
+                  \tagain\n\
+                  : digit 0x20 0x60 0x20 0x20 0x70\n";
+    assert_eq!(octo(source).unwrap(),
+               vec![0x60, 0x05, 0xa2, 0x0e, 0xd0, 0x15, 0x70, 0x01, 0x40, 0x0a, 0x60, 0x00, 0x12,
+                    0x04, 0x20, 0x60, 0x20, 0x20, 0x70]);
+}
+
+#[test]
+fn octo_control_flow() {
+    let source = ":const SPEED 3\n\
+                  :alias x v2\n\
+                  : data 0x01 0x02\n\
+                  :macro twice R { R += SPEED R += SPEED }\n\
+                  : main\n\
+                  \tx := 7\n\
+                  \ttwice x\n\
+                  \tif x > 10 begin\n\
+                  \t\tx := 0\n\
+                  \telse\n\
+                  \t\tx -= 1\n\
+                  \tend\n\
+                  \tloop\n\
+                  \t\tx -= 1\n\
+                  \t\twhile x != 0\n\
+                  \tagain\n\
+                  \t:unpack 0xA data\n\
+                  \ti := long data\n\
+                  \t:calc SIZE { 2 * SPEED + 1 }\n\
+                  \tv0 := SIZE\n\
+                  \t:next target v1 := 0\n\
+                  \ti := target\n\
+                  \t;\n";
+    let program = assemble_octo(source, "test.8o").unwrap();
+    assert_eq!(program.bytes(),
+               &[0x12, 0x04, 0x01, 0x02, 0x62, 0x07, 0x72, 0x03, 0x72, 0x03, 0x6f, 0x0a, 0x8f,
+                 0x25, 0x3f, 0x00, 0x12, 0x16, 0x62, 0x00, 0x12, 0x18, 0x72, 0xff, 0x72, 0xff,
+                 0x42, 0x00, 0x12, 0x20, 0x12, 0x18, 0x60, 0xa2, 0x61, 0x02, 0xf0, 0x00, 0x02,
+                 0x02, 0x60, 0x08, 0x61, 0x00, 0xa2, 0x2b, 0x00, 0xee][..]);
+    let listing = program.listing();
+    assert!(listing.contains("0x0202 test.8o:3  01 02        : data 0x01 0x02\n"));
+    assert!(listing.contains("0x0206 test.8o:7  72 03        twice x\n\
+                              0x0208 test.8o:7  72 03\n"));
+}
+
+#[test]
+fn octo_compare_temp() {
+    // vE := v2  vE =- v1  if vf != 0 then
+    assert_eq!(octo(": main\n  :alias compare-temp vE\n  if v1 < v2 then v3 := 1\n").unwrap(),
+               vec![0x8e, 0x20, 0x8e, 0x17, 0x4f, 0x00, 0x63, 0x01]);
+    assert_eq!(octo(": main\n  if v1 >= 5 then v3 := 1\n").unwrap(),
+               vec![0x6f, 0x05, 0x8f, 0x17, 0x3f, 0x00, 0x63, 0x01]);
+}
+
+#[test]
+fn octo_errors() {
+    let error = |source| octo(source).unwrap_err().to_string();
+    assert_eq!(error(": main\n  jump nowhere\n"), "test.8o:2: Unknown name 'nowhere'");
+    assert_eq!(error(": main\n  loop\n  v0 := 1\n"),
+               "test.8o:2: This 'loop' does not have a matching 'again'");
+    assert_eq!(error(": main\n  v0 := 256\n"),
+               "test.8o:2: 256 is out of range, it must fit in 0xff");
+    assert_eq!(error(": main\n  v0 ^= 1\n"), "test.8o:2: '^=' needs a register");
+    assert_eq!(error(": main\n: main\n"), "test.8o:2: 'main' is already defined");
+    assert_eq!(error(": start\n  clear\n"),
+               "test.8o:2: The program does not define a subroutine called 'main'");
+}
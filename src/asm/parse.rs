@@ -0,0 +1,251 @@
+//
+// Rust Core Imports
+//
+use std::collections::HashMap;
+
+//
+// This Crate Imports
+//
+use cpu::{Reg, reg};
+use super::Parsed;
+use super::instruction::Arg;
+
+/// A number, label or constant, or a sum of them, e.g. `sprites+5`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr(Vec<(bool, Term)>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Parsed<Expr> {
+        let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut rest = text.as_str();
+        let mut negated = rest.starts_with('-');
+        if negated {
+            rest = &rest[1..];
+        }
+        let mut terms = Vec::new();
+        loop {
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            terms.push((negated, term(&rest[..end])?));
+            if end == rest.len() {
+                break;
+            }
+            negated = rest[end..].starts_with('-');
+            rest = &rest[end + 1..];
+        }
+        Ok(Expr(terms))
+    }
+
+    /// Work out the value, every symbol in it must already be defined
+    pub fn eval(&self, symbols: &HashMap<String, i64>) -> Parsed<i64> {
+        let mut value = 0i64;
+        for &(negated, ref term) in &self.0 {
+            let term = match *term {
+                Term::Number(n) => n,
+                Term::Symbol(ref name) => {
+                    *symbols.get(name).ok_or_else(|| format!("Unknown label '{}'", name))?
+                }
+            };
+            value = if negated { value - term } else { value + term };
+        }
+        Ok(value)
+    }
+}
+
+fn term(text: &str) -> Parsed<Term> {
+    if text.is_empty() {
+        return Err("Missing value".to_string());
+    }
+    let parsed = if text.starts_with("0x") || text.starts_with("0X") {
+        i64::from_str_radix(&text[2..], 16)
+    } else if text.starts_with("0b") || text.starts_with("0B") {
+        i64::from_str_radix(&text[2..], 2)
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        text.parse()
+    } else {
+        return identifier(text).map(Term::Symbol);
+    };
+    parsed.map(Term::Number).map_err(|_| format!("Not a number: '{}'", text))
+}
+
+fn register(text: &str) -> Option<Reg> {
+    let upper = text.to_uppercase();
+    if upper.len() == 2 && upper.starts_with('V') {
+        u8::from_str_radix(&upper[1..], 16).ok().and_then(|x| reg(x).ok())
+    } else {
+        None
+    }
+}
+
+/// Check a label or constant name, registers can't be used
+pub fn identifier(text: &str) -> Parsed<String> {
+    let valid = text.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '.') &&
+                text.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+    if !valid || register(text).is_some() || text.eq_ignore_ascii_case("VI") {
+        return Err(format!("'{}' can't be used as a name", text));
+    }
+    Ok(text.to_string())
+}
+
+/// One operand of an instruction, as written
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Reg(Reg),
+    /// `VI`
+    Index,
+    /// `V0+ADDR`, the target of `JumpAddV0`
+    V0Plus(Expr),
+    Value(Expr),
+}
+
+impl Operand {
+    fn parse(text: &str) -> Parsed<Operand> {
+        if let Some(x) = register(text) {
+            return Ok(Operand::Reg(x));
+        }
+        let upper = text.to_uppercase();
+        Ok(if upper == "VI" {
+            Operand::Index
+        } else if upper.starts_with("V0+") {
+            Operand::V0Plus(Expr::parse(&text[3..])?)
+        } else {
+            Operand::Value(Expr::parse(text)?)
+        })
+    }
+
+    pub fn eval(&self, symbols: &HashMap<String, i64>) -> Parsed<Arg> {
+        Ok(match *self {
+            Operand::Reg(x) => Arg::Reg(x),
+            Operand::Index => Arg::Index,
+            Operand::V0Plus(ref addr) => Arg::V0Plus(addr.eval(symbols)?),
+            Operand::Value(ref value) => Arg::Value(value.eval(symbols)?),
+        })
+    }
+}
+
+/// Everything a line can do, apart from defining a label
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    /// An upper case mnemonic like `LOAD`, and its operands
+    Instruction(String, Vec<Operand>),
+    /// `db 1, 2, 3`
+    Bytes(Vec<Expr>),
+    /// `dw 0x1234, label`
+    Words(Vec<Expr>),
+    /// `org 0x300`, continue at a later address
+    Org(Expr),
+    /// `NAME = VALUE`
+    Const(String, Expr),
+}
+
+/// A line of source, e.g. `loop: DRAW V0 V1 0x05 ; comment`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub label: Option<String>,
+    pub statement: Option<Statement>,
+}
+
+pub fn parse_line(text: &str) -> Parsed<Line> {
+    let code = match text.find(';') {
+        Some(comment) => &text[..comment],
+        None => text,
+    };
+    let mut rest = code.trim();
+    let mut label = None;
+    if let Some(first) = rest.split_whitespace().next() {
+        if let Some(name) = first.strip_suffix(':') {
+            label = Some(identifier(name)?);
+            rest = rest[first.len()..].trim();
+        }
+    }
+    if rest.is_empty() {
+        return Ok(Line {
+            label: label,
+            statement: None,
+        });
+    }
+    if let Some(equals) = rest.find('=') {
+        let name = identifier(rest[..equals].trim())?;
+        return Ok(Line {
+            label: label,
+            statement: Some(Statement::Const(name, Expr::parse(&rest[equals + 1..])?)),
+        });
+    }
+
+    let (word, args) = match rest.find(char::is_whitespace) {
+        Some(space) => (&rest[..space], rest[space..].trim()),
+        None => (rest, ""),
+    };
+    // Operands are separated by spaces like the disassembly, or by commas
+    // when they have spaces in them
+    let args: Vec<&str> = if args.contains(',') {
+        args.split(',').map(str::trim).collect()
+    } else {
+        args.split_whitespace().collect()
+    };
+    let exprs = || -> Parsed<Vec<Expr>> {
+        if args.is_empty() {
+            return Err(format!("{} needs a value", word));
+        }
+        args.iter().map(|arg| Expr::parse(arg)).collect()
+    };
+    let statement = match word.to_lowercase().as_str() {
+        "db" => Statement::Bytes(exprs()?),
+        "dw" => Statement::Words(exprs()?),
+        "org" => {
+            let mut exprs = exprs()?;
+            if exprs.len() != 1 {
+                return Err("org takes one address".to_string());
+            }
+            Statement::Org(exprs.remove(0))
+        }
+        _ => {
+            let operands = args.iter()
+                .map(|arg| Operand::parse(arg))
+                .collect::<Parsed<Vec<Operand>>>()?;
+            Statement::Instruction(word.to_uppercase(), operands)
+        }
+    };
+    Ok(Line {
+        label: label,
+        statement: Some(statement),
+    })
+}
+
+#[test]
+fn parse_lines() {
+    let expr = |text| Expr::parse(text).unwrap();
+    let line = parse_line("loop: DRAW V0 vb 0x05 ; comment").unwrap();
+    assert_eq!(line.label, Some("loop".to_string()));
+    assert_eq!(line.statement,
+               Some(Statement::Instruction("DRAW".to_string(),
+                                           vec![Operand::Reg(Reg::V0),
+                                                Operand::Reg(Reg::VB),
+                                                Operand::Value(expr("5"))])));
+    assert_eq!(parse_line("  load vi, V0+table - 2").unwrap().statement,
+               Some(Statement::Instruction("LOAD".to_string(),
+                                           vec![Operand::Index,
+                                                Operand::V0Plus(expr("table-2"))])));
+    assert_eq!(parse_line("SPEED = 0b101").unwrap().statement,
+               Some(Statement::Const("SPEED".to_string(), expr("5"))));
+    assert_eq!(parse_line("; nothing").unwrap(),
+               Line {
+                   label: None,
+                   statement: None,
+               });
+
+    let mut symbols = HashMap::new();
+    symbols.insert("a".to_string(), 0x300);
+    assert_eq!(expr("-1").eval(&symbols), Ok(-1));
+    assert_eq!(expr("a + 0x10 - 2").eval(&symbols), Ok(0x30e));
+    assert!(expr("b").eval(&symbols).is_err());
+    assert!(Expr::parse("1+").is_err());
+    assert!(parse_line("v3: CLS").is_err());
+    assert!(parse_line("db").is_err());
+    assert!(parse_line("org 1 2").is_err());
+}
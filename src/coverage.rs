@@ -0,0 +1,158 @@
+//
+// Rust Core Imports
+//
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use emulator::MemAddr;
+
+const MAGIC: &'static [u8; 4] = b"C8CV";
+const VERSION: u8 = 1;
+
+/// How the running ROM used an address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    /// The first byte of an instruction that was run
+    Fetch,
+    /// The rest of an instruction that was run
+    Operand,
+    /// Loaded by an instruction, like `REG.LOAD` or `AUDIO`
+    Read,
+    /// Stored by an instruction, like `REG.DUMP` or `BCD`
+    Write,
+    /// Drawn as part of a sprite
+    Draw,
+}
+
+impl Access {
+    fn bit(&self) -> u8 {
+        1 << *self as u8
+    }
+}
+
+/// Every `Access` made to each address of RAM during a run
+///
+/// Saved as a `C8CV` header, the size of RAM as a big endian `u32`, then one
+/// byte per address with a bit set for each `Access`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage {
+    marks: Vec<u8>,
+}
+
+impl Coverage {
+    /// An empty map of `size` bytes of RAM
+    pub fn new(size: usize) -> Self {
+        Coverage { marks: vec![0; size] }
+    }
+
+    /// How many addresses are covered
+    pub fn size(&self) -> usize {
+        self.marks.len()
+    }
+
+    /// Note an `access` to `addr`, addresses past the end are ignored
+    pub fn record(&mut self, addr: usize, access: Access) {
+        if let Some(mark) = self.marks.get_mut(addr) {
+            *mark |= access.bit();
+        }
+    }
+
+    /// Was `addr` used this way?
+    pub fn has(&self, addr: MemAddr, access: Access) -> bool {
+        self.marks.get(addr as usize).is_some_and(|mark| mark & access.bit() != 0)
+    }
+
+    /// Was `addr` used at all?
+    pub fn touched(&self, addr: MemAddr) -> bool {
+        self.marks.get(addr as usize).is_some_and(|&mark| mark != 0)
+    }
+
+    /// Every address that was run as the start of an instruction
+    pub fn fetched(&self) -> Vec<MemAddr> {
+        (0..self.marks.len())
+            .map(|addr| addr as MemAddr)
+            .filter(|&addr| self.has(addr, Access::Fetch))
+            .collect()
+    }
+
+    /// Add in the accesses from another run
+    pub fn merge(&mut self, other: &Coverage) {
+        if other.marks.len() > self.marks.len() {
+            self.marks.resize(other.marks.len(), 0);
+        }
+        for (mark, other) in self.marks.iter_mut().zip(&other.marks) {
+            *mark |= *other;
+        }
+    }
+
+    /// The map in its saved form
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(MAGIC.len() + 5 + self.marks.len());
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        let size = self.marks.len() as u32;
+        buf.extend_from_slice(&[(size >> 24) as u8, (size >> 16) as u8, (size >> 8) as u8,
+                                size as u8]);
+        buf.extend_from_slice(&self.marks);
+        buf
+    }
+
+    /// Read back a map from `to_bytes`
+    pub fn from_bytes(buf: &[u8]) -> Result<Self> {
+        let header = MAGIC.len() + 5;
+        if buf.len() < header || &buf[..MAGIC.len()] != MAGIC {
+            bail!(ErrorKind::BadCoverage("not a coverage map"));
+        }
+        if buf[MAGIC.len()] != VERSION {
+            bail!(ErrorKind::BadCoverage("unsupported version"));
+        }
+        let size = buf[MAGIC.len() + 1..header]
+            .iter()
+            .fold(0usize, |size, &byte| size << 8 | byte as usize);
+        if buf.len() - header != size {
+            bail!(ErrorKind::BadCoverage("wrong size"));
+        }
+        Ok(Coverage { marks: buf[header..].to_vec() })
+    }
+
+    /// Write the map to a file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        File::create(path)?.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Read a map written by `save`
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut buf = Vec::new();
+        File::open(path)?.read_to_end(&mut buf)?;
+        Coverage::from_bytes(&buf)
+    }
+}
+
+#[test]
+fn coverage_round_trip() {
+    let mut coverage = Coverage::new(0x1000);
+    coverage.record(0x200, Access::Fetch);
+    coverage.record(0x201, Access::Operand);
+    coverage.record(0x300, Access::Draw);
+    coverage.record(0x2000, Access::Read);
+    assert!(coverage.has(0x200, Access::Fetch));
+    assert!(!coverage.has(0x200, Access::Draw));
+    assert!(!coverage.touched(0x2000));
+    assert_eq!(coverage.fetched(), vec![0x200]);
+
+    let mut other = Coverage::new(0x1000);
+    other.record(0x300, Access::Read);
+    coverage.merge(&other);
+    assert!(coverage.has(0x300, Access::Draw) && coverage.has(0x300, Access::Read));
+
+    let bytes = coverage.to_bytes();
+    assert_eq!(Coverage::from_bytes(&bytes).unwrap(), coverage);
+    assert!(Coverage::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Coverage::from_bytes(b"C8SS").is_err());
+}
@@ -3,38 +3,68 @@
 //
 use std::fmt;
 
-//
-// Third Party Imports
-//
-use slog;
-use rand;
-
 //
 // Declare sub modules
 //
 mod opcodes;
 mod register;
+mod rng;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use quirks::{Machine, Quirks, IndexIncrement};
+use state::{StateReader, StateWriter};
 
 //
 // Public Exports
 //
-pub use self::opcodes::disassemble;
-pub use self::opcodes::Opcode;
+pub use self::opcodes::decode;
+pub use self::opcodes::{Opcode, LONG_MEM_LOAD, variant};
 pub use self::register::{Reg, reg};
-use emulator::{MemAddr, PROGRAM_START};
+pub use self::rng::Rng;
+use emulator::{Config, MemAddr, PROGRAM_START};
+const STACK_SIZE: usize = 16;
+const SCHIP_FLAGS: usize = 8;
 use interconnect::Interconnect;
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Cpu {
-    gpregs: [u8; 16],
-    stack: [MemAddr; 16],
-    vi: MemAddr,
+    pub gpregs: [u8; 16],
+    pub stack: [MemAddr; STACK_SIZE],
+    pub vi: MemAddr,
     pub pc: MemAddr,
-    sp: usize,
-    delay: u8,
-    sound: u8,
-    logger: slog::Logger,
+    pub sp: usize,
+    pub delay: u8,
+    pub sound: u8,
+    flags: [u8; 16],
+    rng: Rng,
+    machine: Machine,
+    quirks: Quirks,
+}
+
+/// Read and decode the instruction at `addr`, returning its first halfword too
+pub fn fetch(interconnect: &Interconnect, addr: MemAddr) -> Result<(u16, Opcode)> {
+    let instr = interconnect.read_halfword(addr)?;
+    let next = if instr == LONG_MEM_LOAD {
+        interconnect.read_halfword(addr.wrapping_add(2))?
+    } else {
+        0
+    };
+    let opcode = decode(instr, next).chain_err(|| ErrorKind::IllegalOpcode(addr, instr))?;
+    Ok((instr, opcode))
+}
+
+/// The register indexes from `x` to `y`, counting down if `y` is less than `x`
+fn reg_range(x: Reg, y: Reg) -> Vec<usize> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y {
+        (x..y + 1).collect()
+    } else {
+        (y..x + 1).rev().collect()
+    }
 }
 
 fn bcd(n: u8) -> (u8, u8, u8) {
@@ -56,16 +86,19 @@ fn check_bcd() {
 }
 
 impl Cpu {
-    pub fn init(logger: slog::Logger) -> Self {
+    pub fn init(config: &Config, seed: u64) -> Self {
         Cpu {
             gpregs: [0u8; 16],
-            stack: [0u16; 16],
+            stack: [0u16; STACK_SIZE],
             vi: 0,
             pc: PROGRAM_START as _,
             sp: 0,
             delay: 0,
             sound: 0,
-            logger: logger,
+            flags: [0u8; 16],
+            rng: Rng::new(seed),
+            machine: config.machine,
+            quirks: config.quirks,
         }
     }
 
@@ -74,34 +107,104 @@ impl Cpu {
         &mut self.gpregs[reg as usize]
     }
 
-    fn execute_opcode(&mut self, opcode: &Opcode, interconnect: &mut Interconnect) {
+    #[inline]
+    fn shift_source(&self, x: Reg, y: Reg) -> Reg {
+        if self.quirks.shift_uses_vy { y } else { x }
+    }
+
+    #[inline]
+    fn logic_vf(&mut self) {
+        if self.quirks.logic_resets_vf {
+            *self.reg(Reg::VF) = 0;
+        }
+    }
+
+    fn increment_index(&mut self, x: Reg) {
+        let increment = match self.quirks.index_increment {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::ByX => x as u16,
+            IndexIncrement::ByXPlusOne => x as u16 + 1,
+        };
+        self.vi = self.vi.wrapping_add(increment);
+    }
+
+    /// Skip over the next instruction, which is 4 bytes long if it is `LongMemLoad`
+    fn skip(&mut self, interconnect: &Interconnect) -> Result<()> {
+        let len = if self.machine == Machine::XoChip &&
+                     interconnect.read_halfword(self.pc)? == LONG_MEM_LOAD {
+            4
+        } else {
+            2
+        };
+        self.pc = self.pc.wrapping_add(len);
+        Ok(())
+    }
+
+    fn check_flags(&self, pc: MemAddr, instr: u16, x: Reg) -> Result<()> {
+        if self.machine == Machine::SuperChip && x as usize >= SCHIP_FLAGS {
+            bail!(ErrorKind::IllegalOpcode(pc, instr));
+        }
+        Ok(())
+    }
+
+    fn execute_opcode(&mut self,
+                      pc: MemAddr,
+                      instr: u16,
+                      opcode: &Opcode,
+                      interconnect: &mut Interconnect)
+                      -> Result<()> {
         match opcode {
             &Opcode::ClearScreen => interconnect.clear_sceen(),
             &Opcode::Return => {
+                if self.sp == 0 {
+                    bail!(ErrorKind::StackUnderflow(pc));
+                }
                 self.sp -= 1;
                 self.pc = self.stack[self.sp];
             }
-            &Opcode::SysAddr(addr) => panic!("SysAddr({:04x}) Opcode not Implemented", addr),
+            &Opcode::ScrollDown(n) => interconnect.scroll_down(n as usize),
+            &Opcode::ScrollUp(n) => interconnect.scroll_up(n as usize),
+            &Opcode::ScrollRight => interconnect.scroll_right(),
+            &Opcode::ScrollLeft => interconnect.scroll_left(),
+            &Opcode::Exit => self.pc = pc,
+            &Opcode::LowRes => interconnect.set_hires(false),
+            &Opcode::HighRes => interconnect.set_hires(true),
+            &Opcode::SysAddr(_) => bail!(ErrorKind::IllegalOpcode(pc, instr)),
             &Opcode::JumpAddr(addr) => self.pc = addr,
             &Opcode::CallAddr(addr) => {
+                if self.sp == STACK_SIZE {
+                    bail!(ErrorKind::StackOverflow(pc));
+                }
                 self.stack[self.sp] = self.pc;
                 self.sp += 1;
                 self.pc = addr
             }
             &Opcode::SkipEqByte(x, byte) => {
                 if *self.reg(x) == byte {
-                    self.pc += 2;
+                    self.skip(interconnect)?;
                 }
             }
             &Opcode::SkipNEqByte(x, byte) => {
                 if *self.reg(x) != byte {
-                    self.pc += 2;
+                    self.skip(interconnect)?;
                 }
             }
             &Opcode::SkipEqReg(x, y) => {
                 let value = *self.reg(y);
                 if *self.reg(x) == value {
-                    self.pc += 2;
+                    self.skip(interconnect)?;
+                }
+            }
+            &Opcode::RegRangeSave(x, y) => {
+                for (offset, idx) in reg_range(x, y).into_iter().enumerate() {
+                    let value = self.gpregs[idx];
+                    interconnect.write_byte(self.vi.wrapping_add(offset as u16), value)?;
+                }
+            }
+            &Opcode::RegRangeLoad(x, y) => {
+                for (offset, idx) in reg_range(x, y).into_iter().enumerate() {
+                    self.gpregs[idx] = interconnect.read_byte(self.vi
+                            .wrapping_add(offset as u16))?;
                 }
             }
             &Opcode::LoadByte(x, byte) => {
@@ -116,12 +219,15 @@ impl Cpu {
             }
             &Opcode::BitOr(x, y) => {
                 *self.reg(x) = *self.reg(x) | *self.reg(y);
+                self.logic_vf();
             }
             &Opcode::BitAnd(x, y) => {
                 *self.reg(x) = *self.reg(x) & *self.reg(y);
+                self.logic_vf();
             }
             &Opcode::BitXor(x, y) => {
                 *self.reg(x) = *self.reg(x) ^ *self.reg(y);
+                self.logic_vf();
             }
             &Opcode::MathAdd(x, y) => {
                 let (z, overflow) = self.reg(x).overflowing_add(*self.reg(y));
@@ -141,9 +247,10 @@ impl Cpu {
                     *self.reg(Reg::VF) = 1;
                 }
             }
-            &Opcode::ShiftRight(x, _) => {
-                *self.reg(Reg::VF) = *self.reg(x) & 0x1;
-                *self.reg(x) = *self.reg(x) >> 1;
+            &Opcode::ShiftRight(x, y) => {
+                let value = *self.reg(self.shift_source(x, y));
+                *self.reg(x) = value >> 1;
+                *self.reg(Reg::VF) = value & 0x1;
             }
             &Opcode::MathSubN(x, y) => {
                 let (z, overflow) = self.reg(y).overflowing_sub(*self.reg(x));
@@ -154,41 +261,61 @@ impl Cpu {
                     *self.reg(Reg::VF) = 1;
                 }
             }
-            &Opcode::ShiftLeft(x, _) => {
-                *self.reg(Reg::VF) = *self.reg(x) >> 7 & 0x1;
-                *self.reg(x) = *self.reg(x) << 1;
+            &Opcode::ShiftLeft(x, y) => {
+                let value = *self.reg(self.shift_source(x, y));
+                *self.reg(x) = value << 1;
+                *self.reg(Reg::VF) = value >> 7 & 0x1;
             }
             &Opcode::SkipNEqReg(x, y) => {
                 let value = *self.reg(y);
                 if *self.reg(x) != value {
-                    self.pc += 2;
+                    self.skip(interconnect)?;
                 }
             }
             &Opcode::MemLoad(addr) => {
                 self.vi = addr;
             }
-            &Opcode::JumpAddV0(addr) => self.pc = addr + *self.reg(Reg::V0) as u16,
+            &Opcode::JumpAddV0(addr) => {
+                let offset = if self.quirks.jump_uses_vx {
+                    reg((addr >> 8) as u8 & 0xF)?
+                } else {
+                    Reg::V0
+                };
+                self.pc = addr + *self.reg(offset) as u16;
+            }
             &Opcode::Rand(x, byte) => {
-                let randombyte = rand::random::<u8>();
+                let randombyte = self.rng.next_u8();
                 *self.reg(x) = randombyte & byte;
             }
             &Opcode::Draw(x, y, byte) => {
+                let (width, rows) = if byte == 0 && self.machine >= Machine::SuperChip {
+                    (16, 16)
+                } else {
+                    (8, byte as usize)
+                };
                 let collision = interconnect.draw_sprite(self.vi as _,
                                                          *self.reg(x) as _,
                                                          *self.reg(y) as _,
-                                                         byte as _);
+                                                         width,
+                                                         rows)?;
                 *self.reg(Reg::VF) = if collision { 1 } else { 0 };
             }
             &Opcode::KeyEqSkip(x) => {
-                if interconnect.check_key(*self.reg(x) as _) {
-                    self.pc += 2;
+                if interconnect.check_key(*self.reg(x))? {
+                    self.skip(interconnect)?;
                 }
             }
             &Opcode::KeyNEqSkip(x) => {
-                if !interconnect.check_key(*self.reg(x) as _) {
-                    self.pc += 2;
+                if !interconnect.check_key(*self.reg(x))? {
+                    self.skip(interconnect)?;
                 }
             }
+            &Opcode::LongMemLoad(addr) => {
+                self.vi = addr;
+            }
+            &Opcode::PlaneSelect(n) => interconnect.select_planes(n),
+            &Opcode::AudioLoad => interconnect.load_audio(self.vi)?,
+            &Opcode::Pitch(x) => interconnect.set_pitch(*self.reg(x)),
             &Opcode::DelayGet(x) => {
                 *self.reg(x) = self.delay;
             }
@@ -196,7 +323,7 @@ impl Cpu {
                 if let Some(key) = interconnect.get_key() {
                     *self.reg(x) = key
                 } else {
-                    self.pc -= 2;
+                    self.pc = pc;
                 }
             }
             &Opcode::DelaySet(x) => {
@@ -206,28 +333,48 @@ impl Cpu {
                 self.sound = *self.reg(x) as _;
             }
             &Opcode::MemAdd(x) => {
-                self.vi += *self.reg(x) as u16;
+                self.vi = self.vi.wrapping_add(*self.reg(x) as u16);
             }
             &Opcode::MemSprite(x) => {
                 self.vi = interconnect.get_font(*self.reg(x)) as _;
             }
+            &Opcode::BigSprite(x) => {
+                self.vi = interconnect.get_big_font(*self.reg(x)) as _;
+            }
             &Opcode::BCD(x) => {
                 let (hundreds, tens, ones) = bcd(*self.reg(x));
-                interconnect.write_byte(self.vi, hundreds);
-                interconnect.write_byte(self.vi + 1, tens);
-                interconnect.write_byte(self.vi + 2, ones);
+                interconnect.write_byte(self.vi, hundreds)?;
+                interconnect.write_byte(self.vi.wrapping_add(1), tens)?;
+                interconnect.write_byte(self.vi.wrapping_add(2), ones)?;
             }
             &Opcode::RegDump(x) => {
                 for idx in 0..(x as usize + 1) {
-                    interconnect.write_byte(self.vi + (idx as u16), *self.reg(reg(idx as _)));
+                    let value = *self.reg(reg(idx as _)?);
+                    interconnect.write_byte(self.vi.wrapping_add(idx as u16), value)?;
                 }
+                self.increment_index(x);
             }
             &Opcode::RegLoad(x) => {
                 for idx in 0..(x as usize + 1) {
-                    *self.reg(reg(idx as _)) = interconnect.read_byte(self.vi + (idx as u16));
+                    *self.reg(reg(idx as _)?) = interconnect.read_byte(self.vi
+                            .wrapping_add(idx as u16))?;
+                }
+                self.increment_index(x);
+            }
+            &Opcode::FlagSave(x) => {
+                self.check_flags(pc, instr, x)?;
+                for idx in 0..(x as usize + 1) {
+                    self.flags[idx] = self.gpregs[idx];
+                }
+            }
+            &Opcode::FlagLoad(x) => {
+                self.check_flags(pc, instr, x)?;
+                for idx in 0..(x as usize + 1) {
+                    self.gpregs[idx] = self.flags[idx];
                 }
             }
         }
+        Ok(())
     }
 
     pub fn timer(&mut self, ticks: u64) {
@@ -244,14 +391,66 @@ impl Cpu {
         }
     }
 
-    pub fn run_cycle(&mut self, interconnect: &mut Interconnect) {
-        let instr = interconnect.read_halfword(self.pc);
-        let opcode = disassemble(instr).unwrap();
-        debug!(self.logger, "run_cycle";
-               "opcode" => format!("{}", opcode),
-               "pc" => format!("0x{:04x}", self.pc));
-        self.pc += 2; // We moved two bytes
-        self.execute_opcode(&opcode, interconnect);
+    /// Restart the random number generator from `seed`
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.sound > 0
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.machine as u8);
+        state.bytes(&self.gpregs);
+        for &addr in self.stack.iter() {
+            state.u16(addr);
+        }
+        state.u16(self.vi);
+        state.u16(self.pc);
+        state.u8(self.sp as u8);
+        state.u8(self.delay);
+        state.u8(self.sound);
+        state.bytes(&self.flags);
+        state.u64(self.rng.state());
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        if state.u8()? != self.machine as u8 {
+            bail!(ErrorKind::BadSaveState("saved from a different machine"));
+        }
+        self.gpregs.copy_from_slice(state.bytes(16)?);
+        for addr in self.stack.iter_mut() {
+            *addr = state.u16()?;
+        }
+        self.vi = state.u16()?;
+        self.pc = state.u16()?;
+        self.sp = state.u8()? as usize;
+        if self.sp > STACK_SIZE {
+            bail!(ErrorKind::BadSaveState("corrupt"));
+        }
+        self.delay = state.u8()?;
+        self.sound = state.u8()?;
+        self.flags.copy_from_slice(state.bytes(16)?);
+        self.rng = Rng::from_state(state.u64()?);
+        Ok(())
+    }
+
+    /// Fetch, decode and execute one instruction
+    ///
+    /// If the instruction faults, `pc` is left pointing at it.
+    pub fn run_cycle(&mut self, interconnect: &mut Interconnect) -> Result<Opcode> {
+        let pc = self.pc;
+        let (instr, opcode) = fetch(interconnect, pc)?;
+        if opcode.machine() > self.machine {
+            bail!(ErrorKind::IllegalOpcode(pc, instr));
+        }
+        self.pc = self.pc.wrapping_add(opcode.len());
+        if let Err(e) = self.execute_opcode(pc, instr, &opcode, interconnect) {
+            self.pc = pc;
+            return Err(e);
+        }
+        Ok(opcode)
     }
 }
 
@@ -264,12 +463,265 @@ impl fmt::Display for Cpu {
                  self.delay,
                  self.sound)?;
         writeln!(f, "\tstack: {:?}", self.stack)?;
-        for i in 0..0x10 {
-            let ireg = reg(i);
-            let ival = self.gpregs[i as usize];
-            writeln!(f, "\t{:?} = 0x{:02x}", ireg, ival)?;
+        for (i, ival) in self.gpregs.iter().enumerate() {
+            writeln!(f, "\tV{:X} = 0x{:02x}", i, ival)?;
         }
         writeln!(f, "\tVI = 0x{:04x}", self.vi)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+fn test_cpu_config(rom: &[u8], config: Config) -> (Cpu, Interconnect) {
+    use slog::DrainExt;
+    let logger = ::slog::Logger::root(::slog_stdlog::StdLog.fuse(), o!());
+    let mut interconnect = Interconnect::init(logger, &config);
+    interconnect.load_bytes(rom).unwrap();
+    (Cpu::init(&config, 0), interconnect)
+}
+
+#[cfg(test)]
+fn test_cpu_quirks(rom: &[u8], quirks: Quirks) -> (Cpu, Interconnect) {
+    test_cpu_config(rom, Config { quirks: quirks, ..Config::default() })
+}
+
+#[cfg(test)]
+fn test_cpu(rom: &[u8]) -> (Cpu, Interconnect) {
+    test_cpu_config(rom, Config::default())
+}
+
+#[test]
+fn stack_faults() {
+    // RETURN
+    let (mut cpu, mut ic) = test_cpu(&[0x00, 0xee]);
+    match cpu.run_cycle(&mut ic) {
+        Err(Error(ErrorKind::StackUnderflow(0x200), _)) => {}
+        r => panic!("expected stack underflow, got {:?}", r),
+    }
+    assert_eq!(cpu.pc, 0x200);
+
+    // CALL 0x0200
+    let (mut cpu, mut ic) = test_cpu(&[0x22, 0x00]);
+    for _ in 0..STACK_SIZE {
+        cpu.run_cycle(&mut ic).unwrap();
+    }
+    match cpu.run_cycle(&mut ic) {
+        Err(Error(ErrorKind::StackOverflow(0x200), _)) => {}
+        r => panic!("expected stack overflow, got {:?}", r),
+    }
+}
+
+#[test]
+fn illegal_opcode_faults() {
+    // SYSADDR 0x0123; 0x5121 is not an instruction
+    let (mut cpu, mut ic) = test_cpu(&[0x01, 0x23, 0x51, 0x21]);
+    match cpu.run_cycle(&mut ic) {
+        Err(Error(ErrorKind::IllegalOpcode(0x200, 0x0123), _)) => {}
+        r => panic!("expected illegal opcode, got {:?}", r),
+    }
+    cpu.pc = 0x202;
+    match cpu.run_cycle(&mut ic) {
+        Err(Error(ErrorKind::IllegalOpcode(0x202, 0x5121), _)) => {}
+        r => panic!("expected illegal opcode, got {:?}", r),
+    }
+}
+
+#[test]
+fn memory_faults() {
+    // LOAD VI 0x0fff; BCD V0
+    let (mut cpu, mut ic) = test_cpu(&[0xaf, 0xff, 0xf0, 0x33]);
+    cpu.run_cycle(&mut ic).unwrap();
+    match cpu.run_cycle(&mut ic) {
+        Err(Error(ErrorKind::MemoryOutOfBounds(0x1000), _)) => {}
+        r => panic!("expected out of bounds, got {:?}", r),
+    }
+    assert_eq!(cpu.pc, 0x202);
+}
+
+#[test]
+fn key_faults() {
+    // LOAD V0 0x10; SKIP.KEY V0
+    let (mut cpu, mut ic) = test_cpu(&[0x60, 0x10, 0xe0, 0x9e]);
+    cpu.run_cycle(&mut ic).unwrap();
+    match cpu.run_cycle(&mut ic) {
+        Err(Error(ErrorKind::InvalidKey(0x10), _)) => {}
+        r => panic!("expected invalid key, got {:?}", r),
+    }
+}
+
+#[test]
+fn quirks_vip() {
+    // BIT.SHR V0 V1; BIT.OR V2 V3; REG.DUMP V2
+    let rom = [0x80, 0x16, 0x82, 0x31, 0xf2, 0x55];
+    let (mut cpu, mut ic) = test_cpu_quirks(&rom, Quirks::vip());
+    cpu.gpregs[0x1] = 0x05;
+    cpu.gpregs[0xf] = 0x07;
+    cpu.vi = 0x300;
+    cpu.run_cycle(&mut ic).unwrap();
+    assert_eq!((cpu.gpregs[0x0], cpu.gpregs[0xf]), (0x02, 1));
+    cpu.run_cycle(&mut ic).unwrap();
+    assert_eq!(cpu.gpregs[0xf], 0);
+    cpu.run_cycle(&mut ic).unwrap();
+    assert_eq!(cpu.vi, 0x303);
+
+    let (mut cpu, mut ic) = test_cpu(&rom);
+    cpu.gpregs[0x1] = 0x05;
+    cpu.gpregs[0xf] = 0x07;
+    cpu.vi = 0x300;
+    for _ in 0..3 {
+        cpu.run_cycle(&mut ic).unwrap();
+    }
+    assert_eq!((cpu.gpregs[0x0], cpu.gpregs[0xf]), (0x00, 0));
+    assert_eq!(cpu.vi, 0x300);
+}
+
+#[test]
+fn quirks_jump_uses_vx() {
+    // JUMP V0+0x0310
+    let (mut cpu, mut ic) = test_cpu_quirks(&[0xb3, 0x10], Quirks::schip());
+    cpu.gpregs[0x0] = 0x01;
+    cpu.gpregs[0x3] = 0x04;
+    cpu.run_cycle(&mut ic).unwrap();
+    assert_eq!(cpu.pc, 0x314);
+}
+
+#[cfg(test)]
+fn test_schip(rom: &[u8]) -> (Cpu, Interconnect) {
+    let config = Config {
+        machine: Machine::SuperChip,
+        quirks: Quirks::schip(),
+        ..Config::default()
+    };
+    test_cpu_config(rom, config)
+}
+
+#[test]
+fn schip_needs_machine() {
+    // HIRES
+    let (mut cpu, mut ic) = test_cpu(&[0x00, 0xff]);
+    match cpu.run_cycle(&mut ic) {
+        Err(Error(ErrorKind::IllegalOpcode(0x200, 0x00ff), _)) => {}
+        r => panic!("expected illegal opcode, got {:?}", r),
+    }
+    let (mut cpu, mut ic) = test_schip(&[0x00, 0xff]);
+    cpu.run_cycle(&mut ic).unwrap();
+    assert_eq!((ic.width(), ic.height()), (128, 64));
+}
+
+#[test]
+fn schip_big_sprite() {
+    // HIRES; LOAD V0 0x7c; SPRITE.BIG V0; DRAW V0 V1 0x00
+    let (mut cpu, mut ic) = test_schip(&[0x00, 0xff, 0x60, 0x7c, 0xf0, 0x30, 0xd0, 0x10]);
+    for _ in 0..4 {
+        cpu.run_cycle(&mut ic).unwrap();
+    }
+    // 16 wide, so the right half of the sprite is clipped off
+    assert_eq!(ic.graphics[126], 1);
+    assert_eq!(ic.graphics[ic.width() + 124], 1);
+    assert_eq!(ic.graphics[ic.width() + 2], 0);
+    assert_eq!(cpu.gpregs[0xf], 0);
+}
+
+#[test]
+fn schip_scroll() {
+    // HIRES; SPRITE V0; DRAW V0 V0 0x01; SCROLL.DOWN 0x02; SCROLL.RIGHT; SCROLL.LEFT
+    let (mut cpu, mut ic) = test_schip(&[0x00, 0xff, 0xf0, 0x29, 0xd0, 0x01, 0x00, 0xc2, 0x00,
+                                         0xfb, 0x00, 0xfc]);
+    for _ in 0..4 {
+        cpu.run_cycle(&mut ic).unwrap();
+    }
+    assert_eq!(ic.graphics[0], 0);
+    assert_eq!(ic.graphics[2 * ic.width()], 1);
+    cpu.run_cycle(&mut ic).unwrap();
+    assert_eq!(ic.graphics[2 * ic.width() + 4], 1);
+    cpu.run_cycle(&mut ic).unwrap();
+    assert_eq!(ic.graphics[2 * ic.width()], 1);
+}
+
+#[test]
+fn schip_flags_and_exit() {
+    // FLAGS.SAVE V7; FLAGS.LOAD V8; EXIT
+    let (mut cpu, mut ic) = test_schip(&[0xf7, 0x75, 0xf8, 0x85, 0x00, 0xfd]);
+    cpu.gpregs[0x7] = 0x42;
+    cpu.run_cycle(&mut ic).unwrap();
+    assert_eq!(cpu.flags[0x7], 0x42);
+    match cpu.run_cycle(&mut ic) {
+        Err(Error(ErrorKind::IllegalOpcode(0x202, 0xf885), _)) => {}
+        r => panic!("expected illegal opcode, got {:?}", r),
+    }
+    cpu.pc = 0x204;
+    assert_eq!(cpu.run_cycle(&mut ic).unwrap(), Opcode::Exit);
+    assert_eq!(cpu.pc, 0x204);
+}
+
+#[cfg(test)]
+fn test_xochip(rom: &[u8]) -> (Cpu, Interconnect) {
+    let config = Config {
+        machine: Machine::XoChip,
+        quirks: Quirks::xochip(),
+        ..Config::default()
+    };
+    test_cpu_config(rom, config)
+}
+
+#[test]
+fn xochip_long_load_and_skip() {
+    // SKIP.EQ V0 0x00; LOAD.LONG VI 0x1234; LOAD.LONG VI 0xfedc
+    let (mut cpu, mut ic) = test_xochip(&[0x30, 0x00, 0xf0, 0x00, 0x12, 0x34, 0xf0, 0x00, 0xfe,
+                                          0xdc]);
+    cpu.run_cycle(&mut ic).unwrap();
+    assert_eq!(cpu.pc, 0x206);
+    assert_eq!(cpu.run_cycle(&mut ic).unwrap(), Opcode::LongMemLoad(0xfedc));
+    assert_eq!((cpu.pc, cpu.vi), (0x20a, 0xfedc));
+}
+
+#[test]
+fn xochip_key_wait_at_end_of_memory() {
+    let (mut cpu, mut ic) = test_xochip(&[]);
+    // KEY.WAIT V0, in the last two bytes of memory
+    ic.poke(0xfffe, 0xf0).unwrap();
+    ic.poke(0xffff, 0x0a).unwrap();
+    cpu.pc = 0xfffe;
+    assert_eq!(cpu.run_cycle(&mut ic).unwrap(), Opcode::KeyGet(Reg::V0));
+    assert_eq!(cpu.pc, 0xfffe);
+}
+
+#[test]
+fn xochip_register_ranges() {
+    // REG.SAVE V1 V3; REG.RESTORE V6 V4
+    let (mut cpu, mut ic) = test_xochip(&[0x51, 0x32, 0x56, 0x43]);
+    cpu.gpregs[1] = 1;
+    cpu.gpregs[2] = 2;
+    cpu.gpregs[3] = 3;
+    cpu.vi = 0x8000;
+    cpu.run_cycle(&mut ic).unwrap();
+    assert_eq!(ic.read_byte(0x8002).unwrap(), 3);
+    cpu.run_cycle(&mut ic).unwrap();
+    assert_eq!(&cpu.gpregs[4..7], &[3, 2, 1]);
+    assert_eq!(cpu.vi, 0x8000);
+}
+
+#[test]
+fn xochip_audio() {
+    // LOAD VI 0x0000; AUDIO; LOAD V0 0x80; PITCH V0
+    let (mut cpu, mut ic) = test_xochip(&[0xa0, 0x00, 0xf0, 0x02, 0x60, 0x80, 0xf0, 0x3a]);
+    for _ in 0..4 {
+        cpu.run_cycle(&mut ic).unwrap();
+    }
+    assert_eq!(ic.audio_pattern()[0], 0xf0);
+    assert_eq!(ic.pitch(), 0x80);
+}
+
+#[test]
+fn rand_is_seeded() {
+    // RAND V0 0xff; RAND V1 0x0f
+    let rom = [0xc0, 0xff, 0xc1, 0x0f];
+    let (mut cpu, mut ic) = test_cpu(&rom);
+    cpu.run_cycle(&mut ic).unwrap();
+    cpu.run_cycle(&mut ic).unwrap();
+    let (mut again, mut ic) = test_cpu(&rom);
+    again.run_cycle(&mut ic).unwrap();
+    again.run_cycle(&mut ic).unwrap();
+    assert_eq!(cpu.gpregs, again.gpregs);
+    assert_eq!(cpu.gpregs[1] & 0xf0, 0);
+}
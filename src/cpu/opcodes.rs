@@ -13,46 +13,294 @@ use std::fmt;
 use errors::*;
 use super::MemAddr;
 use cpu::register::{Reg, reg};
+use quirks::Machine;
 
-#[derive(Debug, Clone, Copy)]
+/// Each `Opcode` variant's name, and the mnemonic it is written with
+const VARIANTS: [(&'static str, &'static str); 51] =
+    [("ClearScreen", "CLS"), ("Return", "RETURN"), ("ScrollDown", "SCROLL.DOWN"),
+     ("ScrollUp", "SCROLL.UP"), ("ScrollRight", "SCROLL.RIGHT"), ("ScrollLeft", "SCROLL.LEFT"),
+     ("Exit", "EXIT"), ("LowRes", "LORES"), ("HighRes", "HIRES"), ("SysAddr", "SYSADDR"),
+     ("JumpAddr", "JUMP"), ("CallAddr", "CALL"), ("SkipEqByte", "SKIP.EQ"),
+     ("SkipNEqByte", "SKIP.NEQ"), ("SkipEqReg", "SKIP.EQ"), ("RegRangeSave", "REG.SAVE"),
+     ("RegRangeLoad", "REG.RESTORE"), ("LoadByte", "LOAD"), ("AddByte", "MATH.ADD"),
+     ("LoadReg", "LOAD"), ("BitOr", "BIT.OR"), ("BitAnd", "BIT.AND"), ("BitXor", "BIT.XOR"),
+     ("MathAdd", "MATH.ADD"), ("MathSub", "MATH.SUB"), ("ShiftRight", "BIT.SHR"),
+     ("MathSubN", "MATH.SUBN"), ("ShiftLeft", "BIT.SHL"), ("SkipNEqReg", "SKIP.NEQ"),
+     ("MemLoad", "LOAD"), ("JumpAddV0", "LOAD"), ("Rand", "RAND"), ("Draw", "DRAW"),
+     ("KeyEqSkip", "SKIP.KEY"), ("KeyNEqSkip", "SKIP.NKEY"), ("LongMemLoad", "LOAD.LONG"),
+     ("PlaneSelect", "PLANE"), ("AudioLoad", "AUDIO"), ("DelayGet", "DELAY.GET"),
+     ("KeyGet", "KEY.GET"), ("DelaySet", "DELAY.SET"), ("SoundSet", "SOUND.SET"),
+     ("MemAdd", "MATH.ADD"), ("MemSprite", "SPRITE"), ("BigSprite", "SPRITE.BIG"),
+     ("Pitch", "PITCH"), ("BCD", "BCD"), ("RegDump", "REG.DUMP"), ("RegLoad", "REG.LOAD"),
+     ("FlagSave", "FLAGS.SAVE"), ("FlagLoad", "FLAGS.LOAD")];
+
+/// A single decoded Chip8 instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Opcode {
+    /// 00E0: Clear the screen
     ClearScreen,
+    /// 00EE: Return from a subroutine
     Return,
+    /// 00CN: Scroll the screen down N pixels (SUPER-CHIP)
+    ScrollDown(u8),
+    /// 00DN: Scroll the screen up N pixels (XO-CHIP)
+    ScrollUp(u8),
+    /// 00FB: Scroll the screen right 4 pixels (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC: Scroll the screen left 4 pixels (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD: Stop the interpreter (SUPER-CHIP)
+    Exit,
+    /// 00FE: Switch to the 64x32 screen (SUPER-CHIP)
+    LowRes,
+    /// 00FF: Switch to the 128x64 screen (SUPER-CHIP)
+    HighRes,
+    /// 0NNN: Call a machine code routine
     SysAddr(MemAddr),
+    /// 1NNN: Jump to an address
     JumpAddr(MemAddr),
+    /// 2NNN: Call a subroutine
     CallAddr(MemAddr),
+    /// 3XNN: Skip the next instruction if `VX == NN`
     SkipEqByte(Reg, u8),
+    /// 4XNN: Skip the next instruction if `VX != NN`
     SkipNEqByte(Reg, u8),
+    /// 5XY0: Skip the next instruction if `VX == VY`
     SkipEqReg(Reg, Reg),
+    /// 5XY2: Store `VX` through `VY` in memory starting at `I` (XO-CHIP)
+    RegRangeSave(Reg, Reg),
+    /// 5XY3: Load `VX` through `VY` from memory starting at `I` (XO-CHIP)
+    RegRangeLoad(Reg, Reg),
+    /// 6XNN: `VX = NN`
     LoadByte(Reg, u8),
+    /// 7XNN: `VX += NN`, without touching the carry flag
     AddByte(Reg, u8),
+    /// 8XY0: `VX = VY`
     LoadReg(Reg, Reg),
+    /// 8XY1: `VX |= VY`
     BitOr(Reg, Reg),
+    /// 8XY2: `VX &= VY`
     BitAnd(Reg, Reg),
+    /// 8XY3: `VX ^= VY`
     BitXor(Reg, Reg),
+    /// 8XY4: `VX += VY`, `VF` is set on carry
     MathAdd(Reg, Reg),
+    /// 8XY5: `VX -= VY`, `VF` is cleared on borrow
     MathSub(Reg, Reg),
+    /// 8XY6: Shift right by one, `VF` gets the bit shifted out
     ShiftRight(Reg, Reg),
+    /// 8XY7: `VX = VY - VX`, `VF` is cleared on borrow
     MathSubN(Reg, Reg),
+    /// 8XYE: Shift left by one, `VF` gets the bit shifted out
     ShiftLeft(Reg, Reg),
+    /// 9XY0: Skip the next instruction if `VX != VY`
     SkipNEqReg(Reg, Reg),
+    /// ANNN: `I = NNN`
     MemLoad(MemAddr),
+    /// BNNN: Jump to `NNN + V0`
     JumpAddV0(MemAddr),
+    /// CXNN: `VX = random & NN`
     Rand(Reg, u8),
+    /// DXYN: Draw an N row sprite from `I` at `(VX, VY)`, `VF` is set on collision
+    ///
+    /// On SUPER-CHIP, N = 0 draws a 16x16 sprite.
     Draw(Reg, Reg, u8),
+    /// EX9E: Skip the next instruction if the key in `VX` is held
     KeyEqSkip(Reg),
+    /// EXA1: Skip the next instruction if the key in `VX` is not held
     KeyNEqSkip(Reg),
+    /// F000 NNNN: `I = NNNN`, this is the only 4 byte instruction (XO-CHIP)
+    LongMemLoad(MemAddr),
+    /// FN01: Select which bitplanes to draw, clear and scroll (XO-CHIP)
+    PlaneSelect(u8),
+    /// F002: Load the 16 byte audio pattern from `I` (XO-CHIP)
+    AudioLoad,
+    /// FX07: `VX = delay timer`
     DelayGet(Reg),
+    /// FX0A: Wait for a key press, and store it in `VX`
     KeyGet(Reg),
+    /// FX15: `delay timer = VX`
     DelaySet(Reg),
+    /// FX18: `sound timer = VX`
     SoundSet(Reg),
+    /// FX1E: `I += VX`
     MemAdd(Reg),
+    /// FX29: Point `I` at the font sprite for the digit in `VX`
     MemSprite(Reg),
+    /// FX30: Point `I` at the large font sprite for the digit in `VX` (SUPER-CHIP)
+    BigSprite(Reg),
+    /// FX3A: Set the audio pattern playback pitch to `VX` (XO-CHIP)
+    Pitch(Reg),
+    /// FX33: Store the decimal digits of `VX` at `I`, `I+1` and `I+2`
     BCD(Reg),
+    /// FX55: Store `V0` through `VX` in memory starting at `I`
     RegDump(Reg),
+    /// FX65: Load `V0` through `VX` from memory starting at `I`
     RegLoad(Reg),
+    /// FX75: Save `V0` through `VX` to the RPL user flags (SUPER-CHIP)
+    FlagSave(Reg),
+    /// FX85: Load `V0` through `VX` from the RPL user flags (SUPER-CHIP)
+    FlagLoad(Reg),
 }
 
+impl Opcode {
+    /// The first machine to support this instruction
+    pub fn machine(&self) -> Machine {
+        match *self {
+            Opcode::ScrollDown(_) |
+            Opcode::ScrollRight |
+            Opcode::ScrollLeft |
+            Opcode::Exit |
+            Opcode::LowRes |
+            Opcode::HighRes |
+            Opcode::BigSprite(_) |
+            Opcode::FlagSave(_) |
+            Opcode::FlagLoad(_) => Machine::SuperChip,
+            Opcode::ScrollUp(_) |
+            Opcode::RegRangeSave(..) |
+            Opcode::RegRangeLoad(..) |
+            Opcode::LongMemLoad(_) |
+            Opcode::PlaneSelect(_) |
+            Opcode::AudioLoad |
+            Opcode::Pitch(_) => Machine::XoChip,
+            _ => Machine::Chip8,
+        }
+    }
+
+    /// The name of this variant, like `KeyGet`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Opcode::ClearScreen => "ClearScreen",
+            Opcode::Return => "Return",
+            Opcode::ScrollDown(_) => "ScrollDown",
+            Opcode::ScrollUp(_) => "ScrollUp",
+            Opcode::ScrollRight => "ScrollRight",
+            Opcode::ScrollLeft => "ScrollLeft",
+            Opcode::Exit => "Exit",
+            Opcode::LowRes => "LowRes",
+            Opcode::HighRes => "HighRes",
+            Opcode::SysAddr(_) => "SysAddr",
+            Opcode::JumpAddr(_) => "JumpAddr",
+            Opcode::CallAddr(_) => "CallAddr",
+            Opcode::SkipEqByte(..) => "SkipEqByte",
+            Opcode::SkipNEqByte(..) => "SkipNEqByte",
+            Opcode::SkipEqReg(..) => "SkipEqReg",
+            Opcode::RegRangeSave(..) => "RegRangeSave",
+            Opcode::RegRangeLoad(..) => "RegRangeLoad",
+            Opcode::LoadByte(..) => "LoadByte",
+            Opcode::AddByte(..) => "AddByte",
+            Opcode::LoadReg(..) => "LoadReg",
+            Opcode::BitOr(..) => "BitOr",
+            Opcode::BitAnd(..) => "BitAnd",
+            Opcode::BitXor(..) => "BitXor",
+            Opcode::MathAdd(..) => "MathAdd",
+            Opcode::MathSub(..) => "MathSub",
+            Opcode::ShiftRight(..) => "ShiftRight",
+            Opcode::MathSubN(..) => "MathSubN",
+            Opcode::ShiftLeft(..) => "ShiftLeft",
+            Opcode::SkipNEqReg(..) => "SkipNEqReg",
+            Opcode::MemLoad(_) => "MemLoad",
+            Opcode::JumpAddV0(_) => "JumpAddV0",
+            Opcode::Rand(..) => "Rand",
+            Opcode::Draw(..) => "Draw",
+            Opcode::KeyEqSkip(_) => "KeyEqSkip",
+            Opcode::KeyNEqSkip(_) => "KeyNEqSkip",
+            Opcode::LongMemLoad(_) => "LongMemLoad",
+            Opcode::PlaneSelect(_) => "PlaneSelect",
+            Opcode::AudioLoad => "AudioLoad",
+            Opcode::DelayGet(_) => "DelayGet",
+            Opcode::KeyGet(_) => "KeyGet",
+            Opcode::DelaySet(_) => "DelaySet",
+            Opcode::SoundSet(_) => "SoundSet",
+            Opcode::MemAdd(_) => "MemAdd",
+            Opcode::MemSprite(_) => "MemSprite",
+            Opcode::BigSprite(_) => "BigSprite",
+            Opcode::Pitch(_) => "Pitch",
+            Opcode::BCD(_) => "BCD",
+            Opcode::RegDump(_) => "RegDump",
+            Opcode::RegLoad(_) => "RegLoad",
+            Opcode::FlagSave(_) => "FlagSave",
+            Opcode::FlagLoad(_) => "FlagLoad",
+        }
+    }
+
+    /// How many bytes this instruction takes up in memory
+    #[allow(clippy::len_without_is_empty)] // never empty
+    pub fn len(&self) -> u16 {
+        match *self {
+            Opcode::LongMemLoad(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// The bytes this instruction is stored as, the inverse of `decode`
+    pub fn encode(&self) -> Vec<u8> {
+        let xy = |op: u16, x: Reg, y: Reg, n: u16| op << 12 | (x as u16) << 8 | (y as u16) << 4 | n;
+        let xnn = |op: u16, x: Reg, nn: u8| op << 12 | (x as u16) << 8 | nn as u16;
+        let fx = |x: Reg, nn: u16| 0xF000 | (x as u16) << 8 | nn;
+        let instr = match *self {
+            Opcode::ClearScreen => 0x00E0,
+            Opcode::Return => 0x00EE,
+            Opcode::ScrollDown(n) => 0x00C0 | (n & 0xF) as u16,
+            Opcode::ScrollUp(n) => 0x00D0 | (n & 0xF) as u16,
+            Opcode::ScrollRight => 0x00FB,
+            Opcode::ScrollLeft => 0x00FC,
+            Opcode::Exit => 0x00FD,
+            Opcode::LowRes => 0x00FE,
+            Opcode::HighRes => 0x00FF,
+            Opcode::SysAddr(addr) => addr & 0xFFF,
+            Opcode::JumpAddr(addr) => 0x1000 | addr & 0xFFF,
+            Opcode::CallAddr(addr) => 0x2000 | addr & 0xFFF,
+            Opcode::SkipEqByte(x, byte) => xnn(0x3, x, byte),
+            Opcode::SkipNEqByte(x, byte) => xnn(0x4, x, byte),
+            Opcode::SkipEqReg(x, y) => xy(0x5, x, y, 0x0),
+            Opcode::RegRangeSave(x, y) => xy(0x5, x, y, 0x2),
+            Opcode::RegRangeLoad(x, y) => xy(0x5, x, y, 0x3),
+            Opcode::LoadByte(x, byte) => xnn(0x6, x, byte),
+            Opcode::AddByte(x, byte) => xnn(0x7, x, byte),
+            Opcode::LoadReg(x, y) => xy(0x8, x, y, 0x0),
+            Opcode::BitOr(x, y) => xy(0x8, x, y, 0x1),
+            Opcode::BitAnd(x, y) => xy(0x8, x, y, 0x2),
+            Opcode::BitXor(x, y) => xy(0x8, x, y, 0x3),
+            Opcode::MathAdd(x, y) => xy(0x8, x, y, 0x4),
+            Opcode::MathSub(x, y) => xy(0x8, x, y, 0x5),
+            Opcode::ShiftRight(x, y) => xy(0x8, x, y, 0x6),
+            Opcode::MathSubN(x, y) => xy(0x8, x, y, 0x7),
+            Opcode::ShiftLeft(x, y) => xy(0x8, x, y, 0xE),
+            Opcode::SkipNEqReg(x, y) => xy(0x9, x, y, 0x0),
+            Opcode::MemLoad(addr) => 0xA000 | addr & 0xFFF,
+            Opcode::JumpAddV0(addr) => 0xB000 | addr & 0xFFF,
+            Opcode::Rand(x, byte) => xnn(0xC, x, byte),
+            Opcode::Draw(x, y, n) => xy(0xD, x, y, (n & 0xF) as u16),
+            Opcode::KeyEqSkip(x) => xnn(0xE, x, 0x9E),
+            Opcode::KeyNEqSkip(x) => xnn(0xE, x, 0xA1),
+            Opcode::LongMemLoad(addr) => {
+                return vec![(LONG_MEM_LOAD >> 8) as u8, LONG_MEM_LOAD as u8, (addr >> 8) as u8,
+                            addr as u8]
+            }
+            Opcode::PlaneSelect(n) => 0xF001 | ((n & 0xF) as u16) << 8,
+            Opcode::AudioLoad => 0xF002,
+            Opcode::DelayGet(x) => fx(x, 0x07),
+            Opcode::KeyGet(x) => fx(x, 0x0A),
+            Opcode::DelaySet(x) => fx(x, 0x15),
+            Opcode::SoundSet(x) => fx(x, 0x18),
+            Opcode::MemAdd(x) => fx(x, 0x1E),
+            Opcode::MemSprite(x) => fx(x, 0x29),
+            Opcode::BigSprite(x) => fx(x, 0x30),
+            Opcode::BCD(x) => fx(x, 0x33),
+            Opcode::Pitch(x) => fx(x, 0x3A),
+            Opcode::RegDump(x) => fx(x, 0x55),
+            Opcode::RegLoad(x) => fx(x, 0x65),
+            Opcode::FlagSave(x) => fx(x, 0x75),
+            Opcode::FlagLoad(x) => fx(x, 0x85),
+        };
+        vec![(instr >> 8) as u8, instr as u8]
+    }
+}
+
+/// The first halfword of `LongMemLoad`, the address is in the halfword after it
+pub const LONG_MEM_LOAD: u16 = 0xF000;
+
 #[inline]
 fn halfword2nibbles(x: u16) -> (u8, u8, u8, u8) {
     let a = ((x >> 0xC) & 0x0F) as u8;
@@ -70,49 +318,93 @@ fn nibble2byte(a: u8, b: u8) -> u8 {
 #[inline]
 fn nibbles2addr(a: u8, b: u8, c: u8) -> MemAddr {
     let mut x = a as MemAddr;
-    x = x << 8;
-    x = x | nibble2byte(b, c) as MemAddr;
+    x <<= 8;
+    x |= nibble2byte(b, c) as MemAddr;
     x
 }
 
+/// The variant called `name`, or written with the mnemonic `name` when that is
+/// the only variant written with it, ignoring case
+pub fn variant(name: &str) -> Option<&'static str> {
+    let named = VARIANTS.iter().find(|&&(variant, _)| variant.eq_ignore_ascii_case(name));
+    if let Some(&(variant, _)) = named {
+        return Some(variant);
+    }
+    let mut written = VARIANTS.iter().filter(|&&(_, mnemonic)| mnemonic.eq_ignore_ascii_case(name));
+    match (written.next(), written.next()) {
+        (Some(&(variant, _)), None) => Some(variant),
+        _ => None,
+    }
+}
+
+/// Decode an instruction, `next` is the halfword following it in memory
+///
+/// `next` is only used by the 4 byte `LongMemLoad`.
+pub fn decode(instr: u16, next: u16) -> Result<Opcode> {
+    if instr == LONG_MEM_LOAD {
+        Ok(Opcode::LongMemLoad(next))
+    } else {
+        disassemble(instr)
+    }
+}
+
+/// Decode a 2 byte instruction
+///
+/// `LONG_MEM_LOAD` is not recognized here, as its address is missing, use
+/// `decode` instead.
 pub fn disassemble(instr: u16) -> Result<Opcode> {
 
     match halfword2nibbles(instr) {
         (0x0, 0, 0xE, 0) => Ok(Opcode::ClearScreen),
         (0x0, 0, 0xE, 0xE) => Ok(Opcode::Return),
+        (0x0, 0, 0xC, n) => Ok(Opcode::ScrollDown(n)),
+        (0x0, 0, 0xD, n) => Ok(Opcode::ScrollUp(n)),
+        (0x0, 0, 0xF, 0xB) => Ok(Opcode::ScrollRight),
+        (0x0, 0, 0xF, 0xC) => Ok(Opcode::ScrollLeft),
+        (0x0, 0, 0xF, 0xD) => Ok(Opcode::Exit),
+        (0x0, 0, 0xF, 0xE) => Ok(Opcode::LowRes),
+        (0x0, 0, 0xF, 0xF) => Ok(Opcode::HighRes),
         (0x0, n1, n2, n3) => Ok(Opcode::SysAddr(nibbles2addr(n1, n2, n3))),
         (0x1, n1, n2, n3) => Ok(Opcode::JumpAddr(nibbles2addr(n1, n2, n3))),
         (0x2, n1, n2, n3) => Ok(Opcode::CallAddr(nibbles2addr(n1, n2, n3))),
-        (0x3, x, n1, n2) => Ok(Opcode::SkipEqByte(reg(x), nibble2byte(n1, n2))),
-        (0x4, x, n1, n2) => Ok(Opcode::SkipNEqByte(reg(x), nibble2byte(n1, n2))),
-        (0x5, x, y, 0) => Ok(Opcode::SkipEqReg(reg(x), reg(y))),
-        (0x6, x, n1, n2) => Ok(Opcode::LoadByte(reg(x), nibble2byte(n1, n2))),
-        (0x7, x, n1, n2) => Ok(Opcode::AddByte(reg(x), nibble2byte(n1, n2))),
-        (0x8, x, y, 0) => Ok(Opcode::LoadReg(reg(x), reg(y))),
-        (0x8, x, y, 1) => Ok(Opcode::BitOr(reg(x), reg(y))),
-        (0x8, x, y, 2) => Ok(Opcode::BitAnd(reg(x), reg(y))),
-        (0x8, x, y, 3) => Ok(Opcode::BitXor(reg(x), reg(y))),
-        (0x8, x, y, 4) => Ok(Opcode::MathAdd(reg(x), reg(y))),
-        (0x8, x, y, 5) => Ok(Opcode::MathSub(reg(x), reg(y))),
-        (0x8, x, y, 6) => Ok(Opcode::ShiftRight(reg(x), reg(y))),
-        (0x8, x, y, 7) => Ok(Opcode::MathSubN(reg(x), reg(y))),
-        (0x8, x, y, 0xE) => Ok(Opcode::ShiftLeft(reg(x), reg(y))),
-        (0x9, x, y, 0) => Ok(Opcode::SkipNEqReg(reg(x), reg(y))),
+        (0x3, x, n1, n2) => Ok(Opcode::SkipEqByte(reg(x)?, nibble2byte(n1, n2))),
+        (0x4, x, n1, n2) => Ok(Opcode::SkipNEqByte(reg(x)?, nibble2byte(n1, n2))),
+        (0x5, x, y, 0) => Ok(Opcode::SkipEqReg(reg(x)?, reg(y)?)),
+        (0x5, x, y, 2) => Ok(Opcode::RegRangeSave(reg(x)?, reg(y)?)),
+        (0x5, x, y, 3) => Ok(Opcode::RegRangeLoad(reg(x)?, reg(y)?)),
+        (0x6, x, n1, n2) => Ok(Opcode::LoadByte(reg(x)?, nibble2byte(n1, n2))),
+        (0x7, x, n1, n2) => Ok(Opcode::AddByte(reg(x)?, nibble2byte(n1, n2))),
+        (0x8, x, y, 0) => Ok(Opcode::LoadReg(reg(x)?, reg(y)?)),
+        (0x8, x, y, 1) => Ok(Opcode::BitOr(reg(x)?, reg(y)?)),
+        (0x8, x, y, 2) => Ok(Opcode::BitAnd(reg(x)?, reg(y)?)),
+        (0x8, x, y, 3) => Ok(Opcode::BitXor(reg(x)?, reg(y)?)),
+        (0x8, x, y, 4) => Ok(Opcode::MathAdd(reg(x)?, reg(y)?)),
+        (0x8, x, y, 5) => Ok(Opcode::MathSub(reg(x)?, reg(y)?)),
+        (0x8, x, y, 6) => Ok(Opcode::ShiftRight(reg(x)?, reg(y)?)),
+        (0x8, x, y, 7) => Ok(Opcode::MathSubN(reg(x)?, reg(y)?)),
+        (0x8, x, y, 0xE) => Ok(Opcode::ShiftLeft(reg(x)?, reg(y)?)),
+        (0x9, x, y, 0) => Ok(Opcode::SkipNEqReg(reg(x)?, reg(y)?)),
         (0xA, n1, n2, n3) => Ok(Opcode::MemLoad(nibbles2addr(n1, n2, n3))),
         (0xB, n1, n2, n3) => Ok(Opcode::JumpAddV0(nibbles2addr(n1, n2, n3))),
-        (0xC, x, n1, n2) => Ok(Opcode::Rand(reg(x), nibble2byte(n1, n2))),
-        (0xD, x, y, n1) => Ok(Opcode::Draw(reg(x), reg(y), n1)),
-        (0xE, x, 0x9, 0xE) => Ok(Opcode::KeyEqSkip(reg(x))),
-        (0xE, x, 0xA, 0x1) => Ok(Opcode::KeyNEqSkip(reg(x))),
-        (0xF, x, 0x0, 0x7) => Ok(Opcode::DelayGet(reg(x))),
-        (0xF, x, 0x0, 0xA) => Ok(Opcode::KeyGet(reg(x))),
-        (0xF, x, 0x1, 0x5) => Ok(Opcode::DelaySet(reg(x))),
-        (0xF, x, 0x1, 0x8) => Ok(Opcode::SoundSet(reg(x))),
-        (0xF, x, 0x1, 0xE) => Ok(Opcode::MemAdd(reg(x))),
-        (0xF, x, 0x2, 0x9) => Ok(Opcode::MemSprite(reg(x))),
-        (0xF, x, 0x3, 0x3) => Ok(Opcode::BCD(reg(x))),
-        (0xF, x, 0x5, 0x5) => Ok(Opcode::RegDump(reg(x))),
-        (0xF, x, 0x6, 0x5) => Ok(Opcode::RegLoad(reg(x))),
+        (0xC, x, n1, n2) => Ok(Opcode::Rand(reg(x)?, nibble2byte(n1, n2))),
+        (0xD, x, y, n1) => Ok(Opcode::Draw(reg(x)?, reg(y)?, n1)),
+        (0xE, x, 0x9, 0xE) => Ok(Opcode::KeyEqSkip(reg(x)?)),
+        (0xE, x, 0xA, 0x1) => Ok(Opcode::KeyNEqSkip(reg(x)?)),
+        (0xF, n, 0x0, 0x1) => Ok(Opcode::PlaneSelect(n)),
+        (0xF, 0, 0x0, 0x2) => Ok(Opcode::AudioLoad),
+        (0xF, x, 0x0, 0x7) => Ok(Opcode::DelayGet(reg(x)?)),
+        (0xF, x, 0x0, 0xA) => Ok(Opcode::KeyGet(reg(x)?)),
+        (0xF, x, 0x1, 0x5) => Ok(Opcode::DelaySet(reg(x)?)),
+        (0xF, x, 0x1, 0x8) => Ok(Opcode::SoundSet(reg(x)?)),
+        (0xF, x, 0x1, 0xE) => Ok(Opcode::MemAdd(reg(x)?)),
+        (0xF, x, 0x2, 0x9) => Ok(Opcode::MemSprite(reg(x)?)),
+        (0xF, x, 0x3, 0x0) => Ok(Opcode::BigSprite(reg(x)?)),
+        (0xF, x, 0x3, 0x3) => Ok(Opcode::BCD(reg(x)?)),
+        (0xF, x, 0x3, 0xA) => Ok(Opcode::Pitch(reg(x)?)),
+        (0xF, x, 0x5, 0x5) => Ok(Opcode::RegDump(reg(x)?)),
+        (0xF, x, 0x6, 0x5) => Ok(Opcode::RegLoad(reg(x)?)),
+        (0xF, x, 0x7, 0x5) => Ok(Opcode::FlagSave(reg(x)?)),
+        (0xF, x, 0x8, 0x5) => Ok(Opcode::FlagLoad(reg(x)?)),
         _ => bail!(ErrorKind::UnrecognizedOpcode(instr)),
     }
 }
@@ -121,12 +413,21 @@ impl fmt::Display for Opcode {
         match self {
             &Opcode::ClearScreen => write!(f, "CLS"),
             &Opcode::Return => write!(f, "RETURN"),
+            &Opcode::ScrollDown(n) => write!(f, "SCROLL.DOWN 0x{:02x}", n),
+            &Opcode::ScrollUp(n) => write!(f, "SCROLL.UP 0x{:02x}", n),
+            &Opcode::ScrollRight => write!(f, "SCROLL.RIGHT"),
+            &Opcode::ScrollLeft => write!(f, "SCROLL.LEFT"),
+            &Opcode::Exit => write!(f, "EXIT"),
+            &Opcode::LowRes => write!(f, "LORES"),
+            &Opcode::HighRes => write!(f, "HIRES"),
             &Opcode::SysAddr(addr) => write!(f, "SYSADDR 0x{:04x}", addr),
             &Opcode::JumpAddr(addr) => write!(f, "JUMP 0x{:04x}", addr),
             &Opcode::CallAddr(addr) => write!(f, "CALL 0x{:04x}", addr),
             &Opcode::SkipEqByte(x, byte) => write!(f, "SKIP.EQ {} 0x{:02x}", x, byte),
             &Opcode::SkipNEqByte(x, byte) => write!(f, "SKIP.NEQ {} 0x{:02x}", x, byte),
             &Opcode::SkipEqReg(x, y) => write!(f, "SKIP.EQ {} {}", x, y),
+            &Opcode::RegRangeSave(x, y) => write!(f, "REG.SAVE {} {}", x, y),
+            &Opcode::RegRangeLoad(x, y) => write!(f, "REG.RESTORE {} {}", x, y),
             &Opcode::LoadByte(x, byte) => write!(f, "LOAD {} 0x{:02x}", x, byte),
             &Opcode::AddByte(x, byte) => write!(f, "MATH.ADD {} 0x{:02x}", x, byte),
             &Opcode::LoadReg(x, y) => write!(f, "LOAD {} {}", x, y),
@@ -145,15 +446,22 @@ impl fmt::Display for Opcode {
             &Opcode::Draw(x, y, byte) => write!(f, "DRAW {} {} 0x{:02x}", x, y, byte),
             &Opcode::KeyEqSkip(x) => write!(f, "SKIP.KEY {}", x),
             &Opcode::KeyNEqSkip(x) => write!(f, "SKIP.NKEY {}", x),
+            &Opcode::LongMemLoad(addr) => write!(f, "LOAD.LONG VI 0x{:04x}", addr),
+            &Opcode::PlaneSelect(n) => write!(f, "PLANE 0x{:02x}", n),
+            &Opcode::AudioLoad => write!(f, "AUDIO"),
             &Opcode::DelayGet(x) => write!(f, "DELAY.GET {}", x),
             &Opcode::KeyGet(x) => write!(f, "KEY.GET {}", x),
             &Opcode::DelaySet(x) => write!(f, "DELAY.SET {}", x),
             &Opcode::SoundSet(x) => write!(f, "SOUND.SET {}", x),
             &Opcode::MemAdd(x) => write!(f, "MATH.ADD VI {}", x),
             &Opcode::MemSprite(x) => write!(f, "SPRITE {}", x),
+            &Opcode::BigSprite(x) => write!(f, "SPRITE.BIG {}", x),
             &Opcode::BCD(x) => write!(f, "BCD {}", x),
+            &Opcode::Pitch(x) => write!(f, "PITCH {}", x),
             &Opcode::RegDump(x) => write!(f, "REG.DUMP {}", x),
             &Opcode::RegLoad(x) => write!(f, "REG.LOAD {}", x),
+            &Opcode::FlagSave(x) => write!(f, "FLAGS.SAVE {}", x),
+            &Opcode::FlagLoad(x) => write!(f, "FLAGS.LOAD {}", x),
         }
     }
 }
@@ -201,3 +509,27 @@ fn addr_from_nibbles() {
     assert_eq!(nibbles2addr(0xF, 0xF, 0xF), 0x0FFF);
 
 }
+
+#[test]
+fn decode_long_load() {
+    assert!(disassemble(LONG_MEM_LOAD).is_err());
+    let opcode = decode(LONG_MEM_LOAD, 0x1234).unwrap();
+    assert_eq!(opcode, Opcode::LongMemLoad(0x1234));
+    assert_eq!(opcode.len(), 4);
+    assert_eq!(decode(0xf301, 0xffff).unwrap(), Opcode::PlaneSelect(3));
+    assert_eq!(decode(0x5ab3, 0xffff).unwrap(), Opcode::RegRangeLoad(Reg::VA, Reg::VB));
+}
+
+#[test]
+fn encode_every_instruction() {
+    for instr in 0..0x10000u32 {
+        let instr = instr as u16;
+        if let Ok(opcode) = decode(instr, 0x1234) {
+            let mut bytes = vec![(instr >> 8) as u8, instr as u8];
+            if opcode.len() == 4 {
+                bytes.extend_from_slice(&[0x12, 0x34]);
+            }
+            assert_eq!(opcode.encode(), bytes, "{}", opcode);
+        }
+    }
+}
@@ -10,10 +10,12 @@ use std::fmt;
 //
 // This Crate Imports
 //
-//use errors::*;
+use errors::*;
 
 
-#[derive(Debug, Clone, Copy)]
+/// One of the 16 general purpose registers
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(missing_docs)]
 pub enum Reg {
     V0 = 0x0,
     V1 = 0x1,
@@ -39,8 +41,8 @@ impl fmt::Display for Reg {
     }
 }
 
-pub fn reg(x: u8) -> Reg {
-    match x {
+pub fn reg(x: u8) -> Result<Reg> {
+    Ok(match x {
         0x00 => Reg::V0,
         0x01 => Reg::V1,
         0x02 => Reg::V2,
@@ -57,6 +59,12 @@ pub fn reg(x: u8) -> Reg {
         0x0D => Reg::VD,
         0x0E => Reg::VE,
         0x0F => Reg::VF,
-        _ => panic!("Register {:02x} not defined!", x),
-    }
+        _ => bail!(ErrorKind::InvalidRegister(x)),
+    })
+}
+
+#[test]
+fn invalid_register() {
+    assert_eq!(reg(0xa).unwrap(), Reg::VA);
+    assert!(reg(0x10).is_err());
 }
@@ -0,0 +1,74 @@
+//
+// Rust Core Imports
+//
+
+//
+// Third Party Imports
+//
+
+//
+// This Crate Imports
+//
+
+/// A small xorshift64* generator for the `Rand` opcode
+///
+/// The whole state is a single `u64`, so runs can be reproduced from a seed,
+/// and the generator can be saved and restored along with the rest of the
+/// machine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rng {
+    state: u64,
+}
+
+// splitmix64, to spread out small seeds like 0, 1, 2
+fn mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng::from_state(mix(seed))
+    }
+
+    /// Resume a generator from a value returned by `state`
+    pub fn from_state(state: u64) -> Self {
+        // xorshift gets stuck at zero
+        Rng { state: if state == 0 { 0x9E3779B97F4A7C15 } else { state } }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 56) as u8
+    }
+}
+
+#[test]
+fn rng_is_repeatable() {
+    let mut a = Rng::new(1234);
+    let mut b = Rng::new(1234);
+    let run_a: Vec<u8> = (0..64).map(|_| a.next_u8()).collect();
+    let run_b: Vec<u8> = (0..64).map(|_| b.next_u8()).collect();
+    assert_eq!(run_a, run_b);
+
+    let mut c = Rng::new(1235);
+    let run_c: Vec<u8> = (0..64).map(|_| c.next_u8()).collect();
+    assert!(run_a != run_c);
+
+    let mut d = Rng::from_state(a.state());
+    assert_eq!(a.next_u8(), d.next_u8());
+}
+
+#[test]
+fn rng_zero_seed() {
+    let mut rng = Rng::from_state(0);
+    assert!((0..16).map(|_| rng.next_u8()).any(|x| x != 0));
+}
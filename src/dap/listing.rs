@@ -0,0 +1,89 @@
+//
+// Rust Core Imports
+//
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use emulator::MemAddr;
+
+/// Where each instruction of a ROM came from, read from an assembler listing
+///
+/// Each line of a listing starts with an address and a `file:line`, e.g.
+/// `0x0200 pong.asm:12  60 07  LOAD V0 0x07`. The rest of the line is ignored,
+/// as are lines that don't start with an address.
+#[derive(Debug, Default, Clone)]
+pub struct SourceMap {
+    lines: BTreeMap<MemAddr, (String, u64)>,
+}
+
+impl SourceMap {
+    /// Parse a listing, with source files relative to `dir`
+    pub fn parse(listing: &str, dir: &Path) -> Self {
+        let mut lines = BTreeMap::new();
+        for line in listing.lines() {
+            let mut fields = line.split_whitespace();
+            let addr = fields.next()
+                .and_then(|f| f.strip_prefix("0x"))
+                .and_then(|f| u16::from_str_radix(f, 16).ok());
+            let location = fields.next().and_then(|f| {
+                let split = f.rfind(':')?;
+                Some((&f[..split], f[split + 1..].parse::<u64>().ok()?))
+            });
+            if let (Some(addr), Some((file, line))) = (addr, location) {
+                let path = dir.join(file).to_string_lossy().into_owned();
+                lines.insert(addr, (path, line));
+            }
+        }
+        SourceMap { lines: lines }
+    }
+
+    /// Read the listing at `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut listing = String::new();
+        File::open(path)?.read_to_string(&mut listing)?;
+        Ok(SourceMap::parse(&listing, path.parent().unwrap_or(Path::new(""))))
+    }
+
+    /// The file and line that `addr` was assembled from
+    pub fn location(&self, addr: MemAddr) -> Option<(&str, u64)> {
+        self.lines.get(&addr).map(|&(ref file, line)| (file.as_str(), line))
+    }
+
+    /// The first instruction on or after `line` of `file`, and its actual line
+    ///
+    /// `file` matches if either path ends with the other, so an editor's
+    /// absolute path finds a listing's relative one.
+    pub fn address(&self, file: &str, line: u64) -> Option<(MemAddr, u64)> {
+        let wanted = Path::new(file);
+        self.lines
+            .iter()
+            .filter(|&(_, &(ref path, l))| {
+                let path = Path::new(path);
+                l >= line && (wanted.ends_with(path) || path.ends_with(wanted))
+            })
+            .min_by_key(|&(&addr, &(_, l))| (l, addr))
+            .map(|(&addr, &(_, l))| (addr, l))
+    }
+}
+
+#[test]
+fn source_map() {
+    let listing = "; a comment\n\
+                   0x0200 game.asm:1  60 07  LOAD V0 0x07\n\
+                   0x0202 game.asm:3  22 06  CALL 0x0206\n\
+                   data:\n\
+                   0x0206 lib.asm:2   00 ee  RETURN\n";
+    let map = SourceMap::parse(listing, Path::new("src"));
+    assert_eq!(map.location(0x202), Some(("src/game.asm", 3)));
+    assert_eq!(map.location(0x204), None);
+    assert_eq!(map.address("/home/me/src/game.asm", 2), Some((0x202, 3)));
+    assert_eq!(map.address("game.asm", 1), Some((0x200, 1)));
+    assert_eq!(map.address("/home/me/src/game.asm", 4), None);
+    assert_eq!(map.address("src/lib.asm", 1), Some((0x206, 2)));
+}
@@ -0,0 +1,94 @@
+//
+// Rust Core Imports
+//
+use std::io::{BufRead, Write};
+
+//
+// Third Party Imports
+//
+use serde_json::{self, Value};
+
+//
+// This Crate Imports
+//
+use errors::*;
+
+const BASE64: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Read one `Content-Length` framed message, `None` at the end of the input
+pub fn read_message<R: BufRead>(input: &mut R) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            // The blank line ends the headers
+            match length {
+                Some(_) => break,
+                None => continue,
+            }
+        }
+        let mut header = line.splitn(2, ':');
+        let name = header.next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("Content-Length") {
+            let value = header.next().unwrap_or("").trim();
+            length = Some(value.parse::<usize>().map_err(|_| {
+                    ErrorKind::BadDapMessage(format!("Content-Length '{}'", value))
+                })?);
+        }
+    }
+    let mut body = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// Write `message` with its `Content-Length` header
+pub fn write_message<W: Write>(out: &mut W, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Encode memory for a `readMemory` response
+pub fn base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn message_round_trip() {
+    use std::io::Cursor;
+    let mut buf = Vec::new();
+    write_message(&mut buf, &json!({"seq": 1, "command": "threads"})).unwrap();
+    write_message(&mut buf, &json!({"seq": 2, "command": "pause"})).unwrap();
+    let mut input = Cursor::new(buf);
+    assert_eq!(read_message(&mut input).unwrap().unwrap()["command"], "threads");
+    assert_eq!(read_message(&mut input).unwrap().unwrap()["seq"], 2);
+    assert!(read_message(&mut input).unwrap().is_none());
+
+    let mut bad = Cursor::new(&b"Content-Length: lots\r\n\r\n{}"[..]);
+    assert!(read_message(&mut bad).is_err());
+}
+
+#[test]
+fn base64_padding() {
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"M"), "TQ==");
+    assert_eq!(base64(b"Ma"), "TWE=");
+    assert_eq!(base64(b"Man"), "TWFu");
+    assert_eq!(base64(&[0xff, 0x00, 0xee, 0x12]), "/wDuEg==");
+}
@@ -0,0 +1,788 @@
+//
+// Rust Core Imports
+//
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+//
+// Third Party Imports
+//
+use serde_json::Value;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use cpu::{Opcode, reg};
+use debugger::{Breakpoint, Expr};
+use emulator::{Chip8, FramePacer, MemAddr, FRAME_NS};
+use frontend::{VideoSink, InputSource, AudioSink};
+
+//
+// Declare sub modules
+//
+mod listing;
+mod message;
+
+use self::listing::SourceMap;
+use self::message::{read_message, write_message, base64};
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REF: u64 = 1;
+const TIMERS_REF: u64 = 2;
+const REGISTER_NAMES: [&'static str; 16] = ["V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7", "V8",
+                                            "V9", "VA", "VB", "VC", "VD", "VE", "VF"];
+
+/// Lets an editor debug a ROM over the Debug Adapter Protocol
+///
+/// There is one thread, and one stack frame for each subroutine being run.
+/// Registers and timers are variables, and can be set to any debugger
+/// expression. With an assembler listing, breakpoints can be set on source
+/// lines and stack frames show where they are in the source; without one,
+/// the editor can still use instruction breakpoints and the disassembly.
+///
+/// The `launch` and `attach` requests take an optional `listing` path, and a
+/// `stopOnEntry` flag that defaults to `true`.
+pub struct DebugAdapter {
+    source_map: SourceMap,
+}
+
+impl Default for DebugAdapter {
+    fn default() -> Self {
+        DebugAdapter::new()
+    }
+}
+
+impl DebugAdapter {
+    /// An adapter with no source lines
+    pub fn new() -> Self {
+        DebugAdapter { source_map: SourceMap::default() }
+    }
+
+    /// Map addresses to source lines using the assembler listing at `path`
+    pub fn load_listing(&mut self, path: &Path) -> Result<()> {
+        self.source_map = SourceMap::load(path)?;
+        Ok(())
+    }
+
+    /// Talk to an editor over stdin and stdout, until it disconnects
+    ///
+    /// Nothing else may write to stdout while this runs.
+    pub fn serve_stdio<F>(&mut self, chip8: &mut Chip8, frontend: &mut F) -> Result<()>
+        where F: VideoSink + InputSource + AudioSink
+    {
+        let input = spawn_reader(BufReader::new(io::stdin()));
+        let stdout = io::stdout();
+        let source_map = self.source_map.clone();
+        Session::new(chip8, frontend, input, stdout.lock(), source_map).run()
+    }
+
+    /// Wait for an editor to connect on `addr`, then talk to it until it disconnects
+    pub fn serve_tcp<A, F>(&mut self, addr: A, chip8: &mut Chip8, frontend: &mut F) -> Result<()>
+        where A: ToSocketAddrs,
+              F: VideoSink + InputSource + AudioSink
+    {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        let input = spawn_reader(BufReader::new(stream.try_clone()?));
+        let source_map = self.source_map.clone();
+        Session::new(chip8, frontend, input, stream, source_map).run()
+    }
+}
+
+// Messages are read on their own thread, so the editor can pause a running program
+fn spawn_reader<R: BufRead + Send + 'static>(mut input: R) -> Receiver<Value> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        while let Ok(Some(message)) = read_message(&mut input) {
+            if tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+fn failed(msg: &str) -> Error {
+    ErrorKind::DebuggerCommand(msg.to_string()).into()
+}
+
+// Parse an address like `0x0200` from a `memoryReference`, plus `offset`
+fn reference(value: &Value, offset: &Value) -> Result<i64> {
+    let text = value.as_str().ok_or_else(|| failed("Missing memory reference"))?;
+    let addr = if let Some(hex) = text.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else {
+        text.parse()
+    };
+    let addr = addr.map_err(|_| failed("Bad memory reference"))?;
+    addr.checked_add(offset.as_i64().unwrap_or(0)).ok_or_else(|| failed("Bad memory offset"))
+}
+
+// When a running program should pause again
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stop {
+    Step,
+    ReturnTo(MemAddr, usize),
+    Finish(usize),
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Paused,
+    Running(Stop),
+    Done,
+}
+
+struct Session<'a, F: 'a, W> {
+    chip8: &'a mut Chip8,
+    frontend: &'a mut F,
+    input: Receiver<Value>,
+    pending: VecDeque<Value>,
+    output: W,
+    seq: u64,
+    source_map: SourceMap,
+    state: State,
+    stop_on_entry: bool,
+    // Whether the program has moved since it was resumed, so it can be
+    // continued from a breakpoint
+    moved: bool,
+    pacer: FramePacer,
+    source_breakpoints: HashMap<String, Vec<usize>>,
+    instruction_breakpoints: Vec<usize>,
+    // Events to send once the current request has its response
+    events: Vec<(&'static str, Value)>,
+}
+
+impl<'a, F, W> Session<'a, F, W>
+    where F: VideoSink + InputSource + AudioSink,
+          W: Write
+{
+    fn new(chip8: &'a mut Chip8,
+           frontend: &'a mut F,
+           input: Receiver<Value>,
+           output: W,
+           source_map: SourceMap)
+           -> Self {
+        Session {
+            chip8: chip8,
+            frontend: frontend,
+            input: input,
+            pending: VecDeque::new(),
+            output: output,
+            seq: 0,
+            source_map: source_map,
+            state: State::Paused,
+            stop_on_entry: true,
+            moved: false,
+            pacer: FramePacer::new(),
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, mut message: Value) -> Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Result<()> {
+        self.send(json!({"type": "event", "event": event, "body": body}))
+    }
+
+    fn run(&mut self) -> Result<()> {
+        let frame = Duration::new(0, FRAME_NS);
+        while self.state != State::Done {
+            if !self.frontend.is_open() {
+                self.event("terminated", json!({}))?;
+                break;
+            }
+            if let State::Running(stop) = self.state {
+                // Only requests that make sense while running are answered
+                // now, the rest wait in order until the program stops
+                loop {
+                    match self.input.try_recv() {
+                        Ok(request) => {
+                            match request["command"].as_str().unwrap_or("") {
+                                "pause" | "disconnect" | "terminate" | "threads" |
+                                "setBreakpoints" |
+                                "setInstructionBreakpoints" if self.pending.is_empty() => {
+                                    self.request(&request)?
+                                }
+                                _ => self.pending.push_back(request),
+                            }
+                        }
+                        Err(TryRecvError::Empty) => break,
+                        // Finish what was already sent before giving up
+                        Err(TryRecvError::Disconnected) if self.pending.is_empty() => {
+                            return Ok(())
+                        }
+                        Err(TryRecvError::Disconnected) => break,
+                    }
+                }
+                if self.state == State::Running(stop) {
+                    self.run_frame(stop)?;
+                }
+                continue;
+            }
+            let request = match self.pending.pop_front() {
+                Some(request) => request,
+                None => {
+                    match self.input.recv_timeout(frame) {
+                        Ok(request) => request,
+                        Err(RecvTimeoutError::Timeout) => {
+                            self.chip8.present(self.frontend);
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            };
+            self.request(&request)?;
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, request: &Value) -> Result<()> {
+        if request["type"] != "request" {
+            return Ok(());
+        }
+        let command = request["command"].as_str().unwrap_or("");
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+        });
+        match self.handle(command, &request["arguments"]) {
+            Ok(body) => {
+                response["success"] = json!(true);
+                response["body"] = body;
+            }
+            Err(e) => {
+                response["success"] = json!(false);
+                response["message"] = json!(e.to_string());
+            }
+        }
+        self.send(response)?;
+        for (event, body) in self.events.drain(..).collect::<Vec<_>>() {
+            self.event(event, body)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, command: &str, args: &Value) -> Result<Value> {
+        match command {
+            "initialize" => {
+                self.events.push(("initialized", json!({})));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsConditionalBreakpoints": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsDisassembleRequest": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsSetVariable": true,
+                    "supportsEvaluateForHovers": true,
+                    "supportsTerminateRequest": true,
+                }))
+            }
+            "launch" | "attach" => {
+                if let Some(path) = args["listing"].as_str() {
+                    self.source_map = SourceMap::load(Path::new(path))?;
+                }
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(true);
+                Ok(json!({}))
+            }
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.events.push(("stopped", stopped("entry")));
+                } else {
+                    self.resume(Stop::Never);
+                }
+                Ok(json!({}))
+            }
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({"breakpoints": []})),
+            "threads" => Ok(json!({"threads": [{"id": THREAD_ID, "name": "CHIP-8"}]})),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => {
+                Ok(json!({"scopes": [
+                    {"name": "Registers", "variablesReference": REGISTERS_REF, "expensive": false},
+                    {"name": "Timers", "variablesReference": TIMERS_REF, "expensive": false},
+                ]}))
+            }
+            "variables" => Ok(json!({"variables": self.variables(args["variablesReference"].as_u64())})),
+            "setVariable" => self.set_variable(args),
+            "evaluate" => {
+                let expr = args["expression"].as_str().unwrap_or("").parse::<Expr>()?;
+                Ok(json!({"result": expr.eval(self.chip8).to_string(), "variablesReference": 0}))
+            }
+            "continue" => {
+                self.resume(Stop::Never);
+                Ok(json!({"allThreadsContinued": true}))
+            }
+            "next" => {
+                let pc = self.chip8.pc();
+                match self.chip8.instruction(pc)? {
+                    Opcode::CallAddr(_) => {
+                        let depth = self.chip8.stack().len();
+                        self.resume(Stop::ReturnTo(pc.wrapping_add(2), depth))
+                    }
+                    _ => self.resume(Stop::Step),
+                }
+                Ok(json!({}))
+            }
+            "stepIn" => {
+                self.resume(Stop::Step);
+                Ok(json!({}))
+            }
+            "stepOut" => {
+                let depth = self.chip8.stack().len();
+                if depth == 0 {
+                    return Err(failed("Not in a subroutine"));
+                }
+                self.resume(Stop::Finish(depth));
+                Ok(json!({}))
+            }
+            "pause" => {
+                if let State::Running(_) = self.state {
+                    self.state = State::Paused;
+                    self.events.push(("stopped", stopped("pause")));
+                }
+                Ok(json!({}))
+            }
+            "disassemble" => Ok(self.disassemble(args)?),
+            "readMemory" => {
+                let start = reference(&args["memoryReference"], &args["offset"])?;
+                let count = args["count"].as_u64().unwrap_or(0);
+                let end = Some(count as i64)
+                    .filter(|&count| count >= 0)
+                    .and_then(|count| start.checked_add(count))
+                    .ok_or_else(|| failed("Bad memory count"))?;
+                let mut bytes = Vec::new();
+                for addr in start..end {
+                    match self.chip8.read_memory(addr as MemAddr) {
+                        Ok(byte) if (0..=0xFFFF).contains(&addr) => bytes.push(byte),
+                        _ => break,
+                    }
+                }
+                Ok(json!({
+                    "address": format!("0x{:04x}", start),
+                    "data": base64(&bytes),
+                    "unreadableBytes": count - bytes.len() as u64,
+                }))
+            }
+            "terminate" => {
+                self.events.push(("terminated", json!({})));
+                Ok(json!({}))
+            }
+            "disconnect" => {
+                self.state = State::Done;
+                Ok(json!({}))
+            }
+            _ => Err(failed(&format!("Unsupported request '{}'", command))),
+        }
+    }
+
+    fn resume(&mut self, stop: Stop) {
+        self.state = State::Running(stop);
+        self.moved = false;
+        self.pacer = FramePacer::new();
+    }
+
+    /// Run the program for a frame, or until `stop` says to pause
+    fn run_frame(&mut self, stop: Stop) -> Result<()> {
+        let keys = self.frontend.keys();
+        self.chip8.set_keys(&keys);
+        let frame = self.chip8.frames();
+        while self.chip8.frames() == frame {
+            if self.moved {
+                if let Some(id) = self.chip8.breakpoint_hit() {
+                    let mut body = stopped("breakpoint");
+                    body["hitBreakpointIds"] = json!([id]);
+                    return self.pause(body);
+                }
+            }
+            let step = match self.chip8.step() {
+                Ok(step) => step,
+                Err(e) => {
+                    let mut body = stopped("exception");
+                    body["text"] = json!(e.to_string());
+                    return self.pause(body);
+                }
+            };
+            self.moved = true;
+            if step.exited {
+                self.state = State::Paused;
+                self.event("exited", json!({"exitCode": 0}))?;
+                return self.event("terminated", json!({}));
+            }
+            if let Some(hit) = step.watch {
+                let mut body = stopped("data breakpoint");
+                body["hitBreakpointIds"] = json!([hit.id]);
+                body["text"] = json!(format!("{} 0x{:04x}", hit.kind, hit.addr));
+                return self.pause(body);
+            }
+            let done = match stop {
+                Stop::Step => true,
+                Stop::ReturnTo(pc, depth) => {
+                    self.chip8.pc() == pc && self.chip8.stack().len() == depth
+                }
+                Stop::Finish(depth) => {
+                    step.opcode == Opcode::Return && self.chip8.stack().len() < depth
+                }
+                Stop::Never => false,
+            };
+            if done {
+                return self.pause(stopped("step"));
+            }
+        }
+        self.chip8.present(self.frontend);
+        self.pacer.wait();
+        Ok(())
+    }
+
+    fn pause(&mut self, body: Value) -> Result<()> {
+        self.state = State::Paused;
+        self.event("stopped", body)
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value> {
+        let path = args["source"]["path"].as_str().unwrap_or("").to_string();
+        for id in self.source_breakpoints.remove(&path).unwrap_or_default() {
+            self.chip8.remove_breakpoint(id);
+        }
+        let mut ids = Vec::new();
+        let mut results = Vec::new();
+        for wanted in args["breakpoints"].as_array().map_or(&[][..], |b| &b[..]) {
+            let line = wanted["line"].as_u64().unwrap_or(0);
+            let result = match self.source_map.address(&path, line) {
+                Some((addr, line)) => {
+                    match self.add_breakpoint(addr, &wanted["condition"]) {
+                        Ok(id) => {
+                            ids.push(id);
+                            json!({"id": id, "verified": true, "line": line})
+                        }
+                        Err(e) => json!({"verified": false, "line": line, "message": e.to_string()}),
+                    }
+                }
+                None => json!({"verified": false, "line": line, "message": "No code on this line"}),
+            };
+            results.push(result);
+        }
+        self.source_breakpoints.insert(path, ids);
+        Ok(json!({"breakpoints": results}))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value> {
+        for id in self.instruction_breakpoints.drain(..).collect::<Vec<_>>() {
+            self.chip8.remove_breakpoint(id);
+        }
+        let mut results = Vec::new();
+        for wanted in args["breakpoints"].as_array().map_or(&[][..], |b| &b[..]) {
+            let added = reference(&wanted["instructionReference"], &wanted["offset"])
+                .and_then(|addr| if (0..=0xFFFF).contains(&addr) {
+                    Ok(addr as MemAddr)
+                } else {
+                    Err(failed("Address out of range"))
+                })
+                .and_then(|addr| self.add_breakpoint(addr, &wanted["condition"]));
+            let result = match added {
+                Ok(id) => {
+                    self.instruction_breakpoints.push(id);
+                    json!({"id": id, "verified": true})
+                }
+                Err(e) => json!({"verified": false, "message": e.to_string()}),
+            };
+            results.push(result);
+        }
+        Ok(json!({"breakpoints": results}))
+    }
+
+    // A conditional breakpoint is a condition that also checks the address
+    fn add_breakpoint(&mut self, addr: MemAddr, condition: &Value) -> Result<usize> {
+        let breakpoint = match condition.as_str() {
+            Some(condition) if !condition.trim().is_empty() => {
+                format!("if PC == 0x{:04x} && ({})", addr, condition).parse::<Breakpoint>()?
+            }
+            _ => Breakpoint::Addr(addr),
+        };
+        Ok(self.chip8.add_breakpoint(breakpoint))
+    }
+
+    fn source(&self, addr: MemAddr) -> Option<(Value, u64)> {
+        self.source_map.location(addr).map(|(path, line)| {
+            let name = Path::new(path).file_name().map_or(path.into(), |n| n.to_string_lossy());
+            (json!({"name": name, "path": path}), line)
+        })
+    }
+
+    fn stack_trace(&self) -> Value {
+        // Each frame is named after the subroutine it is in, found from the
+        // call that entered it
+        let stack = self.chip8.stack();
+        let mut frames = Vec::new();
+        for depth in 0..stack.len() + 1 {
+            let pc = if depth == 0 {
+                self.chip8.pc()
+            } else {
+                stack[stack.len() - depth].wrapping_sub(2)
+            };
+            let name = if depth < stack.len() {
+                let call = stack[stack.len() - 1 - depth].wrapping_sub(2);
+                match self.chip8.instruction(call) {
+                    Ok(Opcode::CallAddr(addr)) => format!("0x{:04x}", addr),
+                    _ => "???".to_string(),
+                }
+            } else {
+                "main".to_string()
+            };
+            let mut frame = json!({
+                "id": depth,
+                "name": name,
+                "line": 0,
+                "column": 0,
+                "instructionPointerReference": format!("0x{:04x}", pc),
+            });
+            if let Some((source, line)) = self.source(pc) {
+                frame["source"] = source;
+                frame["line"] = json!(line);
+                frame["column"] = json!(1);
+            }
+            frames.push(frame);
+        }
+        json!({"stackFrames": frames, "totalFrames": stack.len() + 1})
+    }
+
+    fn variables(&self, reference: Option<u64>) -> Vec<Value> {
+        let var = |name: &str, value: String| {
+            json!({"name": name, "value": value, "variablesReference": 0})
+        };
+        match reference {
+            Some(REGISTERS_REF) => {
+                let mut vars: Vec<Value> = REGISTER_NAMES.iter()
+                    .enumerate()
+                    .map(|(x, name)| {
+                        let value = reg(x as u8).map(|x| self.chip8.register(x)).unwrap_or(0);
+                        var(name, format!("0x{:02x}", value))
+                    })
+                    .collect();
+                let mut index = var("I", format!("0x{:04x}", self.chip8.index()));
+                index["memoryReference"] = json!(format!("0x{:04x}", self.chip8.index()));
+                vars.push(index);
+                vars.push(var("PC", format!("0x{:04x}", self.chip8.pc())));
+                vars.push(var("SP", self.chip8.stack().len().to_string()));
+                vars
+            }
+            Some(TIMERS_REF) => {
+                let (delay, sound) = self.chip8.timers();
+                vec![var("DT", delay.to_string()), var("ST", sound.to_string())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn set_variable(&mut self, args: &Value) -> Result<Value> {
+        let name = args["name"].as_str().unwrap_or("").to_uppercase();
+        let value = args["value"].as_str().unwrap_or("").parse::<Expr>()?.eval(self.chip8);
+        let (delay, sound) = self.chip8.timers();
+        if let Some(x) = REGISTER_NAMES.iter().position(|&n| n == name) {
+            self.chip8.set_register(reg(x as u8)?, value as u8);
+        } else {
+            match name.as_str() {
+                "I" => self.chip8.set_index(value as MemAddr),
+                "PC" => self.chip8.set_pc(value as MemAddr),
+                "DT" => self.chip8.set_timers(value as u8, sound),
+                "ST" => self.chip8.set_timers(delay, value as u8),
+                _ => return Err(failed(&format!("Can not set {}", name))),
+            }
+        }
+        let reference = args["variablesReference"].as_u64();
+        let shown = self.variables(reference)
+            .into_iter()
+            .find(|var| var["name"] == name.as_str())
+            .map_or(json!(""), |var| var["value"].clone());
+        Ok(json!({"value": shown}))
+    }
+
+    fn disassemble(&self, args: &Value) -> Result<Value> {
+        // Instructions are all two bytes, except XO-CHIP's long load
+        let start = reference(&args["memoryReference"], &args["offset"])?;
+        let mut addr = args["instructionOffset"]
+            .as_i64()
+            .unwrap_or(0)
+            .checked_mul(2)
+            .and_then(|offset| start.checked_add(offset))
+            .ok_or_else(|| failed("Bad instruction offset"))?;
+        let count = args["instructionCount"].as_u64().unwrap_or(0);
+        let mut instructions = Vec::new();
+        for _ in 0..count {
+            let mut instruction = json!({"address": format!("0x{:04x}", addr)});
+            let opcode = if (0..=0xFFFF).contains(&addr) {
+                self.chip8.instruction(addr as MemAddr).ok()
+            } else {
+                None
+            };
+            match opcode {
+                Some(opcode) => {
+                    let bytes: Vec<String> = (0..opcode.len())
+                        .filter_map(|i| self.chip8.read_memory((addr as MemAddr).wrapping_add(i)).ok())
+                        .map(|b| format!("{:02x}", b))
+                        .collect();
+                    instruction["instructionBytes"] = json!(bytes.join(" "));
+                    instruction["instruction"] = json!(opcode.to_string());
+                    if let Some((source, line)) = self.source(addr as MemAddr) {
+                        instruction["location"] = source;
+                        instruction["line"] = json!(line);
+                    }
+                    addr += opcode.len() as i64;
+                }
+                None => {
+                    instruction["instruction"] = json!("???");
+                    addr = addr.saturating_add(2);
+                }
+            }
+            instructions.push(instruction);
+        }
+        Ok(json!({"instructions": instructions}))
+    }
+}
+
+fn stopped(reason: &str) -> Value {
+    json!({"reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true})
+}
+
+#[cfg(test)]
+fn dap_session(rom: &[u8], listing: &str, requests: &[Value]) -> Vec<Value> {
+    use std::io::Cursor;
+    use frontend::Headless;
+    let mut chip8 = Chip8::init(None);
+    chip8.load_bytes(rom).unwrap();
+    let (tx, rx) = mpsc::channel();
+    for (seq, request) in requests.iter().enumerate() {
+        let mut request = request.clone();
+        request["type"] = json!("request");
+        request["seq"] = json!(seq + 1);
+        tx.send(request).unwrap();
+    }
+    drop(tx);
+    let mut output = Vec::new();
+    let source_map = SourceMap::parse(listing, Path::new("/src"));
+    Session::new(&mut chip8, &mut Headless, rx, &mut output, source_map).run().unwrap();
+
+    let mut output = Cursor::new(output);
+    let mut messages = Vec::new();
+    while let Some(message) = read_message(&mut output).unwrap() {
+        messages.push(message);
+    }
+    messages
+}
+
+#[test]
+fn dap_protocol() {
+    // LOAD V0 0x05; CALL 0x0206; JUMP 0x0204; ADD V0 1; RETURN
+    let rom = [0x60, 0x05, 0x22, 0x06, 0x12, 0x04, 0x70, 0x01, 0x00, 0xee];
+    let listing = "0x0200 game.asm:1\n0x0202 game.asm:2\n0x0204 game.asm:3\n\
+                   0x0206 game.asm:6\n0x0208 game.asm:7\n";
+    let messages = dap_session(&rom,
+                               listing,
+                               &[json!({"command": "initialize", "arguments": {}}),
+                                 json!({"command": "launch", "arguments": {"stopOnEntry": false}}),
+                                 json!({"command": "setBreakpoints", "arguments": {
+                                     "source": {"path": "/src/game.asm"},
+                                     "breakpoints": [{"line": 4}, {"line": 9}]}}),
+                                 json!({"command": "configurationDone"}),
+                                 json!({"command": "stackTrace", "arguments": {"threadId": 1}}),
+                                 json!({"command": "setVariable", "arguments": {
+                                     "variablesReference": 1, "name": "v1", "value": "V0 + 2"}}),
+                                 json!({"command": "stepOut", "arguments": {"threadId": 1}}),
+                                 json!({"command": "variables", "arguments": {"variablesReference": 1}}),
+                                 json!({"command": "evaluate", "arguments": {"expression": "PC"}}),
+                                 json!({"command": "disassemble", "arguments": {
+                                     "memoryReference": "0x0200", "instructionOffset": 1,
+                                     "instructionCount": 2}}),
+                                 json!({"command": "readMemory", "arguments": {
+                                     "memoryReference": "0x0200", "offset": 2, "count": 3}}),
+                                 json!({"command": "bogus"}),
+                                 json!({"command": "disconnect"})]);
+    let summary: Vec<String> = messages.iter()
+        .map(|m| match m["type"].as_str().unwrap() {
+            "event" => format!("event {} {}", m["event"], m["body"]["reason"]),
+            _ => format!("{} {}", m["command"], m["success"]),
+        })
+        .collect();
+    assert_eq!(summary,
+               vec!["\"initialize\" true",
+                    "event \"initialized\" null",
+                    "\"launch\" true",
+                    "\"setBreakpoints\" true",
+                    "\"configurationDone\" true",
+                    "event \"stopped\" \"breakpoint\"",
+                    "\"stackTrace\" true",
+                    "\"setVariable\" true",
+                    "\"stepOut\" true",
+                    "event \"stopped\" \"step\"",
+                    "\"variables\" true",
+                    "\"evaluate\" true",
+                    "\"disassemble\" true",
+                    "\"readMemory\" true",
+                    "\"bogus\" false",
+                    "\"disconnect\" true"]);
+
+    let body = |command: &str| {
+        messages.iter().find(|m| m["command"] == command).unwrap()["body"].clone()
+    };
+    let breakpoints = body("setBreakpoints")["breakpoints"].clone();
+    assert_eq!(breakpoints[0]["line"], 6);
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[1]["verified"], false);
+    assert_eq!(messages[5]["body"]["hitBreakpointIds"], json!([breakpoints[0]["id"]]));
+
+    let frames = body("stackTrace")["stackFrames"].clone();
+    assert_eq!(frames[0]["name"], "0x0206");
+    assert_eq!(frames[0]["line"], 6);
+    assert_eq!(frames[0]["source"]["path"], "/src/game.asm");
+    assert_eq!(frames[1]["name"], "main");
+    assert_eq!(frames[1]["instructionPointerReference"], "0x0202");
+    assert_eq!(body("setVariable")["value"], "0x07");
+
+    let registers = body("variables")["variables"].clone();
+    assert_eq!(registers[0]["value"], "0x06");
+    assert_eq!(registers[1]["value"], "0x07");
+    assert_eq!(registers[16]["name"], "I");
+    assert_eq!(body("evaluate")["result"], "516");
+
+    let instructions = body("disassemble")["instructions"].clone();
+    assert_eq!(instructions[0]["address"], "0x0202");
+    assert_eq!(instructions[0]["instructionBytes"], "22 06");
+    assert_eq!(instructions[0]["instruction"], "CALL 0x0206");
+    assert_eq!(instructions[1]["line"], 3);
+    assert_eq!(body("readMemory")["data"], "IgYS");
+}
+
+#[test]
+fn dap_rejects_overflowing_references() {
+    let messages = dap_session(&[0x12, 0x00],
+                               "",
+                               &[json!({"command": "readMemory", "arguments": {
+                                     "memoryReference": "0x7fffffffffffffff", "offset": 1,
+                                     "count": 1}}),
+                                 json!({"command": "readMemory", "arguments": {
+                                     "memoryReference": "0x0200",
+                                     "count": 0xffffffffffffffffu64}}),
+                                 json!({"command": "disassemble", "arguments": {
+                                     "memoryReference": "0x0200",
+                                     "instructionOffset": 0x4000000000000000i64,
+                                     "instructionCount": 1}}),
+                                 json!({"command": "disconnect"})]);
+    let success: Vec<bool> = messages.iter().map(|m| m["success"].as_bool().unwrap()).collect();
+    assert_eq!(success, vec![false, false, false, true]);
+}
@@ -0,0 +1,105 @@
+//
+// Rust Core Imports
+//
+use std::fmt;
+use std::str::FromStr;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use cpu::variant;
+use emulator::{Chip8, MemAddr};
+use super::command::number;
+use super::expr::Expr;
+
+/// Somewhere for the program to stop and return to the debugger
+///
+/// Breakpoints are checked before each instruction is executed. They can be
+/// parsed from `0x2a4`, `Draw` or `if pc == 0x2a4 && V3 > 10`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    /// Stop at this address
+    Addr(MemAddr),
+    /// Stop at any instruction of this `Opcode` variant, e.g. `Draw` or `KeyGet`
+    Opcode(&'static str),
+    /// Stop at any instruction when the condition is true
+    Condition(Expr),
+}
+
+impl Breakpoint {
+    /// Should `chip8` stop before running its next instruction?
+    pub fn hit(&self, chip8: &Chip8) -> bool {
+        match *self {
+            Breakpoint::Addr(addr) => chip8.pc() == addr,
+            Breakpoint::Opcode(name) => {
+                chip8.instruction(chip8.pc()).is_ok_and(|opcode| opcode.name() == name)
+            }
+            Breakpoint::Condition(ref expr) => expr.eval(chip8) != 0,
+        }
+    }
+}
+
+impl FromStr for Breakpoint {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(expr) = s.strip_prefix("if ") {
+            return Ok(Breakpoint::Condition(expr.parse()?));
+        }
+        if let Ok(addr) = number(s) {
+            if addr <= 0xFFFF {
+                return Ok(Breakpoint::Addr(addr as MemAddr));
+            }
+        }
+        match variant(s) {
+            Some(name) => Ok(Breakpoint::Opcode(name)),
+            None => bail!(ErrorKind::UnknownOption("breakpoint", s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Addr(addr) => write!(f, "0x{:04x}", addr),
+            Breakpoint::Opcode(name) => write!(f, "{}", name),
+            Breakpoint::Condition(ref expr) => write!(f, "if {}", expr),
+        }
+    }
+}
+
+#[test]
+fn opcode_names_cover_decode() {
+    for instr in 0..0x10000u32 {
+        if let Ok(opcode) = ::cpu::decode(instr as u16, 0) {
+            assert_eq!(variant(opcode.name()), Some(opcode.name()));
+            // A mnemonic only stands for the variant when it is unambiguous
+            let text = opcode.to_string();
+            let written = text.split_whitespace().next().unwrap();
+            assert!(variant(written).is_none_or(|name| name == opcode.name()), "{}", text);
+        }
+    }
+}
+
+#[test]
+fn parse_breakpoints() {
+    assert_eq!("0x2a4".parse::<Breakpoint>().unwrap(), Breakpoint::Addr(0x2a4));
+    assert_eq!("keyget".parse::<Breakpoint>().unwrap(), Breakpoint::Opcode("KeyGet"));
+    assert_eq!("KEY.GET".parse::<Breakpoint>().unwrap(), Breakpoint::Opcode("KeyGet"));
+    assert_eq!("MemAdd".parse::<Breakpoint>().unwrap(), Breakpoint::Opcode("MemAdd"));
+    // LOAD is written for LoadByte, LoadReg, MemLoad and JumpAddV0
+    assert!("load".parse::<Breakpoint>().is_err());
+    let cond = "if pc == 0x2a4 && V3 > 10".parse::<Breakpoint>().unwrap();
+    assert_eq!(format!("{}", cond), "if pc == 0x2a4 && V3 > 10");
+    assert!("0x10000".parse::<Breakpoint>().is_err());
+    assert!("Drawing".parse::<Breakpoint>().is_err());
+    assert!("if v3 >".parse::<Breakpoint>().is_err());
+
+    let mut chip8 = Chip8::init(None);
+    // DRAW V0 V1 0x05
+    chip8.load_bytes(&[0xd0, 0x15]).unwrap();
+    assert!("draw".parse::<Breakpoint>().unwrap().hit(&chip8));
+    assert!("0x200".parse::<Breakpoint>().unwrap().hit(&chip8));
+    assert!(!"KeyGet".parse::<Breakpoint>().unwrap().hit(&chip8));
+}
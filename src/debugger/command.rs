@@ -0,0 +1,240 @@
+//
+// This Crate Imports
+//
+use errors::*;
+use cpu::{Reg, reg};
+use emulator::MemAddr;
+use super::breakpoint::Breakpoint;
+use interconnect::{Watchpoint, WatchKind};
+
+const DEFAULT_MEM_LEN: u16 = 64;
+const DEFAULT_LIST_LEN: u16 = 10;
+
+/// Something the debugger can read or change with `set`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Reg(Reg),
+    Index,
+    Pc,
+    Delay,
+    Sound,
+}
+
+/// A parsed line of debugger input
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Step(u64),
+    Next,
+    Continue,
+    Finish,
+    ReverseStep(u64),
+    ReverseContinue,
+    Regs,
+    Set(Target, u16),
+    Mem(MemAddr, u16),
+    Write(MemAddr, Vec<u8>),
+    List(Option<MemAddr>, u16),
+    Press(u8),
+    Release(u8),
+    Keys,
+    Break(Breakpoint),
+    Watch(Watchpoint),
+    Delete(usize),
+    Breakpoints,
+    History,
+    Help,
+    Quit,
+}
+
+/// `(name, alias, usage)` for every command, names can also be shortened
+/// to any unique prefix
+pub const COMMANDS: [(&'static str, &'static str, &'static str); 23] =
+    [("step", "s", "step [N]          run N instructions"),
+     ("next", "n", "next              step, running a whole CALL at once"),
+     ("continue", "c", "continue          run until interrupted with F12"),
+     ("finish", "fin", "finish            run until the current subroutine returns"),
+     ("reverse-step", "rs", "reverse-step [N]  go back N instructions"),
+     ("reverse-continue", "rc", "reverse-continue  go back to the last breakpoint or watchpoint"),
+     ("regs", "r", "regs              show the registers, timers and stack"),
+     ("set", "", "set TARGET VALUE  change V0-VF, I, PC, DT or ST"),
+     ("mem", "x", "mem ADDR [LEN]    show memory"),
+     ("write", "w", "write ADDR BYTE.. change memory"),
+     ("list", "l", "list [ADDR] [N]   disassemble N instructions"),
+     ("press", "p", "press KEY         hold down a key"),
+     ("release", "", "release KEY       let go of a key"),
+     ("keys", "k", "keys              show which keys are held"),
+     ("break", "b", "break WHERE       stop at an ADDR, an opcode like Draw, or 'if EXPR'"),
+     ("watch", "", "watch RANGE       stop after a write to 0x300, 0x300-0x30f or 0x300+16"),
+     ("rwatch", "", "rwatch RANGE      stop after a read"),
+     ("awatch", "", "awatch RANGE      stop after a read or write"),
+     ("delete", "d", "delete ID         remove a breakpoint or watchpoint"),
+     ("breakpoints", "bl", "breakpoints       list the breakpoints and watchpoints"),
+     ("history", "", "history           show previous commands, !N runs one again"),
+     ("help", "h", "help              show this list"),
+     ("quit", "q", "quit              stop the emulator")];
+
+fn bad(msg: String) -> Error {
+    ErrorKind::DebuggerCommand(msg).into()
+}
+
+/// Find the full name of a command from its name, alias or a unique prefix
+fn resolve(word: &str) -> Result<&'static str> {
+    if let Some(&(name, _, _)) = COMMANDS.iter()
+        .find(|&&(name, alias, _)| word == name || word == alias) {
+        return Ok(name);
+    }
+    let matches: Vec<&str> = COMMANDS.iter()
+        .map(|&(name, _, _)| name)
+        .filter(|name| name.starts_with(word))
+        .collect();
+    match matches.len() {
+        0 => Err(bad(format!("Unknown command '{}', try 'help'", word))),
+        1 => Ok(matches[0]),
+        _ => Err(bad(format!("Ambiguous command '{}': {}", word, matches.join(", ")))),
+    }
+}
+
+/// Parse a number, in hex with a `0x` prefix or decimal otherwise
+pub fn number(word: &str) -> Result<u64> {
+    let parsed = if word.starts_with("0x") || word.starts_with("0X") {
+        u64::from_str_radix(&word[2..], 16)
+    } else {
+        word.parse()
+    };
+    parsed.map_err(|_| bad(format!("Not a number: '{}'", word)))
+}
+
+fn sized(word: &str, max: u64) -> Result<u64> {
+    let value = number(word)?;
+    if value > max {
+        return Err(bad(format!("{} is too large, the limit is 0x{:x}", word, max)));
+    }
+    Ok(value)
+}
+
+fn target(word: &str) -> Result<Target> {
+    let lower = word.to_lowercase();
+    Ok(match lower.as_str() {
+        "i" => Target::Index,
+        "pc" => Target::Pc,
+        "dt" | "delay" => Target::Delay,
+        "st" | "sound" => Target::Sound,
+        _ if lower.len() == 2 && lower.starts_with('v') => {
+            let x = u8::from_str_radix(&lower[1..], 16)
+                .map_err(|_| bad(format!("Unknown register '{}'", word)))?;
+            Target::Reg(reg(x)?)
+        }
+        _ => return Err(bad(format!("Unknown register '{}'", word))),
+    })
+}
+
+fn key(word: &str) -> Result<u8> {
+    match u8::from_str_radix(word, 16) {
+        Ok(k) if k < 16 => Ok(k),
+        _ => Err(bad(format!("Keys are 0-f, not '{}'", word))),
+    }
+}
+
+/// Parse one line of input
+pub fn parse(line: &str) -> Result<Command> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (first, args) = match words.split_first() {
+        Some((first, args)) => (*first, args),
+        None => return Err(bad("No command".to_string())),
+    };
+    let name = resolve(first)?;
+    let arg = |i: usize| -> Result<&str> {
+        args.get(i).cloned().ok_or_else(|| bad("Missing arguments, try 'help'".to_string()))
+    };
+    let command = match name {
+        "step" => Command::Step(args.first().map_or(Ok(1), |n| number(n))?),
+        "next" => Command::Next,
+        "continue" => Command::Continue,
+        "finish" => Command::Finish,
+        "reverse-step" => Command::ReverseStep(args.first().map_or(Ok(1), |n| number(n))?),
+        "reverse-continue" => Command::ReverseContinue,
+        "regs" => Command::Regs,
+        "set" => {
+            let target = target(arg(0)?)?;
+            let max = match target {
+                Target::Index | Target::Pc => 0xFFFF,
+                _ => 0xFF,
+            };
+            Command::Set(target, sized(arg(1)?, max)? as u16)
+        }
+        "mem" => {
+            let len = args.get(1).map_or(Ok(DEFAULT_MEM_LEN as u64), |n| sized(n, 0xFFFF))?;
+            Command::Mem(sized(arg(0)?, 0xFFFF)? as MemAddr, len as u16)
+        }
+        "write" => {
+            let addr = sized(arg(0)?, 0xFFFF)? as MemAddr;
+            arg(1)?;
+            let bytes = args[1..]
+                .iter()
+                .map(|b| sized(b, 0xFF).map(|b| b as u8))
+                .collect::<Result<Vec<u8>>>()?;
+            Command::Write(addr, bytes)
+        }
+        "list" => {
+            let addr = match args.first() {
+                Some(a) => Some(sized(a, 0xFFFF)? as MemAddr),
+                None => None,
+            };
+            let len = args.get(1).map_or(Ok(DEFAULT_LIST_LEN as u64), |n| sized(n, 0xFFFF))?;
+            Command::List(addr, len as u16)
+        }
+        "press" => Command::Press(key(arg(0)?)?),
+        "release" => Command::Release(key(arg(0)?)?),
+        "keys" => Command::Keys,
+        "break" => {
+            arg(0)?;
+            Command::Break(args.join(" ").parse()?)
+        }
+        "watch" => Command::Watch(Watchpoint::parse(arg(0)?, WatchKind::Write)?),
+        "rwatch" => Command::Watch(Watchpoint::parse(arg(0)?, WatchKind::Read)?),
+        "awatch" => Command::Watch(Watchpoint::parse(arg(0)?, WatchKind::Access)?),
+        "delete" => Command::Delete(number(arg(0)?)? as usize),
+        "breakpoints" => Command::Breakpoints,
+        "history" => Command::History,
+        "help" => Command::Help,
+        "quit" => Command::Quit,
+        _ => unreachable!(),
+    };
+    Ok(command)
+}
+
+#[test]
+fn parse_commands() {
+    assert_eq!(parse("s").unwrap(), Command::Step(1));
+    assert_eq!(parse("step 0x10").unwrap(), Command::Step(16));
+    assert_eq!(parse("  fin ").unwrap(), Command::Finish);
+    assert_eq!(parse("cont").unwrap(), Command::Continue);
+    assert_eq!(parse("rs 3").unwrap(), Command::ReverseStep(3));
+    assert_eq!(parse("reverse-c").unwrap(), Command::ReverseContinue);
+    assert_eq!(parse("set vA 0x2a").unwrap(), Command::Set(Target::Reg(Reg::VA), 0x2a));
+    assert_eq!(parse("set I 0x300").unwrap(), Command::Set(Target::Index, 0x300));
+    assert_eq!(parse("x 0x200 4").unwrap(), Command::Mem(0x200, 4));
+    assert_eq!(parse("w 0x200 1 0xff").unwrap(), Command::Write(0x200, vec![1, 0xff]));
+    assert_eq!(parse("l").unwrap(), Command::List(None, DEFAULT_LIST_LEN));
+    assert_eq!(parse("press f").unwrap(), Command::Press(0xf));
+    assert_eq!(parse("rel 3").unwrap(), Command::Release(3));
+    assert_eq!(parse("b 0x2a4").unwrap(), Command::Break(Breakpoint::Addr(0x2a4)));
+    assert_eq!(parse("bl").unwrap(), Command::Breakpoints);
+    assert_eq!(parse("aw 0x300+2").unwrap(),
+               Command::Watch(Watchpoint {
+                   start: 0x300,
+                   end: 0x301,
+                   kind: WatchKind::Access,
+               }));
+
+    assert!(parse("").is_err());
+    assert!(parse("re 1").is_err()); // regs or release
+    assert!(parse("bogus").is_err());
+    assert!(parse("set v0 0x100").is_err());
+    assert!(parse("set vg 1").is_err());
+    assert!(parse("press 16").is_err());
+    assert!(parse("write 0x200").is_err());
+    assert!(parse("break").is_err());
+    assert!(parse("br").is_err()); // break or breakpoints
+    assert!(parse("rev").is_err()); // reverse-step or reverse-continue
+}
@@ -0,0 +1,284 @@
+//
+// Rust Core Imports
+//
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use cpu::{Reg, reg};
+use emulator::Chip8;
+use super::command::number;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Var {
+    Reg(Reg),
+    Index,
+    Pc,
+    Sp,
+    Delay,
+    Sound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    BitAnd,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Num(i64),
+    Var(Var),
+    Not(Box<Node>),
+    Bin(Op, Box<Node>, Box<Node>),
+}
+
+/// A condition over the registers, e.g. `pc == 0x2a4 && V3 > 10`
+///
+/// Values are `V0`-`VF`, `I`, `PC`, `SP` (the stack depth), `DT`, `ST` and
+/// numbers. They can be combined with `+ - & == != < <= > >= && || !` and
+/// parentheses. Anything non-zero is true.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    node: Node,
+    source: String,
+}
+
+fn bad(source: &str, reason: &'static str) -> Error {
+    ErrorKind::BadExpression(source.to_string(), reason).into()
+}
+
+fn tokenize(source: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = source.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let pair: String = chars[i..cmp::min(i + 2, chars.len())].iter().collect();
+            match pair.as_str() {
+                "==" | "!=" | "<=" | ">=" | "&&" | "||" => {
+                    tokens.push(pair);
+                    i += 2;
+                }
+                _ if "<>!+-&()".contains(c) => {
+                    tokens.push(c.to_string());
+                    i += 1;
+                }
+                _ => return Err(bad(source, "unexpected character")),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Result<String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| bad(self.source, "unexpected end"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    // Parse a left associative chain of the operators in `ops`
+    fn binary(&mut self, ops: &[(&str, Op)], operand: fn(&mut Self) -> Result<Node>) -> Result<Node> {
+        let mut node = operand(self)?;
+        loop {
+            let op = match self.peek() {
+                Some(token) => ops.iter().find(|&&(t, _)| t == token).map(|&(_, op)| op),
+                None => None,
+            };
+            match op {
+                Some(op) => {
+                    self.pos += 1;
+                    node = Node::Bin(op, Box::new(node), Box::new(operand(self)?));
+                }
+                None => return Ok(node),
+            }
+        }
+    }
+
+    fn or(&mut self) -> Result<Node> {
+        self.binary(&[("||", Op::Or)], Parser::and)
+    }
+
+    fn and(&mut self) -> Result<Node> {
+        self.binary(&[("&&", Op::And)], Parser::compare)
+    }
+
+    fn compare(&mut self) -> Result<Node> {
+        self.binary(&[("==", Op::Eq),
+                      ("!=", Op::Ne),
+                      ("<", Op::Lt),
+                      ("<=", Op::Le),
+                      (">", Op::Gt),
+                      (">=", Op::Ge)],
+                    Parser::sum)
+    }
+
+    fn sum(&mut self) -> Result<Node> {
+        self.binary(&[("+", Op::Add), ("-", Op::Sub), ("&", Op::BitAnd)],
+                    Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        let token = self.next()?;
+        match token.as_str() {
+            "!" => Ok(Node::Not(Box::new(self.unary()?))),
+            "(" => {
+                let node = self.or()?;
+                if self.next()? != ")" {
+                    return Err(bad(self.source, "expected ')'"));
+                }
+                Ok(node)
+            }
+            _ => self.atom(&token),
+        }
+    }
+
+    fn atom(&self, token: &str) -> Result<Node> {
+        if token.starts_with(|c: char| c.is_ascii_digit()) {
+            return number(token)
+                .map(|n| Node::Num(n as i64))
+                .map_err(|_| bad(self.source, "bad number"));
+        }
+        let lower = token.to_lowercase();
+        let var = match lower.as_str() {
+            "i" => Var::Index,
+            "pc" => Var::Pc,
+            "sp" => Var::Sp,
+            "dt" => Var::Delay,
+            "st" => Var::Sound,
+            _ if lower.len() == 2 && lower.starts_with('v') => {
+                match u8::from_str_radix(&lower[1..], 16) {
+                    Ok(x) => Var::Reg(reg(x)?),
+                    Err(_) => return Err(bad(self.source, "unknown name")),
+                }
+            }
+            _ => return Err(bad(self.source, "unknown name")),
+        };
+        Ok(Node::Var(var))
+    }
+}
+
+impl FromStr for Expr {
+    type Err = Error;
+    fn from_str(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            source: source,
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+        let node = parser.or()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(bad(source, "unexpected trailing input"));
+        }
+        Ok(Expr {
+            node: node,
+            source: source.trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn eval(node: &Node, chip8: &Chip8) -> i64 {
+    match *node {
+        Node::Num(n) => n,
+        Node::Var(var) => {
+            match var {
+                Var::Reg(x) => chip8.register(x) as i64,
+                Var::Index => chip8.index() as i64,
+                Var::Pc => chip8.pc() as i64,
+                Var::Sp => chip8.stack().len() as i64,
+                Var::Delay => chip8.timers().0 as i64,
+                Var::Sound => chip8.timers().1 as i64,
+            }
+        }
+        Node::Not(ref inner) => (eval(inner, chip8) == 0) as i64,
+        Node::Bin(op, ref lhs, ref rhs) => {
+            let lhs = eval(lhs, chip8);
+            // && and || short circuit, which only matters for speed
+            match op {
+                Op::Or if lhs != 0 => return 1,
+                Op::And if lhs == 0 => return 0,
+                _ => {}
+            }
+            let rhs = eval(rhs, chip8);
+            match op {
+                Op::Or | Op::And => (rhs != 0) as i64,
+                Op::Eq => (lhs == rhs) as i64,
+                Op::Ne => (lhs != rhs) as i64,
+                Op::Lt => (lhs < rhs) as i64,
+                Op::Le => (lhs <= rhs) as i64,
+                Op::Gt => (lhs > rhs) as i64,
+                Op::Ge => (lhs >= rhs) as i64,
+                Op::Add => lhs.wrapping_add(rhs),
+                Op::Sub => lhs.wrapping_sub(rhs),
+                Op::BitAnd => lhs & rhs,
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// Work out the value of the expression for the current state of `chip8`
+    pub fn eval(&self, chip8: &Chip8) -> i64 {
+        eval(&self.node, chip8)
+    }
+}
+
+#[test]
+fn parse_and_eval() {
+    let mut chip8 = Chip8::init(None);
+    chip8.set_register(Reg::V3, 11);
+    chip8.set_index(0x300);
+    let check = |source: &str| source.parse::<Expr>().unwrap().eval(&chip8);
+
+    assert_eq!(check("pc == 0x200 && V3 > 10"), 1);
+    assert_eq!(check("pc == 0x2a4 && v3 > 10"), 0);
+    assert_eq!(check("PC==0x2a4||V3>=11"), 1);
+    assert_eq!(check("i + v3 - 1"), 0x30a);
+    assert_eq!(check("!(v3 & 1) || sp != 0"), 0);
+    assert_eq!(check("1 + 2 == 3 && 2 < 3 && 3 <= 3 && !(4 < 3)"), 1);
+    assert_eq!(check("dt == st"), 1);
+
+    for bad in &["", "v3 >", "(v3", "vg == 1", "v3 = 1", "foo", "v3 1", "0xzz"] {
+        assert!(bad.parse::<Expr>().is_err(), "{}", bad);
+    }
+    assert_eq!(format!("{}", " v3 > 1 ".parse::<Expr>().unwrap()), "v3 > 1");
+}
@@ -0,0 +1,572 @@
+//
+// Rust Core Imports
+//
+use std::io::{self, BufRead, Write};
+
+//
+// This Crate Imports
+//
+use errors::*;
+use cpu::{Opcode, reg};
+use emulator::{Chip8, FramePacer, MemAddr, Step};
+use frontend::{VideoSink, InputSource, AudioSink};
+use frontend::Command as FrontendCommand;
+#[cfg(test)]
+use frontend::Headless;
+
+//
+// Declare sub modules
+//
+mod breakpoint;
+mod command;
+mod expr;
+mod timeline;
+
+use self::command::{Command, Target, COMMANDS};
+use self::timeline::Timeline;
+
+//
+// Public Exports
+//
+pub use self::breakpoint::Breakpoint;
+pub use self::expr::Expr;
+
+const PROMPT: &'static str = "(c8db) ";
+const HEXDUMP_COLS: usize = 16;
+// How many instructions apart reverse execution snapshots are, and how many
+// to keep, about half an hour at the default speed
+const TIMELINE_INTERVAL: u64 = 1000;
+const TIMELINE_SNAPSHOTS: usize = 1000;
+
+// When a resumed program should pause again
+enum Stop {
+    Steps(u64),
+    ReturnTo(MemAddr, usize),
+    Finish(usize),
+    Never,
+}
+
+/// An interactive debugger for a `Chip8`, driven from the terminal
+///
+/// The program starts paused, e.g. after `Chip8::run` stops at a breakpoint.
+/// Once it is running, F12 in the window or a breakpoint pauses it again. An
+/// empty line repeats the last command.
+///
+/// Everything run from the debugger is recorded, so `reverse-step` and
+/// `reverse-continue` can go back to an earlier instruction.
+pub struct Debugger {
+    history: Vec<String>,
+    held: [bool; 16],
+    timeline: Timeline,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    /// A debugger with no history and no keys held
+    pub fn new() -> Self {
+        Debugger {
+            history: Vec::new(),
+            held: [false; 16],
+            timeline: Timeline::new(TIMELINE_INTERVAL, TIMELINE_SNAPSHOTS),
+        }
+    }
+
+    /// Read commands from stdin until `quit`, or the frontend is closed
+    pub fn run<F>(&mut self, chip8: &mut Chip8, frontend: &mut F) -> Result<()>
+        where F: VideoSink + InputSource + AudioSink
+    {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        self.repl(chip8, frontend, &mut stdin.lock(), &mut stdout.lock())
+    }
+
+    fn repl<F, R, W>(&mut self,
+                     chip8: &mut Chip8,
+                     frontend: &mut F,
+                     input: &mut R,
+                     out: &mut W)
+                     -> Result<()>
+        where F: VideoSink + InputSource + AudioSink,
+              R: BufRead,
+              W: Write
+    {
+        self.show_context(chip8, out)?;
+        while frontend.is_open() {
+            chip8.present(frontend);
+            write!(out, "{}", PROMPT)?;
+            out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                break;
+            }
+            let command = self.recall(line.trim()).and_then(|line| command::parse(&line));
+            let result = match command {
+                Ok(Command::Quit) => break,
+                Ok(command) => self.execute(chip8, frontend, command, out),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                writeln!(out, "{}", e)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Expand history references, and remember the line
+    fn recall(&mut self, line: &str) -> Result<String> {
+        if line.is_empty() {
+            return self.history
+                .last()
+                .cloned()
+                .ok_or_else(|| ErrorKind::DebuggerCommand("No command".to_string()).into());
+        }
+        let line = if let Some(n) = line.strip_prefix('!') {
+            let n = command::number(n)? as usize;
+            match self.history.get(n.wrapping_sub(1)) {
+                Some(line) => line.clone(),
+                None => bail!(ErrorKind::DebuggerCommand(format!("No history entry {}", n))),
+            }
+        } else {
+            line.to_string()
+        };
+        self.history.push(line.clone());
+        Ok(line)
+    }
+
+    fn execute<F, W>(&mut self,
+                     chip8: &mut Chip8,
+                     frontend: &mut F,
+                     command: Command,
+                     out: &mut W)
+                     -> Result<()>
+        where F: VideoSink + InputSource + AudioSink,
+              W: Write
+    {
+        match command {
+            Command::Step(n) => self.resume(chip8, frontend, Stop::Steps(n), out)?,
+            Command::Next => {
+                let pc = chip8.pc();
+                let stop = match chip8.instruction(pc)? {
+                    Opcode::CallAddr(_) => Stop::ReturnTo(pc.wrapping_add(2), chip8.stack().len()),
+                    _ => Stop::Steps(1),
+                };
+                self.resume(chip8, frontend, stop, out)?
+            }
+            Command::Continue => self.resume(chip8, frontend, Stop::Never, out)?,
+            Command::Finish => {
+                let depth = chip8.stack().len();
+                if depth == 0 {
+                    bail!(ErrorKind::DebuggerCommand("Not in a subroutine".to_string()));
+                }
+                self.resume(chip8, frontend, Stop::Finish(depth), out)?
+            }
+            Command::ReverseStep(n) => {
+                let start = self.timeline.start().unwrap_or(0);
+                let now = chip8.cycles();
+                if now <= start {
+                    bail!(ErrorKind::DebuggerCommand("At the start of the recorded history"
+                        .to_string()));
+                }
+                let cycle = if n > now - start {
+                    writeln!(out, "Reached the start of the recorded history")?;
+                    start
+                } else {
+                    now - n
+                };
+                self.timeline.travel(chip8, cycle)?;
+                self.show_location(chip8, out)?
+            }
+            Command::ReverseContinue => {
+                let now = chip8.cycles();
+                let found = self.timeline.last_stop(chip8, now);
+                let (cycle, reason) = match found {
+                    Ok(Some(found)) => found,
+                    Ok(None) => {
+                        (self.timeline.start().unwrap_or(now),
+                         "Reached the start of the recorded history".to_string())
+                    }
+                    Err(e) => {
+                        // Don't leave the program wherever the search got to
+                        self.timeline.travel(chip8, now)?;
+                        return Err(e);
+                    }
+                };
+                self.timeline.travel(chip8, cycle)?;
+                writeln!(out, "{}", reason)?;
+                self.show_context(chip8, out)?
+            }
+            Command::Regs => self.show_regs(chip8, out)?,
+            Command::Set(target, value) => {
+                let (delay, sound) = chip8.timers();
+                match target {
+                    Target::Reg(x) => chip8.set_register(x, value as u8),
+                    Target::Index => chip8.set_index(value),
+                    Target::Pc => chip8.set_pc(value),
+                    Target::Delay => chip8.set_timers(value as u8, sound),
+                    Target::Sound => chip8.set_timers(delay, value as u8),
+                }
+                self.timeline.snapshot(chip8);
+            }
+            Command::Mem(addr, len) => {
+                for offset in 0..len {
+                    if (offset as usize).is_multiple_of(HEXDUMP_COLS) {
+                        if offset != 0 {
+                            writeln!(out)?;
+                        }
+                        write!(out, "0x{:04x}:", addr.wrapping_add(offset))?;
+                    }
+                    write!(out, " {:02x}", chip8.read_memory(addr.wrapping_add(offset))?)?;
+                }
+                writeln!(out)?;
+            }
+            Command::Write(addr, bytes) => {
+                for (offset, &byte) in bytes.iter().enumerate() {
+                    chip8.write_memory(addr.wrapping_add(offset as u16), byte)?;
+                }
+                self.timeline.snapshot(chip8);
+            }
+            Command::List(addr, len) => {
+                let mut addr = addr.unwrap_or(chip8.pc());
+                for _ in 0..len {
+                    let marker = if addr == chip8.pc() { "-->" } else { "   " };
+                    match chip8.instruction(addr) {
+                        Ok(opcode) => {
+                            writeln!(out, "{} 0x{:04x}: {}", marker, addr, opcode)?;
+                            addr = addr.wrapping_add(opcode.len());
+                        }
+                        Err(e) => {
+                            writeln!(out, "{} 0x{:04x}: ??? {}", marker, addr, e)?;
+                            addr = addr.wrapping_add(2);
+                        }
+                    }
+                }
+            }
+            Command::Press(key) => {
+                self.held[key as usize] = true;
+                self.timeline.set_keys(chip8, &self.held);
+            }
+            Command::Release(key) => {
+                self.held[key as usize] = false;
+                self.timeline.set_keys(chip8, &self.held);
+            }
+            Command::Keys => {
+                let held: Vec<String> = chip8.keys()
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &down)| down)
+                    .map(|(key, _)| format!("{:x}", key))
+                    .collect();
+                writeln!(out, "Keys held: [{}]", held.join(", "))?;
+            }
+            Command::Break(breakpoint) => {
+                let id = chip8.add_breakpoint(breakpoint.clone());
+                writeln!(out, "Breakpoint {}: {}", id, breakpoint)?;
+            }
+            Command::Watch(watchpoint) => {
+                let id = chip8.add_watchpoint(watchpoint);
+                writeln!(out, "Watchpoint {}: {}", id, watchpoint)?;
+            }
+            Command::Delete(id) => {
+                if !chip8.remove_breakpoint(id) && !chip8.remove_watchpoint(id) {
+                    bail!(ErrorKind::DebuggerCommand(format!("No breakpoint {}", id)));
+                }
+            }
+            Command::Breakpoints => {
+                let mut all: Vec<(usize, String)> = chip8.breakpoints()
+                    .iter()
+                    .map(|&(id, ref breakpoint)| (id, format!("break {}", breakpoint)))
+                    .chain(chip8.watchpoints()
+                        .iter()
+                        .map(|&(id, ref watchpoint)| (id, format!("watch {}", watchpoint))))
+                    .collect();
+                all.sort();
+                for (id, description) in all {
+                    writeln!(out, "{:4}  {}", id, description)?;
+                }
+            }
+            Command::History => {
+                for (n, line) in self.history.iter().enumerate() {
+                    writeln!(out, "{:4}  {}", n + 1, line)?;
+                }
+            }
+            Command::Help => {
+                for &(_, alias, usage) in COMMANDS.iter() {
+                    writeln!(out, "  {:4} {}", alias, usage)?;
+                }
+            }
+            Command::Quit => {}
+        }
+        Ok(())
+    }
+
+    /// Run the program, a frame at a time, until `stop` says to pause
+    fn resume<F, W>(&mut self,
+                    chip8: &mut Chip8,
+                    frontend: &mut F,
+                    stop: Stop,
+                    out: &mut W)
+                    -> Result<()>
+        where F: VideoSink + InputSource + AudioSink,
+              W: Write
+    {
+        let mut pacer = FramePacer::new();
+        let mut steps = 0;
+        let mut breakpoint = None;
+        let mut watch = None;
+        let reason = 'frames: loop {
+            if !frontend.is_open() {
+                break "Window closed";
+            }
+            if frontend.commands().contains(&FrontendCommand::Break) {
+                break "Interrupted";
+            }
+            let mut keys = frontend.keys();
+            for (key, &held) in keys.iter_mut().zip(self.held.iter()) {
+                *key |= held;
+            }
+            self.timeline.set_keys(chip8, &keys);
+
+            let frame = chip8.frames();
+            while chip8.frames() == frame {
+                // The first instruction is allowed to be on a breakpoint, or it
+                // would never be possible to continue from one
+                if steps > 0 {
+                    breakpoint = chip8.breakpoint_hit();
+                    if breakpoint.is_some() {
+                        break 'frames "";
+                    }
+                }
+                self.timeline.record(chip8);
+                let step = match chip8.step() {
+                    Ok(step) => step,
+                    Err(e) => {
+                        writeln!(out, "Fault: {}", e)?;
+                        break 'frames "";
+                    }
+                };
+                steps += 1;
+                if step.exited {
+                    break 'frames "ROM exited";
+                }
+                if step.watch.is_some() {
+                    watch = Some(step);
+                    break 'frames "";
+                }
+                let done = match stop {
+                    Stop::Steps(n) => steps >= n,
+                    Stop::ReturnTo(pc, depth) => {
+                        chip8.pc() == pc && chip8.stack().len() == depth
+                    }
+                    Stop::Finish(depth) => {
+                        step.opcode == Opcode::Return && chip8.stack().len() < depth
+                    }
+                    Stop::Never => false,
+                };
+                if done {
+                    break 'frames "";
+                }
+            }
+            chip8.present(frontend);
+            pacer.wait();
+        };
+        if !reason.is_empty() {
+            writeln!(out, "{}", reason)?;
+        }
+        if let Some(step) = watch {
+            writeln!(out, "{}", describe_watch(&step))?;
+            return self.show_context(chip8, out);
+        }
+        match breakpoint {
+            Some(id) => {
+                writeln!(out, "Breakpoint {}", id)?;
+                self.show_context(chip8, out)
+            }
+            None => self.show_location(chip8, out),
+        }
+    }
+
+    // The registers, and the instructions either side of the PC
+    fn show_context<W: Write>(&self, chip8: &Chip8, out: &mut W) -> Result<()> {
+        self.show_regs(chip8, out)?;
+        for i in -5..10 {
+            let addr = (chip8.pc() as isize + 2 * i) as MemAddr;
+            let marker = if i == 0 { "-->" } else { "   " };
+            match chip8.instruction(addr) {
+                Ok(opcode) => writeln!(out, "{} 0x{:04x}: {}", marker, addr, opcode)?,
+                Err(_) => writeln!(out, "{} 0x{:04x}: ???", marker, addr)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn show_location<W: Write>(&self, chip8: &Chip8, out: &mut W) -> Result<()> {
+        let pc = chip8.pc();
+        match chip8.instruction(pc) {
+            Ok(opcode) => writeln!(out, "0x{:04x}: {}", pc, opcode)?,
+            Err(e) => writeln!(out, "0x{:04x}: ??? {}", pc, e)?,
+        }
+        Ok(())
+    }
+
+    fn show_regs<W: Write>(&self, chip8: &Chip8, out: &mut W) -> Result<()> {
+        let (delay, sound) = chip8.timers();
+        writeln!(out,
+                 "PC 0x{:04x}  I 0x{:04x}  DT {}  ST {}",
+                 chip8.pc(),
+                 chip8.index(),
+                 delay,
+                 sound)?;
+        for row in 0..2 {
+            for x in row * 8..row * 8 + 8 {
+                write!(out, "V{:X} {:02x}  ", x, chip8.register(reg(x)?))?;
+            }
+            writeln!(out)?;
+        }
+        let stack: Vec<String> = chip8.stack().iter().map(|addr| format!("0x{:04x}", addr)).collect();
+        writeln!(out, "Stack: [{}]", stack.join(", "))?;
+        Ok(())
+    }
+}
+
+// Which watchpoint an instruction set off, and how
+fn describe_watch(step: &Step) -> String {
+    match step.watch {
+        Some(hit) => {
+            format!("Watchpoint {}: {} 0x{:04x} 0x{:02x} -> 0x{:02x} by 0x{:04x}: {}",
+                    hit.id,
+                    hit.kind,
+                    hit.addr,
+                    hit.old,
+                    hit.new,
+                    step.pc,
+                    step.opcode)
+        }
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+fn debug_session(rom: &[u8], script: &str) -> (Chip8, String) {
+    let mut chip8 = Chip8::init(None);
+    chip8.load_bytes(rom).unwrap();
+    let mut out = Vec::new();
+    Debugger::new().repl(&mut chip8, &mut Headless, &mut script.as_bytes(), &mut out).unwrap();
+    (chip8, String::from_utf8(out).unwrap())
+}
+
+// CALL 0x0206; LOAD V0 0x01; JUMP 0x0204; LOAD V1 0x02; LOAD V2 0x03; RETURN
+#[cfg(test)]
+const SUBROUTINE_ROM: [u8; 12] = [0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x61, 0x02, 0x62, 0x03,
+                                  0x00, 0xee];
+
+#[test]
+fn debugger_next_and_finish() {
+    let (chip8, out) = debug_session(&SUBROUTINE_ROM, "next\nregs\nq\n");
+    assert_eq!(chip8.pc(), 0x202);
+    assert_eq!(chip8.register(::cpu::Reg::V2), 3);
+    assert!(out.contains("0x0202: LOAD V0 0x01"));
+
+    let (chip8, _) = debug_session(&SUBROUTINE_ROM, "s\ns\nfin\n");
+    assert_eq!(chip8.pc(), 0x202);
+    assert_eq!(chip8.register(::cpu::Reg::V1), 2);
+    assert!(chip8.stack().is_empty());
+
+    let (_, out) = debug_session(&SUBROUTINE_ROM, "fin\n");
+    assert!(out.contains("Not in a subroutine"));
+}
+
+#[test]
+fn debugger_edit_state() {
+    let script = "set v3 0x10\nset i 0x300\nw 0x300 0xab 0xcd\nx 0x300 2\npress a\ns 2\n\nhistory\n!1\n";
+    let (chip8, out) = debug_session(&SUBROUTINE_ROM, script);
+    assert_eq!(chip8.register(::cpu::Reg::V3), 0x10);
+    assert_eq!(chip8.index(), 0x300);
+    assert!(out.contains("0x0300: ab cd"));
+    assert!(chip8.keys()[0xa]);
+    // s 2 twice: CALL, LOAD V1, LOAD V2, RETURN
+    assert_eq!(chip8.pc(), 0x202);
+    assert!(out.contains("   7  history"));
+}
+
+#[test]
+fn debugger_breakpoints() {
+    let script = "b 0x208\nb if v1 == 2\nbl\nc\nc\nd 1\nd 1\nc\n";
+    let (chip8, out) = debug_session(&SUBROUTINE_ROM, script);
+    assert!(out.contains("Breakpoint 1: 0x0208"));
+    assert!(out.contains("   2  break if v1 == 2"));
+    // Both are hit at 0x0208, the lowest id wins
+    assert!(out.contains("Breakpoint 1\nPC 0x0208"));
+    // Continuing runs at least one instruction, the condition is still true
+    assert!(out.contains("Breakpoint 2\nPC 0x020a"));
+    assert!(out.contains("No breakpoint 1"));
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn debugger_watchpoints() {
+    // LOAD V0 0x7b; LOAD VI 0x0300; BCD V0; REG.LOAD V2; JUMP 0x0208
+    let rom = [0x60, 0x7b, 0xa3, 0x00, 0xf0, 0x33, 0xf2, 0x65, 0x12, 0x08];
+    let script = "watch 0x302\nrwatch 0x300+3\nbl\nc\nd 1\nc\n";
+    let (chip8, out) = debug_session(&rom, script);
+    assert!(out.contains("   2  watch read 0x0300-0x0302"));
+    assert!(out.contains("Watchpoint 1: write 0x0302 0x00 -> 0x03 by 0x0204: BCD V0"));
+    assert!(out.contains("Watchpoint 2: read 0x0300 0x01 -> 0x01 by 0x0206: REG.LOAD V2"));
+    assert_eq!(chip8.pc(), 0x208);
+    assert_eq!(chip8.register(::cpu::Reg::V2), 3);
+}
+
+#[test]
+fn debugger_reverse_step() {
+    let (chip8, out) = debug_session(&SUBROUTINE_ROM, "rs\ns 4\nrs\nrs 2\ns\n");
+    assert!(out.contains("At the start of the recorded history"));
+    assert!(out.contains("0x020a: RETURN"));
+    assert_eq!(chip8.cycles(), 2);
+    assert_eq!(chip8.pc(), 0x208);
+    assert_eq!(chip8.register(::cpu::Reg::V1), 2);
+    assert_eq!(chip8.register(::cpu::Reg::V2), 0);
+    assert_eq!(chip8.stack().len(), 1);
+
+    // Going back keeps changes made by hand
+    let (chip8, out) = debug_session(&SUBROUTINE_ROM, "s 3\nset v5 9\ns\nrs 10\n");
+    assert!(out.contains("Reached the start of the recorded history"));
+    assert_eq!(chip8.cycles(), 0);
+    let (chip8, _) = debug_session(&SUBROUTINE_ROM, "s 3\nset v5 9\ns\nrs\n");
+    assert_eq!(chip8.cycles(), 3);
+    assert_eq!(chip8.register(::cpu::Reg::V5), 9);
+
+    // KEY.GET V0; KEY.GET V1; LOAD V2 0x01; JUMP 0x0206
+    // Holding a key gives it to the second KEY.GET only at the next frame
+    let rom = [0xf0, 0x0a, 0xf1, 0x0a, 0x62, 0x01, 0x12, 0x06];
+    let (chip8, _) = debug_session(&rom, "press 5\ns 12\nrs 3\n");
+    assert_eq!(chip8.cycles(), 9);
+    assert_eq!(chip8.pc(), 0x204);
+    assert_eq!(chip8.register(::cpu::Reg::V1), 5);
+    assert_eq!(chip8.register(::cpu::Reg::V2), 0);
+}
+
+#[test]
+fn debugger_reverse_continue() {
+    // LOAD V0 0x7b; LOAD VI 0x0300; BCD V0; ADD V0 0x01; JUMP 0x0204
+    let rom = [0x60, 0x7b, 0xa3, 0x00, 0xf0, 0x33, 0x70, 0x01, 0x12, 0x04];
+    let (chip8, out) = debug_session(&rom, "s 20\nwatch 0x302\nrc\nrc\n");
+    assert!(out.contains("Watchpoint 1: write 0x0302 0x07 -> 0x08 by 0x0204: BCD V0"));
+    assert!(out.contains("Watchpoint 1: write 0x0302 0x06 -> 0x07 by 0x0204: BCD V0"));
+    // Stopped before the write, not after it
+    assert_eq!(chip8.cycles(), 14);
+    assert_eq!(chip8.pc(), 0x204);
+    assert_eq!(chip8.register(::cpu::Reg::V0), 0x7f);
+    assert_eq!(chip8.read_memory(0x302).unwrap(), 6);
+
+    let script = "s 20\nb if v0 == 0x7d\nrc\nd 1\nrs\nrc\ns 2\n";
+    let (chip8, out) = debug_session(&rom, script);
+    assert!(out.contains("Breakpoint 1\nPC 0x0206"));
+    assert!(out.contains("Reached the start of the recorded history\nPC 0x0200"));
+    assert_eq!(chip8.cycles(), 2);
+    assert_eq!(chip8.register(::cpu::Reg::V0), 0x7b);
+    assert_eq!(chip8.index(), 0x300);
+}
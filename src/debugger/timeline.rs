@@ -0,0 +1,151 @@
+//
+// Rust Core Imports
+//
+use std::collections::VecDeque;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use emulator::{Chip8, Step};
+use rewind::{compress, decompress};
+use super::describe_watch;
+
+/// The past of a program run under the debugger, so it can be run backwards
+///
+/// A snapshot is kept every `interval` instructions, along with the keys each
+/// time they are set, since that also re-arms `KEY.GET`. Any earlier
+/// instruction is reached by loading the snapshot before it and replaying
+/// forward, which the seeded RNG and instruction counted timers make exact.
+pub struct Timeline {
+    interval: u64,
+    capacity: usize,
+    snapshots: VecDeque<(u64, Vec<u8>)>,
+    keys: Vec<(u64, [bool; 16])>,
+}
+
+impl Timeline {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        Timeline {
+            interval: if interval == 0 { 1 } else { interval },
+            capacity: if capacity == 0 { 1 } else { capacity },
+            snapshots: VecDeque::new(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Call before each instruction the debugger runs
+    pub fn record(&mut self, chip8: &Chip8) {
+        let cycle = chip8.cycles();
+        // Going forward again after going back replaces the old future
+        self.forget_after(cycle);
+        if self.snapshots.back().is_none_or(|&(last, _)| cycle >= last + self.interval) {
+            self.snapshot(chip8);
+        }
+    }
+
+    /// Set the keys held on `chip8`, so replays set them at the same point
+    pub fn set_keys(&mut self, chip8: &mut Chip8, keys: &[bool; 16]) {
+        self.forget_after(chip8.cycles());
+        chip8.set_keys(keys);
+        self.keys.push((chip8.cycles(), *keys));
+    }
+
+    /// Take a snapshot now, e.g. after the state was changed by hand
+    pub fn snapshot(&mut self, chip8: &Chip8) {
+        let cycle = chip8.cycles();
+        self.forget_after(cycle);
+        if self.snapshots.back().is_some_and(|&(last, _)| last == cycle) {
+            self.snapshots.pop_back();
+        }
+        self.snapshots.push_back((cycle, compress(&chip8.save_state())));
+        if self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+            // The snapshots hold the keys too, so older changes aren't needed
+            let oldest = self.start().unwrap_or(0);
+            self.keys.retain(|&(at, _)| at >= oldest);
+        }
+    }
+
+    fn forget_after(&mut self, cycle: u64) {
+        while self.snapshots.back().is_some_and(|&(at, _)| at > cycle) {
+            self.snapshots.pop_back();
+        }
+        self.keys.retain(|&(at, _)| at <= cycle);
+    }
+
+    /// The earliest instruction that can be gone back to
+    pub fn start(&self) -> Option<u64> {
+        self.snapshots.front().map(|&(at, _)| at)
+    }
+
+    /// Put `chip8` back to just before instruction `cycle` ran
+    pub fn travel(&self, chip8: &mut Chip8, cycle: u64) -> Result<()> {
+        self.replay(chip8, cycle, |_, _| {})
+    }
+
+    /// Find the last instruction before `cycle` where a breakpoint or
+    /// watchpoint stops the program, and describe why
+    ///
+    /// For a watchpoint that is the instruction which set it off. `chip8` is
+    /// left somewhere in the past, so `travel` it to where it should be.
+    pub fn last_stop(&self, chip8: &mut Chip8, cycle: u64) -> Result<Option<(u64, String)>> {
+        let mut found = None;
+        self.replay(chip8, cycle, |chip8, step| {
+            match step {
+                None => {
+                    if let Some(id) = chip8.breakpoint_hit() {
+                        found = Some((chip8.cycles(), format!("Breakpoint {}", id)));
+                    }
+                }
+                Some(step) => {
+                    if step.watch.is_some() {
+                        found = Some((chip8.cycles() - 1, describe_watch(step)));
+                    }
+                }
+            }
+        })?;
+        Ok(found)
+    }
+
+    // Load the snapshot before `cycle` and run up to it, calling `visit`
+    // before each instruction and again after it with the step
+    fn replay<F>(&self, chip8: &mut Chip8, cycle: u64, mut visit: F) -> Result<()>
+        where F: FnMut(&Chip8, Option<&Step>)
+    {
+        let &(start, ref state) = self.snapshots
+            .iter()
+            .rev()
+            .find(|&&(at, _)| at <= cycle)
+            .ok_or_else(|| {
+                let start = self.start().map_or("No history recorded yet".to_string(),
+                                                |start| format!("History starts at cycle {}", start));
+                Error::from(ErrorKind::DebuggerCommand(start))
+            })?;
+        // Going back in time shouldn't lose the window's rewind history
+        chip8.restore_state(&decompress(state))?;
+        // Don't write the same instructions to a trace twice
+        let tracer = chip8.stop_trace();
+        let mut keys = self.keys.iter().skip_while(|&&(at, _)| at < start).peekable();
+        let mut result = Ok(());
+        while chip8.cycles() < cycle {
+            while keys.peek().is_some_and(|&&(at, _)| at <= chip8.cycles()) {
+                chip8.set_keys(&keys.next().unwrap().1);
+            }
+            visit(chip8, None);
+            match chip8.step() {
+                Ok(step) => {
+                    visit(chip8, Some(&step));
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        if let Some(tracer) = tracer {
+            chip8.trace(tracer);
+        }
+        result
+    }
+}
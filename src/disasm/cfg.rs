@@ -0,0 +1,391 @@
+//
+// Rust Core Imports
+//
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::str::FromStr;
+
+//
+// Third Party Imports
+//
+use serde_json::Value;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use cpu::Opcode;
+use emulator::{MemAddr, PROGRAM_START};
+use super::{Disassembly, is_skip};
+
+/// How a control flow graph is written out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    /// Graphviz, for `dot -Tsvg`
+    Dot,
+    /// The blocks and subroutines as a JSON object
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "json" => Ok(GraphFormat::Json),
+            _ => bail!(ErrorKind::UnknownOption("graph format", s.to_string())),
+        }
+    }
+}
+
+/// Why control can pass from one block to another
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+    /// Running on into the next instruction, including when a skip isn't taken
+    Next,
+    /// A `JUMP`
+    Jump,
+    /// A skip instruction that is taken
+    Skip,
+    /// One of the entries of a `LOAD VI V0+ADDR` jump table
+    Table,
+}
+
+impl Edge {
+    fn name(&self) -> &'static str {
+        match *self {
+            Edge::Next => "next",
+            Edge::Jump => "jump",
+            Edge::Skip => "skip",
+            Edge::Table => "table",
+        }
+    }
+}
+
+/// A run of instructions that is only entered at the top and left at the bottom
+///
+/// A `CALL` doesn't end a block, as it comes back to the next instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// The address of the first instruction
+    pub start: MemAddr,
+    /// The address after the last instruction
+    pub end: MemAddr,
+    /// Each instruction, and its address
+    pub instructions: Vec<(MemAddr, Opcode)>,
+    /// The blocks control can go to next
+    pub successors: Vec<(MemAddr, Edge)>,
+    /// The subroutines called from this block
+    pub calls: Vec<MemAddr>,
+}
+
+impl Block {
+    fn last(&self) -> Opcode {
+        self.instructions[self.instructions.len() - 1].1
+    }
+}
+
+/// The blocks reached from a `CALL` target, without following other calls
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subroutine {
+    /// The first block
+    pub entry: MemAddr,
+    /// Every block in the subroutine
+    pub blocks: Vec<MemAddr>,
+    /// The blocks that end with a `RETURN`
+    pub exits: Vec<MemAddr>,
+    /// The subroutines it calls
+    pub calls: Vec<MemAddr>,
+}
+
+impl Subroutine {
+    /// `main` for the program itself, otherwise named after the entry
+    pub fn name(&self) -> String {
+        if self.entry == PROGRAM_START as MemAddr {
+            "main".to_string()
+        } else {
+            format!("sub_{:04x}", self.entry)
+        }
+    }
+}
+
+/// The basic blocks and call graph of a `Disassembly`
+#[derive(Debug, Clone)]
+pub struct ControlFlow {
+    blocks: BTreeMap<MemAddr, Block>,
+    subroutines: BTreeMap<MemAddr, Subroutine>,
+}
+
+// The addresses a jump table can go to
+fn table(instructions: &BTreeMap<MemAddr, Opcode>, start: MemAddr) -> Vec<MemAddr> {
+    let mut entries = Vec::new();
+    let mut entry = start;
+    while let Some(&Opcode::JumpAddr(_)) = instructions.get(&entry) {
+        entries.push(entry);
+        entry = entry.wrapping_add(2);
+    }
+    entries
+}
+
+impl ControlFlow {
+    /// Split the instructions found by `disassembly` into blocks
+    pub fn new(disassembly: &Disassembly) -> Self {
+        let instructions = disassembly.instructions();
+        let next = |addr: MemAddr, opcode: Opcode| addr.wrapping_add(opcode.len());
+
+        // Where control can arrive other than from the instruction before
+        let mut leaders = BTreeSet::new();
+        leaders.insert(PROGRAM_START as MemAddr);
+        for (&addr, &opcode) in instructions {
+            let after = next(addr, opcode);
+            match opcode {
+                Opcode::JumpAddr(target) => {
+                    leaders.insert(target);
+                    leaders.insert(after);
+                }
+                Opcode::CallAddr(target) => {
+                    leaders.insert(target);
+                }
+                Opcode::JumpAddV0(target) => {
+                    leaders.extend(table(instructions, target));
+                    leaders.insert(after);
+                }
+                Opcode::Return | Opcode::Exit => {
+                    leaders.insert(after);
+                }
+                _ if is_skip(opcode) => {
+                    leaders.insert(after);
+                    if let Some(skipped) = instructions.get(&after) {
+                        leaders.insert(next(after, *skipped));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut blocks: BTreeMap<MemAddr, Block> = BTreeMap::new();
+        let mut current: Option<Block> = None;
+        for (&addr, &opcode) in instructions {
+            let joined = current.as_ref().is_some_and(|block| block.end == addr) &&
+                         !leaders.contains(&addr);
+            if !joined {
+                if let Some(block) = current.take() {
+                    blocks.insert(block.start, block);
+                }
+                current = Some(Block {
+                    start: addr,
+                    end: addr,
+                    instructions: Vec::new(),
+                    successors: Vec::new(),
+                    calls: Vec::new(),
+                });
+            }
+            let block = current.as_mut().unwrap();
+            block.instructions.push((addr, opcode));
+            block.end = next(addr, opcode);
+            if let Opcode::CallAddr(target) = opcode {
+                block.calls.push(target);
+            }
+        }
+        if let Some(block) = current {
+            blocks.insert(block.start, block);
+        }
+
+        let starts: BTreeSet<MemAddr> = blocks.keys().cloned().collect();
+        for block in blocks.values_mut() {
+            let after = block.end;
+            let successors = match block.last() {
+                Opcode::JumpAddr(target) => vec![(target, Edge::Jump)],
+                Opcode::JumpAddV0(target) => {
+                    table(instructions, target).into_iter().map(|e| (e, Edge::Table)).collect()
+                }
+                Opcode::Return | Opcode::Exit => Vec::new(),
+                opcode if is_skip(opcode) => {
+                    let skipped = instructions.get(&after).map_or(2, |opcode| opcode.len());
+                    vec![(after, Edge::Next), (after.wrapping_add(skipped), Edge::Skip)]
+                }
+                _ => vec![(after, Edge::Next)],
+            };
+            block.successors = successors.into_iter()
+                .filter(|&(to, _)| starts.contains(&to))
+                .collect();
+        }
+
+        let mut flow = ControlFlow {
+            blocks: blocks,
+            subroutines: BTreeMap::new(),
+        };
+        let entries = ::std::iter::once(PROGRAM_START as MemAddr)
+            .chain(disassembly.subroutines().iter().cloned());
+        for entry in entries {
+            if flow.blocks.contains_key(&entry) {
+                let subroutine = flow.subroutine(entry);
+                flow.subroutines.insert(entry, subroutine);
+            }
+        }
+        flow
+    }
+
+    fn subroutine(&self, entry: MemAddr) -> Subroutine {
+        let mut seen = BTreeSet::new();
+        let mut work = vec![entry];
+        while let Some(addr) = work.pop() {
+            if seen.insert(addr) {
+                work.extend(self.blocks[&addr].successors.iter().map(|&(to, _)| to));
+            }
+        }
+        let blocks: Vec<&Block> = seen.iter().map(|addr| &self.blocks[addr]).collect();
+        let calls: BTreeSet<MemAddr> = blocks.iter()
+            .flat_map(|block| block.calls.iter().cloned())
+            .collect();
+        Subroutine {
+            entry: entry,
+            blocks: seen.iter().cloned().collect(),
+            exits: blocks.iter()
+                .filter(|block| block.last() == Opcode::Return)
+                .map(|block| block.start)
+                .collect(),
+            calls: calls.into_iter().collect(),
+        }
+    }
+
+    /// Every block, by start address
+    pub fn blocks(&self) -> &BTreeMap<MemAddr, Block> {
+        &self.blocks
+    }
+
+    /// `main` and every subroutine that is called, by entry address
+    pub fn subroutines(&self) -> &BTreeMap<MemAddr, Subroutine> {
+        &self.subroutines
+    }
+
+    /// Write the graph in `format`
+    pub fn write(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.dot(),
+            GraphFormat::Json => format!("{:#}\n", self.json()),
+        }
+    }
+
+    /// A Graphviz digraph, with a cluster for each subroutine
+    ///
+    /// Blocks that end in `RETURN` are drawn in bold, and calls are dashed.
+    pub fn dot(&self) -> String {
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph cfg {{");
+        let _ = writeln!(dot, "    node [shape=box, fontname=\"monospace\"];");
+        let mut drawn = BTreeSet::new();
+        for subroutine in self.subroutines.values() {
+            let _ = writeln!(dot, "    subgraph cluster_{:04x} {{", subroutine.entry);
+            let _ = writeln!(dot, "        label=\"{}\";", subroutine.name());
+            for addr in &subroutine.blocks {
+                // A block shared by two subroutines is drawn in the first
+                if !drawn.insert(*addr) {
+                    continue;
+                }
+                let block = &self.blocks[addr];
+                let mut label = String::new();
+                for &(addr, opcode) in &block.instructions {
+                    let _ = write!(label, "0x{:04x}  {}\\l", addr, opcode);
+                }
+                let style = if subroutine.exits.contains(addr) { ", style=bold" } else { "" };
+                let _ = writeln!(dot, "        b{:04x} [label=\"{}\"{}];", addr, label, style);
+            }
+            let _ = writeln!(dot, "    }}");
+        }
+        for block in self.blocks.values().filter(|block| !drawn.contains(&block.start)) {
+            let _ = writeln!(dot, "    b{:04x} [label=\"0x{:04x}\"];", block.start, block.start);
+        }
+        for block in self.blocks.values() {
+            for &(to, edge) in &block.successors {
+                let label = match edge {
+                    Edge::Next | Edge::Jump => String::new(),
+                    edge => format!(" [label=\"{}\"]", edge.name()),
+                };
+                let _ = writeln!(dot, "    b{:04x} -> b{:04x}{};", block.start, to, label);
+            }
+            for to in block.calls.iter().filter(|to| self.blocks.contains_key(to)) {
+                let _ = writeln!(dot,
+                                 "    b{:04x} -> b{:04x} [style=dashed, label=\"call\"];",
+                                 block.start,
+                                 to);
+            }
+        }
+        let _ = writeln!(dot, "}}");
+        dot
+    }
+
+    /// `{"blocks": [...], "subroutines": [...]}`, with addresses as numbers
+    pub fn json(&self) -> Value {
+        let blocks: Vec<Value> = self.blocks
+            .values()
+            .map(|block| {
+                let instructions: Vec<Value> = block.instructions
+                    .iter()
+                    .map(|&(addr, opcode)| json!({"addr": addr, "opcode": opcode.to_string()}))
+                    .collect();
+                let successors: Vec<Value> = block.successors
+                    .iter()
+                    .map(|&(to, edge)| json!({"to": to, "edge": edge.name()}))
+                    .collect();
+                json!({
+                    "start": block.start,
+                    "end": block.end,
+                    "instructions": instructions,
+                    "successors": successors,
+                    "calls": block.calls,
+                })
+            })
+            .collect();
+        let subroutines: Vec<Value> = self.subroutines
+            .values()
+            .map(|subroutine| {
+                json!({
+                    "name": subroutine.name(),
+                    "entry": subroutine.entry,
+                    "blocks": subroutine.blocks,
+                    "exits": subroutine.exits,
+                    "calls": subroutine.calls,
+                })
+            })
+            .collect();
+        json!({"blocks": blocks, "subroutines": subroutines})
+    }
+}
+
+#[test]
+fn control_flow() {
+    let rom = [0x22, 0x08, // CALL 0x0208
+               0x30, 0x01, // SKIP.EQ V0 0x01
+               0x12, 0x00, // JUMP 0x0200
+               0x00, 0xfd, // EXIT
+               0x70, 0x01, // MATH.ADD V0 0x01
+               0x00, 0xee]; // RETURN
+    let flow = ControlFlow::new(&Disassembly::new(&rom));
+    let starts: Vec<MemAddr> = flow.blocks().keys().cloned().collect();
+    assert_eq!(starts, vec![0x200, 0x204, 0x206, 0x208]);
+    assert_eq!(flow.blocks()[&0x200].successors,
+               vec![(0x204, Edge::Next), (0x206, Edge::Skip)]);
+    assert_eq!(flow.blocks()[&0x200].calls, vec![0x208]);
+    assert_eq!(flow.blocks()[&0x204].successors, vec![(0x200, Edge::Jump)]);
+
+    let main = &flow.subroutines()[&0x200];
+    assert_eq!(main.blocks, vec![0x200, 0x204, 0x206]);
+    assert!(main.exits.is_empty());
+    assert_eq!(main.calls, vec![0x208]);
+    let sub = &flow.subroutines()[&0x208];
+    assert_eq!(sub.name(), "sub_0208");
+    assert_eq!(sub.exits, vec![0x208]);
+
+    let dot = flow.write(GraphFormat::Dot);
+    assert!(dot.contains("    subgraph cluster_0208 {\n        label=\"sub_0208\";\n"));
+    assert!(dot.contains("b0208 [label=\"0x0208  MATH.ADD V0 0x01\\l0x020a  RETURN\\l\", \
+                          style=bold];"));
+    assert!(dot.contains("    b0200 -> b0206 [label=\"skip\"];\n"));
+    assert!(dot.contains("    b0200 -> b0208 [style=dashed, label=\"call\"];\n"));
+
+    let json = flow.json();
+    assert_eq!(json["blocks"][0]["successors"][1], json!({"to": 0x206, "edge": "skip"}));
+    assert_eq!(json["subroutines"][1]["exits"], json!([0x208]));
+    assert_eq!(json["subroutines"][0]["name"], json!("main"));
+}
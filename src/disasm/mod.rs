@@ -0,0 +1,416 @@
+//
+// Rust Core Imports
+//
+use std::collections::{BTreeMap, BTreeSet};
+
+//
+// This Crate Imports
+//
+use coverage::{Access, Coverage};
+use cpu::{decode, Opcode};
+use emulator::{MemAddr, PROGRAM_START};
+
+//
+// Declare sub modules
+//
+mod cfg;
+mod source;
+mod summary;
+
+//
+// Public Exports
+//
+pub use self::cfg::{ControlFlow, Block, Edge, Subroutine, GraphFormat};
+pub use self::summary::CoverageSummary;
+
+/// What a byte of a ROM was found to be used for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Usage {
+    /// Not reached by following the code
+    Unknown,
+    /// The first byte of an instruction
+    Code,
+    /// The rest of an instruction
+    Operand,
+    /// Read or written through `I`
+    Data,
+    /// Drawn as part of a sprite
+    Sprite,
+}
+
+// Why an address is labeled, the first kind wins when there are several
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    Sub,
+    Table,
+    Jump,
+    Sprite,
+    Data,
+}
+
+impl Label {
+    fn prefix(&self) -> &'static str {
+        match *self {
+            Label::Sub => "sub",
+            Label::Table => "table",
+            Label::Jump => "label",
+            Label::Sprite => "sprite",
+            Label::Data => "data",
+        }
+    }
+}
+
+/// A ROM taken apart by following its code from 0x200
+///
+/// Jumps, calls, both sides of skips and `LOAD VI V0+ADDR` tables of jumps
+/// are followed. Memory that `I` points at when it is drawn, or read and
+/// written, is marked as sprites or data.
+///
+/// With a `Coverage` map from running the ROM, code is also followed from
+/// everything that was run, which finds what computed jumps and
+/// self-modifying code reach, and memory is marked by how it was really used.
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    rom: Vec<u8>,
+    usage: Vec<Usage>,
+    instructions: BTreeMap<MemAddr, Opcode>,
+    labels: BTreeMap<MemAddr, Label>,
+    subroutines: BTreeSet<MemAddr>,
+    // Sprites rows that are 16 pixels wide
+    wide: BTreeSet<MemAddr>,
+    coverage: Option<Coverage>,
+    // Instructions only found by following the coverage map
+    found_by_running: BTreeSet<MemAddr>,
+}
+
+fn is_skip(opcode: Opcode) -> bool {
+    matches!(opcode,
+             Opcode::SkipEqByte(..) |
+             Opcode::SkipNEqByte(..) |
+             Opcode::SkipEqReg(..) |
+             Opcode::SkipNEqReg(..) |
+             Opcode::KeyEqSkip(_) |
+             Opcode::KeyNEqSkip(_))
+}
+
+impl Disassembly {
+    /// Follow the code in `rom`, as it would be loaded at 0x200
+    pub fn new(rom: &[u8]) -> Self {
+        let mut disassembly = Disassembly {
+            rom: rom.to_vec(),
+            usage: vec![Usage::Unknown; rom.len()],
+            instructions: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            subroutines: BTreeSet::new(),
+            wide: BTreeSet::new(),
+            coverage: None,
+            found_by_running: BTreeSet::new(),
+        };
+        disassembly.follow(PROGRAM_START as MemAddr);
+        disassembly.drop_inner_labels();
+        disassembly
+    }
+
+    /// Follow the code in `rom`, and everything `coverage` saw being run
+    pub fn with_coverage(rom: &[u8], coverage: &Coverage) -> Self {
+        let found: BTreeSet<MemAddr> = Disassembly::new(rom).instructions.keys().cloned().collect();
+        let mut disassembly = Disassembly {
+            rom: rom.to_vec(),
+            usage: vec![Usage::Unknown; rom.len()],
+            instructions: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            subroutines: BTreeSet::new(),
+            wide: BTreeSet::new(),
+            coverage: Some(coverage.clone()),
+            found_by_running: BTreeSet::new(),
+        };
+        // What was run comes first, so a guess can't take its place
+        for addr in coverage.fetched() {
+            if disassembly.offset(addr).is_some() && !disassembly.instructions.contains_key(&addr) {
+                // Reached some way the code doesn't show, like a computed jump
+                if !found.contains(&addr) {
+                    disassembly.label(addr, Label::Jump);
+                }
+                disassembly.follow(addr);
+            }
+        }
+        disassembly.follow(PROGRAM_START as MemAddr);
+        for a in PROGRAM_START..PROGRAM_START + rom.len() {
+            let addr = a as MemAddr;
+            if coverage.has(addr, Access::Draw) {
+                disassembly.mark(addr, 1, Usage::Sprite);
+            } else if coverage.has(addr, Access::Read) || coverage.has(addr, Access::Write) {
+                disassembly.mark(addr, 1, Usage::Data);
+            }
+        }
+        disassembly.found_by_running = disassembly.instructions
+            .keys()
+            .cloned()
+            .filter(|addr| !found.contains(addr))
+            .collect();
+        disassembly.drop_inner_labels();
+        disassembly
+    }
+
+    /// What the byte at `addr` is used for
+    pub fn usage(&self, addr: MemAddr) -> Usage {
+        self.offset(addr).map_or(Usage::Unknown, |i| self.usage[i])
+    }
+
+    /// Every instruction that was found, by address
+    pub fn instructions(&self) -> &BTreeMap<MemAddr, Opcode> {
+        &self.instructions
+    }
+
+    /// The start of every subroutine that is called
+    pub fn subroutines(&self) -> &BTreeSet<MemAddr> {
+        &self.subroutines
+    }
+
+    /// The coverage map this was made with, if any
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    fn offset(&self, addr: MemAddr) -> Option<usize> {
+        (addr as usize)
+            .checked_sub(PROGRAM_START)
+            .and_then(|i| if i < self.rom.len() { Some(i) } else { None })
+    }
+
+    fn halfword(&self, addr: MemAddr) -> Option<u16> {
+        let i = self.offset(addr)?;
+        let low = *self.rom.get(i + 1)?;
+        Some((self.rom[i] as u16) << 8 | low as u16)
+    }
+
+    fn decode(&self, addr: MemAddr) -> Option<Opcode> {
+        let instr = self.halfword(addr)?;
+        let next = self.halfword(addr.wrapping_add(2));
+        match next {
+            Some(next) => decode(instr, next).ok(),
+            None => {
+                decode(instr, 0)
+                    .ok()
+                    .and_then(|opcode| if opcode.len() == 2 { Some(opcode) } else { None })
+            }
+        }
+    }
+
+    fn label(&mut self, addr: MemAddr, kind: Label) {
+        if self.offset(addr).is_some() {
+            let label = self.labels.entry(addr).or_insert(kind);
+            if kind < *label {
+                *label = kind;
+            }
+        }
+    }
+
+    // Mark `len` bytes from `addr` as data, leaving any code alone
+    fn mark(&mut self, addr: MemAddr, len: usize, usage: Usage) {
+        for a in addr as usize..addr as usize + len {
+            if let Some(i) = self.offset(a as MemAddr) {
+                if self.usage[i] != Usage::Code && self.usage[i] != Usage::Operand {
+                    self.usage[i] = usage;
+                }
+            }
+        }
+    }
+
+    // Where an instruction reads or writes through I
+    fn access(&mut self, index: MemAddr, opcode: Opcode) {
+        let (len, usage) = match opcode {
+            Opcode::Draw(_, _, 0) => (32, Usage::Sprite),
+            Opcode::Draw(_, _, n) => (n as usize, Usage::Sprite),
+            Opcode::BCD(_) => (3, Usage::Data),
+            Opcode::RegDump(x) |
+            Opcode::RegLoad(x) => (x as usize + 1, Usage::Data),
+            Opcode::RegRangeSave(x, y) |
+            Opcode::RegRangeLoad(x, y) => ((x as isize - y as isize).unsigned_abs() + 1, Usage::Data),
+            Opcode::AudioLoad => (16, Usage::Data),
+            _ => return,
+        };
+        if usage == Usage::Sprite {
+            self.label(index, Label::Sprite);
+            if len == 32 {
+                for row in 0..16 {
+                    self.wide.insert(index.wrapping_add(2 * row));
+                }
+            }
+        } else {
+            self.label(index, Label::Data);
+        }
+        self.mark(index, len, usage);
+    }
+
+    fn follow(&mut self, start: MemAddr) {
+        // Each path also carries where I points, if that is known
+        let mut paths = vec![(start, None)];
+        while let Some((mut pc, mut index)) = paths.pop() {
+            while let Some(opcode) = self.decode(pc) {
+                let len = opcode.len() as usize;
+                let i = self.offset(pc).unwrap();
+                let taken = self.usage[i..i + len]
+                    .iter()
+                    .any(|&u| u == Usage::Code || u == Usage::Operand);
+                if taken {
+                    break;
+                }
+                self.usage[i] = Usage::Code;
+                for u in &mut self.usage[i + 1..i + len] {
+                    *u = Usage::Operand;
+                }
+                self.instructions.insert(pc, opcode);
+                if let Some(index) = index {
+                    self.access(index, opcode);
+                }
+                let next = pc.wrapping_add(len as MemAddr);
+                index = match opcode {
+                    Opcode::MemLoad(addr) |
+                    Opcode::LongMemLoad(addr) => Some(addr),
+                    Opcode::MemAdd(_) |
+                    Opcode::MemSprite(_) |
+                    Opcode::BigSprite(_) |
+                    Opcode::RegDump(_) |
+                    Opcode::RegLoad(_) => None,
+                    _ => index,
+                };
+                match opcode {
+                    Opcode::JumpAddr(addr) => {
+                        self.label(addr, Label::Jump);
+                        paths.push((addr, index));
+                        break;
+                    }
+                    Opcode::CallAddr(addr) => {
+                        self.label(addr, Label::Sub);
+                        self.subroutines.insert(addr);
+                        paths.push((addr, index));
+                        // The subroutine could have changed I
+                        index = None;
+                    }
+                    Opcode::JumpAddV0(addr) => {
+                        // Usually a table of jumps, one for each value of V0
+                        self.label(addr, Label::Table);
+                        let mut entry = addr;
+                        while let Some(Opcode::JumpAddr(_)) = self.decode(entry) {
+                            paths.push((entry, index));
+                            entry = entry.wrapping_add(2);
+                        }
+                        break;
+                    }
+                    Opcode::Return | Opcode::Exit => break,
+                    _ if is_skip(opcode) => {
+                        let skipped = self.decode(next).map_or(2, |opcode| opcode.len());
+                        paths.push((next.wrapping_add(skipped), index));
+                    }
+                    _ => {}
+                }
+                pc = next;
+            }
+        }
+    }
+
+    // Labels are only written at the start of an instruction or data row
+    fn drop_inner_labels(&mut self) {
+        let inner: Vec<MemAddr> = self.labels
+            .keys()
+            .cloned()
+            .filter(|&addr| self.usage(addr) == Usage::Operand)
+            .collect();
+        for addr in inner {
+            self.labels.remove(&addr);
+        }
+    }
+
+    fn label_name(&self, addr: MemAddr) -> Option<String> {
+        self.labels.get(&addr).map(|label| format!("{}_{:04x}", label.prefix(), addr))
+    }
+}
+
+#[test]
+fn follow_code() {
+    let rom = [0x00, 0xe0, // CLS
+               0xa2, 0x12, // LOAD VI 0x0212
+               0x22, 0x0e, // CALL 0x020e
+               0x30, 0x01, // SKIP.EQ V0 0x01
+               0x12, 0x04, // JUMP 0x0204
+               0xb2, 0x17, // LOAD VI V0+0x0217
+               0xff, 0xff, // never reached
+               0xd0, 0x15, // DRAW V0 V1 0x05
+               0x00, 0xee, // RETURN
+               0x20, 0x60, 0x20, 0x20, 0x70, // the digit 1
+               0x12, 0x1b, // JUMP 0x021b, at an odd address
+               0x12, 0x1d, // JUMP 0x021d
+               0x00, 0xfd, // EXIT
+               0x12, 0x1d]; // JUMP 0x021d
+    let disassembly = Disassembly::new(&rom);
+    assert_eq!(disassembly.usage(0x0204), Usage::Code);
+    assert_eq!(disassembly.usage(0x0205), Usage::Operand);
+    assert_eq!(disassembly.usage(0x020c), Usage::Unknown);
+    assert_eq!(disassembly.usage(0x0216), Usage::Sprite);
+    assert_eq!(disassembly.usage(0x021b), Usage::Code);
+    assert_eq!(disassembly.instructions().len(), 12);
+    assert!(disassembly.subroutines().contains(&0x020e));
+
+    let source = disassembly.source();
+    for line in &["    LOAD VI sprite_0212             ; 0x0202\n",
+                  "label_0204:\n    CALL sub_020e",
+                  "    LOAD VI V0+table_0217           ; 0x020a\n",
+                  "    db 0xff, 0xff                   ; 0x020c\n",
+                  "sprite_0212:\n    db 0b00100000                   ; ..#.....\n",
+                  "table_0217:\n    JUMP label_021b",
+                  "label_021d:\n    JUMP label_021d"] {
+        assert!(source.contains(line), "{} not in\n{}", line, source);
+    }
+    assert_eq!(::asm::assemble(&source, "rom.asm").unwrap().bytes(), &rom[..]);
+}
+
+#[test]
+fn follow_coverage() {
+    let rom = [0x60, 0x02, // LOAD V0 0x02
+               0xb2, 0x06, // LOAD VI V0+0x0206, to 0x0208
+               0x00, 0x00, 0x00, 0x00, // never reached
+               0xa2, 0x10, // LOAD VI 0x0210
+               0xf0, 0x65, // REG.LOAD V0
+               0x12, 0x0c, // JUMP 0x020c
+               0x00, 0x00, // never reached
+               0x42]; // read by REG.LOAD
+    let mut chip8 = ::emulator::Chip8::init(None);
+    chip8.load_bytes(&rom).unwrap();
+    chip8.record_coverage();
+    chip8.run_cycles(6).unwrap();
+    let coverage = chip8.stop_coverage().unwrap();
+
+    assert_eq!(Disassembly::new(&rom).usage(0x0208), Usage::Unknown);
+    let disassembly = Disassembly::with_coverage(&rom, &coverage);
+    assert_eq!(disassembly.usage(0x0208), Usage::Code);
+    assert_eq!(disassembly.usage(0x0210), Usage::Data);
+
+    let source = disassembly.source();
+    for line in &["    LOAD V0 0x02                    ; 0x0200 run\n",
+                  "table_0206:\n    db 0x00, 0x00                   ; 0x0206 not reached\n",
+                  "label_0208:\n    LOAD VI data_0210               ; 0x0208 run\n",
+                  "data_0210:\n    db 0x42                         ; 0x0210 read\n"] {
+        assert!(source.contains(line), "{} not in\n{}", line, source);
+    }
+    assert_eq!(::asm::assemble(&source, "rom.asm").unwrap().bytes(), &rom[..]);
+
+    let summary = disassembly.summary().unwrap();
+    assert_eq!((summary.bytes, summary.run, summary.data, summary.untouched()), (17, 10, 1, 6));
+    assert_eq!((summary.instructions, summary.instructions_run, summary.found_by_running),
+               (5, 5, 3));
+    assert!(summary.to_string().contains("  run           10   58.8%\n"));
+    assert!(Disassembly::new(&rom).summary().is_none());
+}
+
+#[test]
+fn disassemble_full_memory() {
+    // JUMP 0x0200, then zeros to the end of XO-CHIP memory
+    let mut rom = vec![0; 0x10000 - PROGRAM_START];
+    rom[0] = 0x12;
+    let disassembly = Disassembly::new(&rom);
+    let source = disassembly.source();
+    assert!(source.starts_with("label_0200:\n    JUMP label_0200"));
+    assert!(source.ends_with("    db 0x00, 0x00, 0x00, 0x00, 0x00, 0x00; 0xfffa\n"));
+    assert_eq!(::asm::assemble(&source, "rom.asm").unwrap().bytes(), &rom[..]);
+}
@@ -0,0 +1,119 @@
+//
+// Rust Core Imports
+//
+use std::fmt::Write;
+
+//
+// This Crate Imports
+//
+use coverage::Access;
+use cpu::Opcode;
+use emulator::{MemAddr, PROGRAM_START};
+use super::{Disassembly, Usage};
+
+const DATA_COLS: usize = 8;
+
+// Draw a sprite row as it appears on screen
+fn pixels(row: &[u8]) -> String {
+    row.iter()
+        .flat_map(|byte| (0..8).map(move |bit| if byte << bit & 0x80 != 0 { '#' } else { '.' }))
+        .collect()
+}
+
+impl Disassembly {
+    // An instruction, with labels in place of addresses that have one
+    fn instruction(&self, opcode: Opcode) -> String {
+        let labeled = match opcode {
+            Opcode::JumpAddr(addr) => self.label_name(addr).map(|l| format!("JUMP {}", l)),
+            Opcode::CallAddr(addr) => self.label_name(addr).map(|l| format!("CALL {}", l)),
+            Opcode::MemLoad(addr) => self.label_name(addr).map(|l| format!("LOAD VI {}", l)),
+            Opcode::JumpAddV0(addr) => {
+                self.label_name(addr).map(|l| format!("LOAD VI V0+{}", l))
+            }
+            Opcode::LongMemLoad(addr) => {
+                self.label_name(addr).map(|l| format!("LOAD.LONG VI {}", l))
+            }
+            _ => None,
+        };
+        labeled.unwrap_or_else(|| opcode.to_string())
+    }
+
+    // How the run used `addr`, when there is a coverage map
+    fn note(&self, addr: MemAddr) -> Option<&'static str> {
+        let coverage = self.coverage.as_ref()?;
+        let has = |access| coverage.has(addr, access);
+        Some(if has(Access::Fetch) && has(Access::Write) {
+            "run, modified"
+        } else if has(Access::Fetch) {
+            "run"
+        } else if has(Access::Draw) {
+            "drawn"
+        } else if has(Access::Read) && has(Access::Write) {
+            "read, written"
+        } else if has(Access::Read) {
+            "read"
+        } else if has(Access::Write) {
+            "written"
+        } else {
+            "not reached"
+        })
+    }
+
+    /// Source that `assemble` turns back into the same ROM
+    ///
+    /// Code is labeled and commented with its address. Anything else is
+    /// written as `db` rows, with a picture of each sprite row beside it.
+    /// With a coverage map, each row also says how the run used it.
+    pub fn source(&self) -> String {
+        let mut source = String::new();
+        let mut i = 0;
+        while i < self.rom.len() {
+            let addr = (PROGRAM_START + i) as MemAddr;
+            if let Some(label) = self.label_name(addr) {
+                let _ = writeln!(source, "{}:", label);
+            }
+            let (text, len) = match self.usage[i] {
+                Usage::Code => {
+                    let opcode = self.instructions[&addr];
+                    (self.instruction(opcode), opcode.len() as usize)
+                }
+                Usage::Sprite => {
+                    let wide = self.wide.contains(&addr) &&
+                               self.usage(addr.wrapping_add(1)) == Usage::Sprite &&
+                               !self.labels.contains_key(&addr.wrapping_add(1));
+                    let row = &self.rom[i..i + if wide { 2 } else { 1 }];
+                    let bytes: Vec<String> = row.iter().map(|b| format!("0b{:08b}", b)).collect();
+                    let _ = writeln!(source,
+                                     "    {:32}; {}{}",
+                                     format!("db {}", bytes.join(", ")),
+                                     pixels(row),
+                                     self.note(addr).map_or(String::new(), |n| format!(" {}", n)));
+                    i += row.len();
+                    continue;
+                }
+                usage => {
+                    // A row of bytes used the same way, up to the next label
+                    let mut len = 1;
+                    while len < DATA_COLS && i + len < self.rom.len() &&
+                          self.usage[i + len] == usage &&
+                          self.note(addr.wrapping_add(len as MemAddr)) == self.note(addr) &&
+                          !self.labels.contains_key(&addr.wrapping_add(len as MemAddr)) {
+                        len += 1;
+                    }
+                    let bytes: Vec<String> = self.rom[i..i + len]
+                        .iter()
+                        .map(|b| format!("0x{:02x}", b))
+                        .collect();
+                    (format!("db {}", bytes.join(", ")), len)
+                }
+            };
+            let _ = writeln!(source,
+                             "    {:32}; 0x{:04x}{}",
+                             text,
+                             addr,
+                             self.note(addr).map_or(String::new(), |n| format!(" {}", n)));
+            i += len;
+        }
+        source
+    }
+}
@@ -0,0 +1,96 @@
+//
+// Rust Core Imports
+//
+use std::fmt;
+
+//
+// This Crate Imports
+//
+use coverage::Access;
+use emulator::{MemAddr, PROGRAM_START};
+use super::Disassembly;
+
+/// How much of a ROM a run used
+///
+/// Each byte is counted once, as run code before sprites, and sprites before
+/// data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoverageSummary {
+    /// Bytes in the ROM
+    pub bytes: usize,
+    /// Bytes of instructions that were run
+    pub run: usize,
+    /// Bytes drawn as sprites
+    pub sprites: usize,
+    /// Bytes read or written as data
+    pub data: usize,
+    /// Instructions found, by following the code and the coverage map
+    pub instructions: usize,
+    /// Instructions that were run
+    pub instructions_run: usize,
+    /// Instructions that were only found by following what was run
+    pub found_by_running: usize,
+}
+
+impl CoverageSummary {
+    /// Bytes that the run never touched
+    pub fn untouched(&self) -> usize {
+        self.bytes - self.run - self.sprites - self.data
+    }
+}
+
+// `part` as a percentage of `whole`
+fn percent(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        100.0 * part as f64 / whole as f64
+    }
+}
+
+impl fmt::Display for CoverageSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} bytes", self.bytes)?;
+        for &(name, count) in &[("run", self.run),
+                                ("sprites", self.sprites),
+                                ("data", self.data),
+                                ("untouched", self.untouched())] {
+            writeln!(f, "  {:10}{:6}  {:5.1}%", name, count, percent(count, self.bytes))?;
+        }
+        writeln!(f,
+                 "{} of {} instructions run, {} only found by running",
+                 self.instructions_run,
+                 self.instructions,
+                 self.found_by_running)
+    }
+}
+
+impl Disassembly {
+    /// Count up how the ROM was used, if there is a coverage map
+    pub fn summary(&self) -> Option<CoverageSummary> {
+        let coverage = self.coverage.as_ref()?;
+        let mut summary = CoverageSummary {
+            bytes: self.rom.len(),
+            run: 0,
+            sprites: 0,
+            data: 0,
+            instructions: self.instructions.len(),
+            instructions_run: self.instructions
+                .keys()
+                .filter(|&&addr| coverage.has(addr, Access::Fetch))
+                .count(),
+            found_by_running: self.found_by_running.len(),
+        };
+        for a in PROGRAM_START..PROGRAM_START + self.rom.len() {
+            let has = |access| coverage.has(a as MemAddr, access);
+            if has(Access::Fetch) || has(Access::Operand) {
+                summary.run += 1;
+            } else if has(Access::Draw) {
+                summary.sprites += 1;
+            } else if has(Access::Read) || has(Access::Write) {
+                summary.data += 1;
+            }
+        }
+        Some(summary)
+    }
+}
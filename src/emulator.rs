@@ -1,182 +1,731 @@
 //
 // Rust Core Imports
 //
+use std::cmp;
+use std::fs;
+use std::io::{Read, Write};
 use std::path::PathBuf;
 use std::thread;
 use std::time::{Duration, Instant};
 
-//TODO REMOVE
-use std::io;
-
 //
 // Third Party Imports
 //
 use slog;
 use slog_stdlog;
 use slog::DrainExt;
-use minifb::{WindowOptions, Window, Key, Scale};
+use rand;
 
 //
 // This Crate Imports
 //
 use errors::*;
-use cpu;
-use interconnect::{Interconnect, SCREEN_WIDTH, SCREEN_HEIGHT};
+use coverage::Coverage;
+use cpu::{self, Opcode, Reg};
+use interconnect::{Interconnect, MemoryPolicy, Watchpoint, WatchHit};
+use quirks::{Machine, Quirks};
+use frontend::{VideoSink, InputSource, AudioSink, Command};
+use state::{StateReader, StateWriter};
+use rewind::Rewind;
+use debugger::Breakpoint;
+use trace::{Tracer, TraceRecord, TraceRegs, TraceScreen};
 
-pub const SCREEN_SCALE: usize = 16; // Should be power of 2
-pub const DISPLAY_WIDTH: usize = SCREEN_WIDTH * SCREEN_SCALE;
-pub const DISPLAY_HEIGHT: usize = SCREEN_HEIGHT * SCREEN_SCALE;
-pub const DISPLAY_SIZE: usize = DISPLAY_HEIGHT * DISPLAY_WIDTH;
 pub const PROGRAM_START: usize = 0x200;
-pub const NS_IN_SECOND: u64 = 1000000000;
-pub const CPU_CYCLE_NS: u64 = 2000000; //500Hz
-pub const TIMER_CYCLE_NS: u64 = 16666667;
+pub const FRAME_NS: u32 = 16666667; //60Hz
+pub const DEFAULT_CYCLES_PER_FRAME: u64 = 8; //480Hz
+const REWIND_INTERVAL: u64 = 30;
+const REWIND_SNAPSHOTS: usize = 60; // 30 seconds
 const HEXDUMP_COLS: usize = 16;
+/// An address in the Chip8 memory
 pub type MemAddr = u16;
 
+/// Settings which are fixed when the `Chip8` is created
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// How to handle memory accesses outside of RAM
+    pub memory_policy: MemoryPolicy,
+    /// Which instruction set to run
+    pub machine: Machine,
+    /// Which interpreter's behavior to follow
+    pub quirks: Quirks,
+    /// Seed for the `Rand` opcode, `None` picks one at random
+    pub seed: Option<u64>,
+}
+
+/// What happened when a single instruction was executed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    /// The address the instruction was fetched from
+    pub pc: MemAddr,
+    /// The instruction that was executed
+    pub opcode: Opcode,
+    /// The instruction changed what is on the screen
+    pub screen_changed: bool,
+    /// The CPU is stuck on a `KeyGet` waiting for a key press
+    pub blocked: bool,
+    /// The ROM has asked the interpreter to stop with `Exit`
+    pub exited: bool,
+    /// The instruction touched memory under a watchpoint
+    pub watch: Option<WatchHit>,
+}
+
+/// Why `Chip8::run` returned
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Halt {
+    /// The frontend was closed
+    Closed,
+    /// The ROM executed `Exit`
+    Exited,
+    /// The breakpoint with this id was hit, before its instruction was executed
+    Breakpoint(usize),
+    /// The instruction in `Step` set off the watchpoint in `Step::watch`
+    Watchpoint(Step),
+    /// The user asked to break into the debugger
+    Interrupted,
+}
+
 /// The interface to the core Chip8 system.
+///
+/// This owns the CPU, memory and timers, but has no knowledge of any window or
+/// keyboard, so it can be driven headless. A frontend feeds it keys and reads
+/// back the screen, either by hand or through `run`.
+///
+/// Time is counted in instructions rather than read from the host clock: every
+/// `cycles_per_frame` instructions make up one 60Hz frame, at the end of which
+/// the timers tick. The same ROM and inputs will always give the same result.
 pub struct Chip8 {
     logger: slog::Logger,
     cpu: cpu::Cpu,
     interconnect: Interconnect,
-    window: Window,
-    start_time: Instant,
     cpu_cycles: u64,
     timer_ticks: u64,
-    debug_mode: bool,
-}
-
-fn key_map(key: Key) -> Option<usize> {
-    match key {
-        Key::Key1 => Some(0x0),
-        Key::Key2 => Some(0x1),
-        Key::Key3 => Some(0x2),
-        Key::Key4 => Some(0x3),
-        Key::Q => Some(0x4),
-        Key::W => Some(0x5),
-        Key::E => Some(0x6),
-        Key::R => Some(0x7),
-        Key::A => Some(0x8),
-        Key::S => Some(0x9),
-        Key::D => Some(0xA),
-        Key::F => Some(0xB),
-        Key::Z => Some(0xC),
-        Key::X => Some(0xD),
-        Key::C => Some(0xE),
-        Key::V => Some(0xF),
-        _ => None,
+    cycles_per_frame: u64,
+    frame_cycle: u64,
+    seed: u64,
+    rom_path: Option<PathBuf>,
+    rewind: Rewind,
+    breakpoints: Vec<(usize, Breakpoint)>,
+    next_breakpoint: usize,
+    tracer: Option<Tracer>,
+}
+
+/// Sleeps between frames to keep to 60Hz
+///
+/// If the host falls behind, it runs slow rather than trying to catch up.
+pub struct FramePacer {
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        FramePacer { next_frame: Instant::now() }
+    }
+
+    /// Wait until it is time for the next frame
+    pub fn wait(&mut self) {
+        self.next_frame += Duration::new(0, FRAME_NS);
+        let now = Instant::now();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else {
+            self.next_frame = now;
+        }
     }
 }
 
 impl Chip8 {
-    /// Initialize the `Chip8` system
+    /// Initialize the `Chip8` system with the default `Config`
     ///
     /// `logger = None`, will use the standard `log` crate.
     pub fn init(logger: Option<slog::Logger>) -> Self {
+        Chip8::with_config(logger, Config::default())
+    }
+
+    /// Initialize the `Chip8` system
+    ///
+    /// `logger = None`, will use the standard `log` crate.
+    pub fn with_config(logger: Option<slog::Logger>, config: Config) -> Self {
         let emu_logger = logger.unwrap_or(slog::Logger::root(slog_stdlog::StdLog.fuse(), o!()));
-        let cpu_logger = emu_logger.new(o!("device" => "cpu"));
         let int_logger = emu_logger.new(o!("device" => "interconnect"));
+        let seed = config.seed.unwrap_or_else(rand::random);
+        info!(emu_logger, "init"; "seed" => seed);
         Chip8 {
             logger: emu_logger,
-            cpu: cpu::Cpu::init(cpu_logger),
-            interconnect: Interconnect::init(int_logger),
+            cpu: cpu::Cpu::init(&config, seed),
+            interconnect: Interconnect::init(int_logger, &config),
             cpu_cycles: 0,
             timer_ticks: 0,
-            debug_mode: false,
-            start_time: Instant::now(),
-            window: Window::new("Chip8",
-                                DISPLAY_WIDTH,
-                                DISPLAY_HEIGHT,
-                                WindowOptions {
-                                    borderless: false,
-                                    title: true,
-                                    resize: false,
-                                    scale: Scale::X1,
-                                })
-                .unwrap(),
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            frame_cycle: 0,
+            seed: seed,
+            rom_path: None,
+            rewind: Rewind::new(REWIND_INTERVAL, REWIND_SNAPSHOTS),
+            breakpoints: Vec::new(),
+            next_breakpoint: 1,
+            tracer: None,
         }
     }
 
     /// Load a Chip8 ROM from the filesystem
     pub fn load_rom(&mut self, path: PathBuf) -> Result<usize> {
-        self.interconnect.load_rom(path)
+        let bytes = self.interconnect.load_rom(path.clone())?;
+        self.rom_path = Some(path);
+        Ok(bytes)
     }
 
+    /// Load a Chip8 ROM that is already in memory
+    pub fn load_bytes(&mut self, rom: &[u8]) -> Result<usize> {
+        self.interconnect.load_bytes(rom)
+    }
 
-    /// Run the emulator
-    pub fn run(&mut self) {
-        self.start_time = Instant::now();
-        let naptime = Duration::from_millis(3);
-        let mut buffer = [0u32; DISPLAY_SIZE]; // TODO box it
+    /// The seed the random number generator was last started from
+    ///
+    /// Passing this back in as `Config::seed` will replay the same random numbers.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 
-        while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
-            let emulation_time = self.start_time.elapsed();
-            let emulation_ns = emulation_time.as_secs() * NS_IN_SECOND +
-                               emulation_time.subsec_nanos() as u64;
-            let ideal_cpu_cycles = emulation_ns / CPU_CYCLE_NS;
-            let ideal_timer_ticks = emulation_ns / TIMER_CYCLE_NS;
+    /// Restart the random number generator from `seed`
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.cpu.reseed(seed);
+    }
 
+    /// Capture everything needed to resume the machine from this point
+    ///
+    /// The `Config` and `cycles_per_frame` are settings, and are not included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.u64(self.cpu_cycles);
+        state.u64(self.timer_ticks);
+        state.u64(self.frame_cycle);
+        state.u64(self.seed);
+        self.cpu.save_state(&mut state);
+        self.interconnect.save_state(&mut state);
+        state.finish()
+    }
 
-            self.cpu.timer(ideal_timer_ticks - self.timer_ticks);
-            self.timer_ticks = ideal_timer_ticks;
+    /// Restore a state from `save_state`
+    ///
+    /// The state must come from a `Chip8` with the same `Machine`. If it can
+    /// not be loaded, nothing is changed. The rewind history is lost.
+    pub fn load_state(&mut self, buf: &[u8]) -> Result<()> {
+        self.restore_state(buf)?;
+        self.rewind.clear();
+        Ok(())
+    }
 
+    /// Restore a state like `load_state`, keeping the rewind history
+    pub(crate) fn restore_state(&mut self, buf: &[u8]) -> Result<()> {
+        let mut state = StateReader::new(buf)?;
+        let cpu_cycles = state.u64()?;
+        let timer_ticks = state.u64()?;
+        let frame_cycle = state.u64()?;
+        let seed = state.u64()?;
+        let mut cpu = self.cpu.clone();
+        cpu.load_state(&mut state)?;
+        let mut interconnect = self.interconnect.clone();
+        interconnect.load_state(&mut state)?;
+        state.finish()?;
 
-            if !self.debug_mode {
-                self.update_keys();
+        self.cpu_cycles = cpu_cycles;
+        self.timer_ticks = timer_ticks;
+        self.frame_cycle = frame_cycle;
+        self.seed = seed;
+        self.cpu = cpu;
+        self.interconnect = interconnect;
+        Ok(())
+    }
+
+    /// Where save slot `slot` is kept, next to the ROM file
+    pub fn slot_path(&self, slot: u8) -> Result<PathBuf> {
+        let rom_path = self.rom_path.as_ref().ok_or(ErrorKind::NoSaveSlots)?;
+        let mut name = rom_path.file_name().ok_or(ErrorKind::NoSaveSlots)?.to_os_string();
+        name.push(format!(".ss{}", slot));
+        Ok(rom_path.with_file_name(name))
+    }
+
+    /// Write a save state to numbered slot on disk
+    pub fn save_slot(&self, slot: u8) -> Result<()> {
+        let path = self.slot_path(slot)?;
+        fs::File::create(&path)?.write_all(&self.save_state())?;
+        info!(self.logger, "save_slot"; "slot" => slot, "file" => path.to_str());
+        Ok(())
+    }
+
+    /// Restore the save state in a numbered slot on disk
+    pub fn load_slot(&mut self, slot: u8) -> Result<()> {
+        let path = self.slot_path(slot)?;
+        let mut buf = Vec::new();
+        fs::File::open(&path)?.read_to_end(&mut buf)?;
+        self.load_state(&buf)?;
+        info!(self.logger, "load_slot"; "slot" => slot, "file" => path.to_str());
+        Ok(())
+    }
+
+    /// Keep a snapshot every `interval` frames for rewinding, up to `snapshots` of them
+    ///
+    /// `snapshots = 0` turns rewinding off.
+    pub fn set_rewind(&mut self, interval: u64, snapshots: usize) {
+        self.rewind = Rewind::new(interval, snapshots);
+    }
+
+    /// The earliest frame `rewind_to` can go back to
+    pub fn rewind_start(&self) -> Option<u64> {
+        self.rewind.oldest()
+    }
+
+    /// Go back to the start of an earlier `frame`
+    ///
+    /// Only frames run by `run` are recorded. The machine is restored from the
+    /// closest snapshot, then the recorded keys are replayed up to `frame`.
+    pub fn rewind_to(&mut self, frame: u64) -> Result<()> {
+        if frame > self.frames() {
+            bail!(ErrorKind::RewindUnavailable(frame));
+        }
+        let (_, state) = self.rewind.snapshot(frame).ok_or(ErrorKind::RewindUnavailable(frame))?;
+        self.restore_state(&state)?;
+        // Don't write the same instructions to a trace twice
+        let tracer = self.stop_trace();
+        let replayed = self.replay_frames(frame);
+        if let Some(tracer) = tracer {
+            self.trace(tracer);
+        }
+        replayed?;
+        self.rewind.truncate(frame);
+        Ok(())
+    }
+
+    fn replay_frames(&mut self, frame: u64) -> Result<()> {
+        while self.frames() < frame {
+            let current = self.frames();
+            let keys = self.rewind.keys(current).ok_or(ErrorKind::RewindUnavailable(frame))?;
+            self.set_keys(&keys);
+            let steps = self.run_frame()?;
+            if steps.last().is_some_and(|step| step.exited) {
+                break;
             }
-            while self.cpu_cycles < ideal_cpu_cycles {
-                // if self.cpu.pc == 0x0278 {
-                //     self.debug_mode = true;
-                // }
-                if self.debug_mode {
-                    debug!(self.logger, "debug_cpu"; "keys" => self.interconnect.display_keys());
-                    println!("{}", self.cpu);
-                    for i in -5..10 {
-                        let memaddr = (self.cpu.pc as isize + 2 * i) as u16;
-                        let instr = self.interconnect.read_halfword(memaddr);
-                        if i == 0 {
-                            print!("-->");
-                        }
-                        match cpu::disassemble(instr) {
-                            Ok(opcode) => println!("\t0x{:04x} {}", memaddr, opcode),
-                            Err(e) => println!("\t0x{:04x} UNRECOGNIZED {}", memaddr, e),
-                        }
-                    }
+        }
+        Ok(())
+    }
 
-                    // hack to input a key in debug mode
-                    // insert 0-16 to press key
-                    // insert something larger than 16 to clear keys
-                    let mut input_text = String::new();
-                    io::stdin()
-                        .read_line(&mut input_text)
-                        .expect("failed to read from stdin");
-                    match input_text.trim().parse::<usize>() {
-                        Ok(x) => {
-                            if x > 16 {
-                                self.interconnect.reset_keys();
-                            } else {
-                                self.interconnect.set_key(x);
-                            }
-                        }
-                        Err(e) => {
-                            println!("{:?}", e);
-                        }
+    // Called at the start of each frame `run` executes, before the keys are set
+    fn record_frame(&mut self, keys: &[bool; 16]) {
+        if self.frame_cycle != 0 {
+            return;
+        }
+        let frame = self.frames();
+        let snapshot = if self.rewind.wants_snapshot(frame) {
+            Some(self.save_state())
+        } else {
+            None
+        };
+        self.rewind.record(frame, keys, snapshot);
+    }
+
+    /// Stop `run` when `breakpoint` is hit, returning an id to remove it with
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_breakpoint;
+        self.next_breakpoint += 1;
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    /// Remove a breakpoint, returns `false` if there was no such id
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        let before = self.breakpoints.len();
+        self.breakpoints.retain(|&(bp_id, _)| bp_id != id);
+        self.breakpoints.len() != before
+    }
 
+    /// Every breakpoint, with its id
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    /// Stop `run` after an instruction touches memory under `watchpoint`
+    ///
+    /// Returns an id to remove it with, from the same range as breakpoints.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.next_breakpoint;
+        self.next_breakpoint += 1;
+        self.interconnect.add_watchpoint(id, watchpoint);
+        id
+    }
+
+    /// Remove a watchpoint, returns `false` if there was no such id
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        self.interconnect.remove_watchpoint(id)
+    }
+
+    /// Every watchpoint, with its id
+    pub fn watchpoints(&self) -> &[(usize, Watchpoint)] {
+        self.interconnect.watchpoints()
+    }
+
+    /// The id of the first breakpoint that the next instruction hits
+    pub fn breakpoint_hit(&self) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .find(|(_, breakpoint)| breakpoint.hit(self))
+            .map(|&(id, _)| id)
+    }
+
+    /// How many instructions are executed in each 60Hz frame
+    pub fn cycles_per_frame(&self) -> u64 {
+        self.cycles_per_frame
+    }
+
+    /// Change the emulated CPU speed, takes effect from the current frame
+    pub fn set_cycles_per_frame(&mut self, cycles: u64) {
+        self.cycles_per_frame = cmp::max(cycles, 1);
+    }
+
+    /// How many instructions have run since the `Chip8` was created
+    pub fn cycles(&self) -> u64 {
+        self.cpu_cycles
+    }
+
+    /// How many frames have completed since the `Chip8` was created
+    pub fn frames(&self) -> u64 {
+        self.timer_ticks
+    }
+
+    /// Execute a single instruction
+    ///
+    /// If the ROM does something illegal, the fault is returned and the CPU is
+    /// left pointing at the offending instruction. The timers tick when this
+    /// instruction completes a frame.
+    pub fn step(&mut self) -> Result<Step> {
+        let traced = match self.tracer {
+            Some(ref mut tracer) => tracer.wants(self.cpu_cycles, self.cpu.pc),
+            None => false,
+        };
+        let (step, record) = self.execute(traced)?;
+        if let (Some(record), Some(tracer)) = (record, self.tracer.as_mut()) {
+            tracer.record(&record)?;
+        }
+        Ok(step)
+    }
+
+    /// Execute a single instruction, like `step`, and describe what it did
+    ///
+    /// This is the same record a `Tracer` would write, whether or not one is
+    /// running.
+    pub fn step_traced(&mut self) -> Result<(Step, TraceRecord)> {
+        let (step, record) = self.execute(true)?;
+        Ok((step, record.expect("execute always records when asked")))
+    }
+
+    fn execute(&mut self, traced: bool) -> Result<(Step, Option<TraceRecord>)> {
+        let pc = self.cpu.pc;
+        // Read the instruction first, in case it overwrites itself
+        let before = if traced {
+            self.interconnect.record_writes();
+            let raw = (0..4).fold(0u32, |raw, offset| {
+                let byte = self.interconnect.peek(pc.wrapping_add(offset)).unwrap_or(0);
+                raw << 8 | byte as u32
+            });
+            Some((raw, self.trace_regs()))
+        } else {
+            None
+        };
+        // Forget any hit left over from an instruction that faulted
+        self.interconnect.take_watch_hit();
+        let result = self.cpu.run_cycle(&mut self.interconnect);
+        let writes = self.interconnect.take_writes();
+        let opcode = result?;
+        self.interconnect.cover_instruction(pc, opcode.len());
+        let screen_changed = matches!(opcode,
+                                      Opcode::ClearScreen |
+                                      Opcode::Draw(..) |
+                                      Opcode::ScrollDown(_) |
+                                      Opcode::ScrollUp(_) |
+                                      Opcode::ScrollRight |
+                                      Opcode::ScrollLeft |
+                                      Opcode::LowRes |
+                                      Opcode::HighRes);
+        let record = before.map(|(raw, before)| {
+            let (width, height) = self.screen_size();
+            TraceRecord {
+                cycle: self.cpu_cycles,
+                pc: pc,
+                raw: if opcode.len() == 4 { raw } else { raw >> 16 },
+                opcode: opcode,
+                before: before,
+                after: self.trace_regs(),
+                writes: writes,
+                stack: Some(self.stack().to_vec()),
+                screen: if screen_changed {
+                    Some(TraceScreen::new(width, height, self.graphics()))
+                } else {
+                    None
+                },
+            }
+        });
+        self.cpu_cycles += 1;
+        self.frame_cycle += 1;
+        if self.frame_cycle >= self.cycles_per_frame {
+            self.frame_cycle = 0;
+            self.tick_timers(1);
+        }
+        let blocked = match opcode {
+            Opcode::KeyGet(_) => self.cpu.pc == pc,
+            _ => false,
+        };
+        let step = Step {
+            pc: pc,
+            opcode: opcode,
+            screen_changed: screen_changed,
+            blocked: blocked,
+            exited: opcode == Opcode::Exit,
+            watch: self.interconnect.take_watch_hit(),
+        };
+        Ok((step, record))
+    }
+
+    fn trace_regs(&self) -> TraceRegs {
+        TraceRegs {
+            v: self.cpu.gpregs,
+            i: self.cpu.vi,
+            sp: self.cpu.sp as u8,
+            dt: self.cpu.delay,
+            st: self.cpu.sound,
+        }
+    }
+
+    /// Start recording every instruction run into `tracer`
+    ///
+    /// Any tracer already running is replaced, and returned so it can be
+    /// finished. A trace that can't be written stops `step` with an error.
+    pub fn trace(&mut self, tracer: Tracer) -> Option<Tracer> {
+        self.tracer.replace(tracer)
+    }
+
+    /// Stop tracing, returning the tracer so it can be finished
+    pub fn stop_trace(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Start noting which addresses are run, read, written and drawn
+    ///
+    /// Carries on with the map already being recorded, if there is one.
+    pub fn record_coverage(&mut self) {
+        self.interconnect.record_coverage();
+    }
+
+    /// Stop recording coverage, returning the map
+    pub fn stop_coverage(&mut self) -> Option<Coverage> {
+        self.interconnect.take_coverage()
+    }
+
+    /// Execute `n` instructions
+    ///
+    /// Stops at the first fault.
+    pub fn run_cycles(&mut self, n: u64) -> Result<Vec<Step>> {
+        (0..n).map(|_| self.step()).collect()
+    }
+
+    /// Execute instructions up to the end of the current 60Hz frame
+    ///
+    /// Stops early at a fault, or after an `Exit`.
+    pub fn run_frame(&mut self) -> Result<Vec<Step>> {
+        let mut steps = Vec::new();
+        loop {
+            let step = self.step()?;
+            steps.push(step);
+            if step.exited || self.frame_cycle == 0 {
+                return Ok(steps);
+            }
+        }
+    }
+
+    /// Count down the delay and sound timers by `ticks` 60Hz periods
+    ///
+    /// This happens by itself as instructions are executed, so is only needed
+    /// to move the timers without running the CPU.
+    pub fn tick_timers(&mut self, ticks: u64) {
+        self.cpu.timer(ticks);
+        self.timer_ticks += ticks;
+    }
+
+    /// The current state of the screen, row major
+    ///
+    /// Each pixel has one bit set for each bitplane that is lit.
+    pub fn graphics(&self) -> &[u8] {
+        &self.interconnect.graphics
+    }
+
+    /// The current `(width, height)` of the screen
+    pub fn screen_size(&self) -> (usize, usize) {
+        (self.interconnect.width(), self.interconnect.height())
+    }
+
+    /// The address of the next instruction
+    pub fn pc(&self) -> MemAddr {
+        self.cpu.pc
+    }
+
+    /// Jump to `addr` without executing anything
+    pub fn set_pc(&mut self, addr: MemAddr) {
+        self.cpu.pc = addr;
+    }
+
+    /// The value of a general purpose register
+    pub fn register(&self, reg: Reg) -> u8 {
+        self.cpu.gpregs[reg as usize]
+    }
+
+    /// Overwrite a general purpose register
+    pub fn set_register(&mut self, reg: Reg, value: u8) {
+        self.cpu.gpregs[reg as usize] = value;
+    }
+
+    /// The value of the `I` register
+    pub fn index(&self) -> MemAddr {
+        self.cpu.vi
+    }
+
+    /// Overwrite the `I` register
+    pub fn set_index(&mut self, addr: MemAddr) {
+        self.cpu.vi = addr;
+    }
+
+    /// The `(delay, sound)` timers
+    pub fn timers(&self) -> (u8, u8) {
+        (self.cpu.delay, self.cpu.sound)
+    }
+
+    /// Overwrite the `(delay, sound)` timers
+    pub fn set_timers(&mut self, delay: u8, sound: u8) {
+        self.cpu.delay = delay;
+        self.cpu.sound = sound;
+    }
+
+    /// The return addresses of the subroutines being run, innermost last
+    pub fn stack(&self) -> &[MemAddr] {
+        &self.cpu.stack[..self.cpu.sp]
+    }
+
+    /// Read a byte of memory, following the `MemoryPolicy`
+    ///
+    /// This does not set off any watchpoints.
+    pub fn read_memory(&self, addr: MemAddr) -> Result<u8> {
+        self.interconnect.peek(addr)
+    }
+
+    /// Write a byte of memory, following the `MemoryPolicy`
+    ///
+    /// This does not set off any watchpoints.
+    pub fn write_memory(&mut self, addr: MemAddr, value: u8) -> Result<()> {
+        self.interconnect.poke(addr, value)
+    }
+
+    /// Decode the instruction at `addr`
+    pub fn instruction(&self, addr: MemAddr) -> Result<Opcode> {
+        cpu::fetch(&self.interconnect, addr).map(|(_, opcode)| opcode)
+    }
+
+    /// The keys currently held down
+    pub fn keys(&self) -> [bool; 16] {
+        self.interconnect.keys()
+    }
+
+    /// Replace the state of the keypad, `keys[n]` is `true` if key `n` is held
+    pub fn set_keys(&mut self, keys: &[bool; 16]) {
+        self.interconnect.reset_keys();
+        for (key, &down) in keys.iter().enumerate() {
+            if down {
+                self.interconnect.set_key(key);
+            }
+        }
+    }
+
+
+    /// Run the emulator until the frontend is closed, the ROM exits, a
+    /// breakpoint is hit, or it faults
+    ///
+    /// The host clock is only used to pace frames out at 60Hz, and never
+    /// changes how much work is done in a frame.
+    pub fn run<F>(&mut self, frontend: &mut F) -> Result<Halt>
+        where F: VideoSink + InputSource + AudioSink
+    {
+        let mut pacer = FramePacer::new();
+
+        while frontend.is_open() {
+            let mut rewinding = false;
+            for command in frontend.commands() {
+                let result = match command {
+                    Command::SaveSlot(slot) => self.save_slot(slot),
+                    Command::LoadSlot(slot) => self.load_slot(slot),
+                    Command::Rewind => {
+                        rewinding = true;
+                        Ok(())
                     }
+                    Command::Break => return Ok(Halt::Interrupted),
+                };
+                if let Err(e) = result {
+                    warn!(self.logger, "command"; "command" => format!("{:?}", command),
+                          "error" => format!("{}", e));
                 }
-                self.cpu.run_cycle(&mut self.interconnect);
-                self.cpu_cycles += 1;
-                if self.debug_mode {
-                    break;
+            }
+
+            if rewinding {
+                let frame = self.frames().saturating_sub(1);
+                if self.rewind_start().is_some_and(|start| start <= frame) {
+                    self.rewind_to(frame).map_err(|e| self.halted(e))?;
+                }
+            } else {
+                let keys = frontend.keys();
+                self.record_frame(&keys);
+                self.set_keys(&keys);
+                let frame = self.frames();
+                while self.frames() == frame {
+                    if let Some(id) = self.breakpoint_hit() {
+                        info!(self.logger, "breakpoint"; "id" => id,
+                              "pc" => format!("0x{:04x}", self.cpu.pc));
+                        return Ok(Halt::Breakpoint(id));
+                    }
+                    let step = self.step().map_err(|e| self.halted(e))?;
+                    if step.exited {
+                        info!(self.logger, "exit"; "pc" => format!("0x{:04x}", step.pc));
+                        return Ok(Halt::Exited);
+                    }
+                    if let Some(hit) = step.watch {
+                        info!(self.logger, "watchpoint"; "id" => hit.id,
+                              "pc" => format!("0x{:04x}", step.pc),
+                              "opcode" => format!("{}", step.opcode),
+                              "addr" => format!("0x{:04x}", hit.addr),
+                              "old" => hit.old, "new" => hit.new);
+                        return Ok(Halt::Watchpoint(step));
+                    }
                 }
             }
 
-            self.draw_screen(&mut buffer);
-            thread::sleep(naptime);
+            self.present(frontend);
+            pacer.wait();
         }
+        Ok(Halt::Closed)
+    }
+
+    fn halted(&self, e: Error) -> Error {
+        crit!(self.logger, "halted";
+              "error" => format!("{}", e), "cpu" => format!("{}", self.cpu));
+        e
     }
 
+    /// Show the current screen and play the current sound
+    pub fn present<F>(&self, frontend: &mut F)
+        where F: VideoSink + AudioSink
+    {
+        let (width, height) = self.screen_size();
+        frontend.draw(self.graphics(), width, height);
+        frontend.pattern(self.interconnect.audio_pattern(),
+                         self.interconnect.pitch());
+        frontend.tone(self.cpu.sound_active());
+    }
 
+    /// Print a hexdump of `mem`, labelled from `start_offset`
     pub fn mem_dump(mem: &[u8], start_offset: usize) {
         let max_bytes = HEXDUMP_COLS * 16; //rows
         let mut spacer;
@@ -194,49 +743,297 @@ impl Chip8 {
         }
         println!();
     }
-    pub fn set_debug(&mut self, status: bool) {
-        self.debug_mode = status;
-    }
 
-    fn update_keys(&mut self) {
-        self.interconnect.reset_keys();
-        if let Some(keys) = self.window.get_keys() {
-            for key in keys {
-                if let Some(chip8_key) = key_map(key) {
-                    self.interconnect.set_key(chip8_key)
+    /// Print the opcodes in the first `total` bytes of the program
+    pub fn disassemble(&self, total: usize) {
+        let mut idx = PROGRAM_START;
+        while idx + 1 < total + PROGRAM_START {
+            let instr = match self.interconnect.read_halfword(idx as _) {
+                Ok(instr) => instr,
+                Err(_) => break,
+            };
+            print!("0x:{:04x} (0x{:04x}):\t", idx, instr);
+            match cpu::fetch(&self.interconnect, idx as _) {
+                Ok((_, opcode)) => {
+                    idx += opcode.len() as usize;
+                    println!("{}", opcode)
+                }
+                Err(e) => {
+                    idx += 2;
+                    println!("UNKNOWN {}", e)
                 }
             }
         }
+
     }
+}
 
-    fn draw_screen(&mut self, buffer: &mut [u32; DISPLAY_SIZE]) {
-        for dy in 0..DISPLAY_HEIGHT {
-            let sy = dy / SCREEN_SCALE;
-            for dx in 0..DISPLAY_WIDTH {
-                let sx = dx / SCREEN_SCALE;
-                let display_index = dy * DISPLAY_WIDTH + dx;
-                let screen_index = sy * SCREEN_WIDTH + sx;
-                buffer[display_index] = if self.interconnect.graphics[screen_index] {
-                    0x00ffffff
-                } else {
-                    0
-                };
-            }
+#[test]
+fn headless_step() {
+    let mut chip8 = Chip8::init(None);
+    // LOAD V0 0x2a; JUMP 0x0200
+    chip8.load_bytes(&[0x60, 0x2a, 0x12, 0x00]).unwrap();
+    let step = chip8.step().unwrap();
+    assert_eq!(step.pc, 0x200);
+    assert_eq!(step.opcode, Opcode::LoadByte(cpu::Reg::V0, 0x2a));
+    assert_eq!(chip8.cpu.pc, 0x202);
+    chip8.step().unwrap();
+    assert_eq!(chip8.cpu.pc, 0x200);
+    assert_eq!(chip8.cpu_cycles, 2);
+}
+
+#[test]
+fn step_reports_screen_and_keys() {
+    let mut chip8 = Chip8::init(None);
+    // CLS; KEY.GET V1; DRAW V0 V0 0x01
+    chip8.load_bytes(&[0x00, 0xe0, 0xf1, 0x0a, 0xd0, 0x01]).unwrap();
+    let steps = chip8.run_cycles(3).unwrap();
+    assert!(steps[0].screen_changed);
+    assert!(steps[1].blocked && steps[2].blocked);
+    assert_eq!(steps[2].pc, 0x202);
+
+    let mut keys = [false; 16];
+    keys[0x5] = true;
+    chip8.set_keys(&keys);
+    let step = chip8.step().unwrap();
+    assert!(!step.blocked);
+    assert!(chip8.step().unwrap().screen_changed);
+}
+
+#[test]
+fn frame_ticks_timers() {
+    let mut chip8 = Chip8::init(None);
+    // LOAD V0 0x01; SOUND.SET V0; JUMP 0x0204
+    chip8.load_bytes(&[0x60, 0x01, 0xf0, 0x18, 0x12, 0x04]).unwrap();
+    chip8.run_cycles(2).unwrap();
+    assert!(chip8.cpu.sound_active());
+    let steps = chip8.run_frame().unwrap();
+    assert_eq!(steps.len() as u64, DEFAULT_CYCLES_PER_FRAME - 2);
+    assert_eq!(chip8.frames(), 1);
+    assert!(!chip8.cpu.sound_active());
+    assert_eq!(chip8.run_frame().unwrap().len() as u64, DEFAULT_CYCLES_PER_FRAME);
+}
+
+#[test]
+fn cycles_per_frame() {
+    let mut chip8 = Chip8::init(None);
+    // JUMP 0x0200
+    chip8.load_bytes(&[0x12, 0x00]).unwrap();
+    chip8.set_cycles_per_frame(3);
+    chip8.run_cycles(7).unwrap();
+    assert_eq!(chip8.frames(), 2);
+    assert_eq!(chip8.run_frame().unwrap().len(), 2);
+    chip8.set_cycles_per_frame(0);
+    assert_eq!(chip8.run_frame().unwrap().len(), 1);
+    assert_eq!(chip8.frames(), 4);
+}
+
+#[cfg(test)]
+fn test_state_rom() -> Chip8 {
+    let config = Config { seed: Some(7), ..Config::default() };
+    let mut chip8 = Chip8::with_config(None, config);
+    // RAND V0 0x0f; DRAW V0 V0 0x05; LOAD V1 0x20; SOUND.SET V1; CALL 0x020c; JUMP 0x0200
+    // SKIP.KEY V0; MATH.ADD V2 0x01; RETURN
+    chip8.load_bytes(&[0xc0, 0x0f, 0xd0, 0x05, 0x61, 0x20, 0xf1, 0x18, 0x22, 0x0c, 0x12,
+                       0x00, 0xe0, 0x9e, 0x72, 0x01, 0x00, 0xee])
+        .unwrap();
+    chip8
+}
+
+#[test]
+fn save_state_round_trip() {
+    let mut chip8 = test_state_rom();
+    chip8.run_cycles(13).unwrap();
+    let state = chip8.save_state();
+    let expected = chip8.run_cycles(20).unwrap();
+    let graphics = chip8.graphics().to_vec();
+    let later = chip8.save_state();
+
+    chip8.load_state(&state).unwrap();
+    assert_eq!(chip8.cpu_cycles, 13);
+    assert_eq!(chip8.run_cycles(20).unwrap(), expected);
+    assert_eq!(chip8.graphics(), &graphics[..]);
+    assert_eq!(chip8.save_state(), later);
+
+    // A state from a fresh machine restores into a used one exactly
+    let mut other = Chip8::init(None);
+    other.load_state(&state).unwrap();
+    assert_eq!(other.save_state(), state);
+}
+
+#[test]
+fn save_state_rejects_bad_data() {
+    let mut chip8 = test_state_rom();
+    let state = chip8.save_state();
+    chip8.run_cycles(3).unwrap();
+    let before = chip8.save_state();
+    assert!(chip8.load_state(&state[..state.len() - 1]).is_err());
+    assert!(chip8.load_state(&state[1..]).is_err());
+    let mut long = state.clone();
+    long.push(0);
+    assert!(chip8.load_state(&long).is_err());
+
+    let config = Config { machine: Machine::XoChip, ..Config::default() };
+    assert!(Chip8::with_config(None, config).load_state(&state).is_err());
+    assert_eq!(chip8.save_state(), before);
+}
+
+#[test]
+fn save_slots() {
+    use std::env;
+    let mut chip8 = Chip8::init(None);
+    assert!(chip8.save_slot(1).is_err());
+
+    let rom_path = env::temp_dir().join(format!("c8e-slots-{}.ch8", rand::random::<u32>()));
+    // LOAD V0 0x2a; JUMP 0x0202
+    fs::File::create(&rom_path).unwrap().write_all(&[0x60, 0x2a, 0x12, 0x02]).unwrap();
+    chip8.load_rom(rom_path.clone()).unwrap();
+    chip8.step().unwrap();
+    chip8.save_slot(3).unwrap();
+    let slot_path = chip8.slot_path(3).unwrap();
+    assert_eq!(slot_path.extension().unwrap(), "ss3");
+
+    let mut fresh = Chip8::init(None);
+    fresh.load_rom(rom_path.clone()).unwrap();
+    fresh.load_slot(3).unwrap();
+    assert_eq!(fresh.save_state(), chip8.save_state());
+    assert!(fresh.load_slot(4).is_err());
+    fs::remove_file(slot_path).unwrap();
+    fs::remove_file(rom_path).unwrap();
+}
+
+#[test]
+fn rewind_to_frame() {
+    let mut chip8 = test_state_rom();
+    // Keep every snapshot, so the test can also check the oldest one
+    chip8.set_rewind(10, 100);
+    let mut expected = Vec::new();
+    for frame in 0..100 {
+        if frame == 0 || frame == 37 || frame == 70 {
+            expected.push(chip8.save_state());
         }
-        self.window.update_with_buffer(buffer);
+        let mut keys = [false; 16];
+        keys[frame % 16] = frame % 3 == 0;
+        chip8.record_frame(&keys);
+        chip8.set_keys(&keys);
+        chip8.run_frame().unwrap();
     }
+    assert_eq!(chip8.rewind_start(), Some(0));
 
-    pub fn disassemble(&self, total: usize) {
-        let mut idx = PROGRAM_START;
-        while idx + 1 < total + PROGRAM_START {
-            let instr = self.interconnect.read_halfword(idx as _);
-            print!("0x:{:04x} (0x{:04x}):\t", idx, instr);
-            idx += 2;
-            match cpu::disassemble(instr) {
-                Ok(opcode) => println!("{}", opcode),
-                Err(e) => println!("UNKNOWN {}", e),
-            }
-        }
+    chip8.rewind_to(70).unwrap();
+    assert_eq!(chip8.save_state(), expected[2]);
+    // Replaying frames 30 to 37 isn't traced again
+    chip8.trace(Tracer::new(::std::io::sink(), ::trace::TraceFormat::JsonLines).unwrap());
+    chip8.rewind_to(37).unwrap();
+    assert_eq!(chip8.save_state(), expected[1]);
+    assert_eq!(chip8.stop_trace().unwrap().records(), 0);
+    assert!(chip8.rewind_to(40).is_err());
+    chip8.rewind_to(0).unwrap();
+    assert_eq!(chip8.save_state(), expected[0]);
+
+    chip8.set_rewind(10, 0);
+    chip8.record_frame(&[false; 16]);
+    assert!(chip8.rewind_to(0).is_err());
+}
 
+#[test]
+fn run_stops_at_breakpoint() {
+    use frontend::Headless;
+    let mut chip8 = test_state_rom();
+    let draw = chip8.add_breakpoint("0x0206".parse().unwrap());
+    let mut frontend = Headless;
+    assert_eq!(chip8.run(&mut frontend).unwrap(), Halt::Breakpoint(draw));
+    assert_eq!(chip8.pc(), 0x206);
+
+    assert!(chip8.remove_breakpoint(draw));
+    assert!(!chip8.remove_breakpoint(draw));
+    let cond = chip8.add_breakpoint("if v2 == 3".parse().unwrap());
+    assert_eq!(chip8.run(&mut frontend).unwrap(), Halt::Breakpoint(cond));
+    assert_eq!(chip8.register(Reg::V2), 3);
+}
+
+#[test]
+fn watchpoint_reports_step() {
+    let mut chip8 = Chip8::init(None);
+    // LOAD V0 0x7b; LOAD VI 0x0300; BCD V0; JUMP 0x0206
+    chip8.load_bytes(&[0x60, 0x7b, 0xa3, 0x00, 0xf0, 0x33, 0x12, 0x06]).unwrap();
+    chip8.write_memory(0x301, 0x55).unwrap();
+    let id = chip8.add_watchpoint("0x301".parse().unwrap());
+    let steps = chip8.run_cycles(3).unwrap();
+    assert_eq!(steps[1].watch, None);
+    let hit = steps[2].watch.unwrap();
+    assert_eq!((hit.id, hit.addr, hit.old, hit.new), (id, 0x301, 0x55, 2));
+    assert_eq!(steps[2].pc, 0x204);
+    assert!(chip8.remove_watchpoint(id));
+    assert!(chip8.watchpoints().is_empty());
+}
+
+#[cfg(test)]
+#[derive(Clone)]
+struct SharedBuf(::std::sync::Arc<::std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
     }
+
+    fn flush(&mut self) -> ::std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn trace_instructions() {
+    use trace::{TraceFormat, TracePoint};
+    let mut chip8 = Chip8::init(None);
+    // LOAD V0 0x7b; LOAD VI 0x0300; BCD V0; JUMP 0x0206
+    chip8.load_bytes(&[0x60, 0x7b, 0xa3, 0x00, 0xf0, 0x33, 0x12, 0x06]).unwrap();
+    let buf = SharedBuf(Default::default());
+    let mut tracer = Tracer::new(buf.clone(), TraceFormat::JsonLines).unwrap();
+    tracer.start_at(TracePoint::Addr(0x202));
+    tracer.stop_at(TracePoint::Cycle(4));
+    assert!(chip8.trace(tracer).is_none());
+    chip8.run_cycles(6).unwrap();
+    let tracer = chip8.stop_trace().unwrap();
+    assert_eq!(tracer.records(), 3);
+    tracer.finish().unwrap();
+
+    let trace = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+    let records: Vec<::serde_json::Value> =
+        trace.lines().map(|line| ::serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0]["cycle"], 1);
+    assert_eq!(records[0]["opcode"], "LOAD VI 0x0300");
+    assert_eq!(records[0]["before"]["i"], 0);
+    assert_eq!(records[0]["after"]["i"], 0x300);
+    assert_eq!(records[1]["pc"], 0x204);
+    assert_eq!(records[1]["raw"], 0xf033);
+    assert_eq!(records[1]["after"]["v"][0], 0x7b);
+    assert_eq!(records[1]["writes"],
+               json!([{"addr": 0x300, "old": 0, "new": 1},
+                      {"addr": 0x301, "old": 0, "new": 2},
+                      {"addr": 0x302, "old": 0, "new": 3}]));
+    assert_eq!(records[2]["writes"], json!([]));
+}
+
+#[test]
+fn coverage_records_accesses() {
+    use coverage::Access;
+    let mut chip8 = Chip8::init(None);
+    // LOAD VI 0x0300; BCD V0; LOAD VI 0x0000; DRAW V0 V0 0x5; JUMP 0x0208
+    chip8.load_bytes(&[0xa3, 0x00, 0xf0, 0x33, 0xa0, 0x00, 0xd0, 0x05, 0x12, 0x08]).unwrap();
+    chip8.run_cycles(1).unwrap();
+    chip8.record_coverage();
+    chip8.run_cycles(5).unwrap();
+    let coverage = chip8.stop_coverage().unwrap();
+    assert!(!coverage.touched(0x200));
+    assert!(coverage.has(0x202, Access::Fetch));
+    assert!(coverage.has(0x203, Access::Operand));
+    assert!(coverage.has(0x302, Access::Write));
+    assert!(!coverage.touched(0x303));
+    assert!(coverage.has(0x004, Access::Draw));
+    assert!(!coverage.touched(0x005));
+    assert_eq!(coverage.fetched(), vec![0x202, 0x204, 0x206, 0x208]);
+    assert!(chip8.stop_coverage().is_none());
 }
@@ -1,5 +1,7 @@
 use std::io;
 
+use serde_json;
+
 
 // impl From<Error> for RedisError {
 //     fn from(e: Error) -> RedisError {
@@ -11,11 +13,84 @@ use std::io;
 error_chain! {
     foreign_links {
         IOError(io::Error) #[doc = "A wrapper around the `std::io::Error`"];
+        JsonError(serde_json::Error) #[doc = "A wrapper around the `serde_json::Error`"];
     }
     errors {
         UnrecognizedOpcode(instr: u16) {
             description("Could not disassemble Opcode")
                 display("Opcode: 0x{:04x}", instr)
         }
+        IllegalOpcode(pc: u16, instr: u16) {
+            description("Opcode can not be executed")
+                display("Illegal opcode 0x{:04x} at PC 0x{:04x}", instr, pc)
+        }
+        StackOverflow(pc: u16) {
+            description("Call stack is full")
+                display("Stack overflow at PC 0x{:04x}", pc)
+        }
+        StackUnderflow(pc: u16) {
+            description("Return with an empty call stack")
+                display("Stack underflow at PC 0x{:04x}", pc)
+        }
+        MemoryOutOfBounds(addr: usize) {
+            description("Memory access outside of RAM")
+                display("Out of bounds memory access at 0x{:04x}", addr)
+        }
+        InvalidRegister(x: u8) {
+            description("No such register")
+                display("Register 0x{:02x} not defined", x)
+        }
+        InvalidKey(key: u8) {
+            description("No such key")
+                display("Key 0x{:02x} not defined", key)
+        }
+        UnknownOption(kind: &'static str, value: String) {
+            description("Unknown option")
+                display("Unknown {}: '{}'", kind, value)
+        }
+        RomTooLarge(size: usize, space: usize) {
+            description("ROM does not fit in memory")
+                display("ROM is {} bytes, only {} bytes available", size, space)
+        }
+        BadSaveState(reason: &'static str) {
+            description("Save state could not be loaded")
+                display("Bad save state: {}", reason)
+        }
+        NoSaveSlots {
+            description("Save slots are kept next to the ROM file")
+                display("Save slots need a ROM loaded from a file")
+        }
+        RewindUnavailable(frame: u64) {
+            description("Frame is not in the rewind buffer")
+                display("Can not rewind to frame {}", frame)
+        }
+        DebuggerCommand(msg: String) {
+            description("Could not run debugger command")
+                display("{}", msg)
+        }
+        BadExpression(expr: String, reason: &'static str) {
+            description("Could not parse expression")
+                display("Bad expression '{}': {}", expr, reason)
+        }
+        BadDapMessage(reason: String) {
+            description("Could not understand a debug adapter message")
+                display("Bad debug adapter message: {}", reason)
+        }
+        BadAssembly(file: String, line: usize, reason: String) {
+            description("Could not assemble source")
+                display("{}:{}: {}", file, line, reason)
+        }
+        BadTrace(reason: String) {
+            description("Trace could not be read")
+                display("Bad trace: {}", reason)
+        }
+        BadCoverage(reason: &'static str) {
+            description("Coverage map could not be read")
+                display("Bad coverage map: {}", reason)
+        }
+        WindowError(msg: String) {
+            description("Could not open a window")
+                display("Window: {}", msg)
+        }
     }
 }
@@ -0,0 +1,167 @@
+//
+// Declare sub modules
+//
+#[cfg(feature = "minifb")]
+mod window;
+
+//
+// This Crate Imports
+//
+use interconnect::AUDIO_PATTERN_SIZE;
+
+//
+// Public Exports
+//
+#[cfg(feature = "minifb")]
+pub use self::window::WindowFrontend;
+
+/// Somewhere to show the Chip8 screen
+pub trait VideoSink {
+    /// Present a frame. `pixels` is `width * height` long, in row major order.
+    ///
+    /// Each pixel is a palette index from 0 to 3, with one bit for each
+    /// bitplane. Only XO-CHIP uses the second plane, so other ROMs only ever
+    /// draw 0 or 1.
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize);
+}
+
+/// A request from the user to the emulator itself, rather than to the ROM
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Write a save state to the numbered slot
+    SaveSlot(u8),
+    /// Restore the save state in the numbered slot
+    LoadSlot(u8),
+    /// Step back one frame, sent every frame while rewind is held
+    Rewind,
+    /// Stop a running program and drop into the debugger
+    Break,
+}
+
+/// Somewhere to read the Chip8 keypad from
+pub trait InputSource {
+    /// Which of the 16 keys are currently held down
+    fn keys(&mut self) -> [bool; 16];
+
+    /// Returns `false` once the user has asked to stop the emulator
+    fn is_open(&self) -> bool;
+
+    /// Any `Command`s given since the last frame
+    fn commands(&mut self) -> Vec<Command> {
+        Vec::new()
+    }
+}
+
+/// Somewhere to play the Chip8 buzzer
+pub trait AudioSink {
+    /// Called once per frame, `on` is `true` while the sound timer is running
+    fn tone(&mut self, on: bool);
+
+    /// Called once per frame, before `tone`, with the XO-CHIP audio settings
+    ///
+    /// `pattern` is 128 one bit samples, played at `4000 * 2^((pitch - 64) / 48)`
+    /// samples a second. ROMs for other machines leave this at a square wave.
+    fn pattern(&mut self, _pattern: &[u8; AUDIO_PATTERN_SIZE], _pitch: u8) {}
+}
+
+/// An `AudioSink` that never makes a sound
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullAudio;
+
+impl AudioSink for NullAudio {
+    fn tone(&mut self, _on: bool) {}
+}
+
+// A frontend can be assembled from three separate parts,
+// e.g. `chip8.run(&mut (video, input, NullAudio))`
+
+impl<V: VideoSink, I, A> VideoSink for (V, I, A) {
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize) {
+        self.0.draw(pixels, width, height)
+    }
+}
+
+impl<V, I: InputSource, A> InputSource for (V, I, A) {
+    fn keys(&mut self) -> [bool; 16] {
+        self.1.keys()
+    }
+
+    fn is_open(&self) -> bool {
+        self.1.is_open()
+    }
+
+    fn commands(&mut self) -> Vec<Command> {
+        self.1.commands()
+    }
+}
+
+impl<V, I, A: AudioSink> AudioSink for (V, I, A) {
+    fn tone(&mut self, on: bool) {
+        self.2.tone(on)
+    }
+
+    fn pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_SIZE], pitch: u8) {
+        self.2.pattern(pattern, pitch)
+    }
+}
+
+/// A frontend that is always open, shows nothing and presses nothing
+#[cfg(test)]
+pub struct Headless;
+
+#[cfg(test)]
+impl VideoSink for Headless {
+    fn draw(&mut self, _pixels: &[u8], _width: usize, _height: usize) {}
+}
+
+#[cfg(test)]
+impl InputSource for Headless {
+    fn keys(&mut self) -> [bool; 16] {
+        [false; 16]
+    }
+
+    fn is_open(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+impl AudioSink for Headless {
+    fn tone(&mut self, _on: bool) {}
+}
+
+#[cfg(test)]
+struct CountingVideo(usize);
+
+#[cfg(test)]
+impl VideoSink for CountingVideo {
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize) {
+        assert_eq!(pixels.len(), width * height);
+        self.0 += 1;
+    }
+}
+
+#[cfg(test)]
+struct FramesInput(usize);
+
+#[cfg(test)]
+impl InputSource for FramesInput {
+    fn keys(&mut self) -> [bool; 16] {
+        self.0 -= 1;
+        [false; 16]
+    }
+
+    fn is_open(&self) -> bool {
+        self.0 > 0
+    }
+}
+
+#[test]
+fn run_composed_frontend() {
+    let mut chip8 = ::emulator::Chip8::init(None);
+    // JUMP 0x0200
+    chip8.load_bytes(&[0x12, 0x00]).unwrap();
+    let mut frontend = (CountingVideo(0), FramesInput(3), NullAudio);
+    chip8.run(&mut frontend).unwrap();
+    assert_eq!((frontend.0).0, 3);
+}
@@ -0,0 +1,144 @@
+//
+// Third Party Imports
+//
+use minifb::{WindowOptions, Window, Key, KeyRepeat, Scale};
+
+//
+// This Crate Imports
+//
+use errors::*;
+use interconnect::{SCREEN_WIDTH, SCREEN_HEIGHT};
+use super::{VideoSink, InputSource, AudioSink, Command};
+
+pub const SCREEN_SCALE: usize = 16; // Should be power of 2
+pub const DISPLAY_WIDTH: usize = SCREEN_WIDTH * SCREEN_SCALE;
+pub const DISPLAY_HEIGHT: usize = SCREEN_HEIGHT * SCREEN_SCALE;
+pub const DISPLAY_SIZE: usize = DISPLAY_HEIGHT * DISPLAY_WIDTH;
+const TITLE: &'static str = "Chip8";
+// Background, plane 1, plane 2, and both planes
+const PALETTE: [u32; 4] = [0x00000000, 0x00ffffff, 0x00ff6600, 0x00662200];
+const TITLE_BEEP: &'static str = "Chip8 - BEEP";
+
+fn key_map(key: Key) -> Option<usize> {
+    match key {
+        Key::Key1 => Some(0x0),
+        Key::Key2 => Some(0x1),
+        Key::Key3 => Some(0x2),
+        Key::Key4 => Some(0x3),
+        Key::Q => Some(0x4),
+        Key::W => Some(0x5),
+        Key::E => Some(0x6),
+        Key::R => Some(0x7),
+        Key::A => Some(0x8),
+        Key::S => Some(0x9),
+        Key::D => Some(0xA),
+        Key::F => Some(0xB),
+        Key::Z => Some(0xC),
+        Key::X => Some(0xD),
+        Key::C => Some(0xE),
+        Key::V => Some(0xF),
+        _ => None,
+    }
+}
+
+// F1-F4 save to slots 1-4, F5-F8 load them back, and Backspace is held to rewind.
+// F12 drops back to the debugger prompt.
+fn command_map(key: Key) -> Option<Command> {
+    match key {
+        Key::F1 => Some(Command::SaveSlot(1)),
+        Key::F2 => Some(Command::SaveSlot(2)),
+        Key::F3 => Some(Command::SaveSlot(3)),
+        Key::F4 => Some(Command::SaveSlot(4)),
+        Key::F5 => Some(Command::LoadSlot(1)),
+        Key::F6 => Some(Command::LoadSlot(2)),
+        Key::F7 => Some(Command::LoadSlot(3)),
+        Key::F8 => Some(Command::LoadSlot(4)),
+        Key::F12 => Some(Command::Break),
+        _ => None,
+    }
+}
+
+/// A desktop window, using `minifb`, to show the screen and read the keyboard
+///
+/// `minifb` has no sound output, so the buzzer is shown in the title bar.
+pub struct WindowFrontend {
+    window: Window,
+    buffer: Vec<u32>,
+    beeping: bool,
+}
+
+impl WindowFrontend {
+    /// Open a new window
+    pub fn new() -> Result<Self> {
+        let window = Window::new(TITLE,
+                                 DISPLAY_WIDTH,
+                                 DISPLAY_HEIGHT,
+                                 WindowOptions {
+                                     borderless: false,
+                                     title: true,
+                                     resize: false,
+                                     scale: Scale::X1,
+                                 })
+            .map_err(|e| ErrorKind::WindowError(format!("{}", e)))?;
+        Ok(WindowFrontend {
+            window: window,
+            buffer: vec![0; DISPLAY_SIZE],
+            beeping: false,
+        })
+    }
+}
+
+impl InputSource for WindowFrontend {
+    fn keys(&mut self) -> [bool; 16] {
+        let mut chip8_keys = [false; 16];
+        if let Some(keys) = self.window.get_keys() {
+            for key in keys {
+                if let Some(chip8_key) = key_map(key) {
+                    chip8_keys[chip8_key] = true;
+                }
+            }
+        }
+        chip8_keys
+    }
+
+    fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
+    }
+
+    fn commands(&mut self) -> Vec<Command> {
+        let mut commands: Vec<Command> = self.window
+            .get_keys_pressed(KeyRepeat::No)
+            .map(|keys| keys.into_iter().filter_map(command_map).collect())
+            .unwrap_or_default();
+        if self.window.is_key_down(Key::Backspace) {
+            commands.push(Command::Rewind);
+        }
+        commands
+    }
+}
+
+impl VideoSink for WindowFrontend {
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize) {
+        let scale_x = DISPLAY_WIDTH / width;
+        let scale_y = DISPLAY_HEIGHT / height;
+        for dy in 0..DISPLAY_HEIGHT {
+            let sy = dy / scale_y;
+            for dx in 0..DISPLAY_WIDTH {
+                let sx = dx / scale_x;
+                let display_index = dy * DISPLAY_WIDTH + dx;
+                let screen_index = sy * width + sx;
+                self.buffer[display_index] = PALETTE[pixels[screen_index] as usize & 0x3];
+            }
+        }
+        self.window.update_with_buffer(&self.buffer);
+    }
+}
+
+impl AudioSink for WindowFrontend {
+    fn tone(&mut self, on: bool) {
+        if on != self.beeping {
+            self.beeping = on;
+            self.window.set_title(if on { TITLE_BEEP } else { TITLE });
+        }
+    }
+}
@@ -0,0 +1,609 @@
+//
+// Rust Core Imports
+//
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use cpu::reg;
+use debugger::Breakpoint;
+use emulator::{Chip8, FramePacer, MemAddr, FRAME_NS};
+use frontend::{VideoSink, InputSource, AudioSink};
+use interconnect::{Watchpoint, WatchKind, WatchHit};
+
+//
+// Declare sub modules
+//
+mod packet;
+
+use self::packet::{Event, PacketReader, INTERRUPT, encode, to_hex, from_hex};
+
+// V0-VF, I, PC, SP, DT, ST
+const REGISTERS: usize = 21;
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+const PACKET_SIZE: usize = 0x1000;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGKILL: u8 = 9;
+const SIGSEGV: u8 = 11;
+
+const TARGET_XML: &'static str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.c8e.chip8">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+/// Lets gdb, or anything else speaking the remote serial protocol, debug a ROM
+///
+/// The registers are V0-VF, I, PC, SP (the stack depth), DT and ST, numbered
+/// in that order, with `I` and `PC` 16 bits little endian and the rest 8 bits.
+/// The layout is served to gdb as `target.xml`. Software and hardware
+/// breakpoints, and all three kinds of watchpoint, are supported.
+pub struct GdbServer {
+    listener: TcpListener,
+}
+
+impl GdbServer {
+    /// Listen for a debugger, e.g. on `127.0.0.1:1234`
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        Ok(GdbServer { listener: TcpListener::bind(addr)? })
+    }
+
+    /// Where the server is listening
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Wait for a debugger to connect, then let it control `chip8` until it
+    /// detaches or kills the program
+    ///
+    /// The program starts stopped. The screen is kept up to date the whole time.
+    pub fn serve<F>(&self, chip8: &mut Chip8, frontend: &mut F) -> Result<()>
+        where F: VideoSink + InputSource + AudioSink
+    {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+        let control = stream.try_clone()?;
+        let (tx, rx) = mpsc::channel();
+        // Bytes are read on their own thread, so a Ctrl-C can be seen while
+        // the program is running
+        thread::spawn(move || {
+            let mut buf = [0u8; 1024];
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 || buf[..n].iter().any(|&b| tx.send(b).is_err()) {
+                    break;
+                }
+            }
+        });
+        let result = Session::new(chip8, frontend, rx, stream).run();
+        let _ = control.shutdown(Shutdown::Both);
+        result
+    }
+}
+
+enum Action {
+    Reply(String),
+    Detach,
+    Kill,
+}
+
+struct Session<'a, F: 'a, W> {
+    chip8: &'a mut Chip8,
+    frontend: &'a mut F,
+    input: Receiver<u8>,
+    pending: VecDeque<u8>,
+    output: W,
+    reader: PacketReader,
+    ack: bool,
+    breakpoints: HashMap<MemAddr, usize>,
+    watchpoints: HashMap<(u32, MemAddr, u32), usize>,
+}
+
+fn hex_number(s: &str) -> Option<u32> {
+    u32::from_str_radix(s, 16).ok()
+}
+
+// Parse `addr,len` as used by the memory packets
+fn addr_len(s: &str) -> Option<(MemAddr, usize)> {
+    let mut parts = s.splitn(2, ',');
+    let addr = parts.next().and_then(hex_number)?;
+    let len = parts.next().and_then(hex_number)?;
+    if addr.checked_add(len)? > 0x10000 || len as usize > PACKET_SIZE / 2 {
+        return None;
+    }
+    Some((addr as MemAddr, len as usize))
+}
+
+fn fault_signal(e: &Error) -> u8 {
+    match *e.kind() {
+        ErrorKind::MemoryOutOfBounds(_) |
+        ErrorKind::StackOverflow(_) |
+        ErrorKind::StackUnderflow(_) => SIGSEGV,
+        _ => SIGILL,
+    }
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+fn ok() -> String {
+    "OK".to_string()
+}
+
+impl<'a, F, W> Session<'a, F, W>
+    where F: VideoSink + InputSource + AudioSink,
+          W: Write
+{
+    fn new(chip8: &'a mut Chip8, frontend: &'a mut F, input: Receiver<u8>, output: W) -> Self {
+        Session {
+            chip8: chip8,
+            frontend: frontend,
+            input: input,
+            pending: VecDeque::new(),
+            output: output,
+            reader: PacketReader::new(),
+            ack: true,
+            breakpoints: HashMap::new(),
+            watchpoints: HashMap::new(),
+        }
+    }
+
+    fn send(&mut self, body: &str) -> Result<()> {
+        self.output.write_all(&encode(body.as_bytes()))?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<()> {
+        let frame = Duration::new(0, FRAME_NS);
+        loop {
+            let next = match self.pending.pop_front() {
+                Some(byte) => Ok(byte),
+                None => self.input.recv_timeout(frame),
+            };
+            let byte = match next {
+                Ok(byte) => byte,
+                Err(RecvTimeoutError::Timeout) => {
+                    if !self.frontend.is_open() {
+                        return Ok(());
+                    }
+                    self.chip8.present(self.frontend);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            };
+            match self.reader.push(byte) {
+                None => {}
+                Some(Event::Interrupt) => self.send(&format!("S{:02x}", SIGINT))?,
+                Some(Event::BadChecksum) => self.output.write_all(b"-")?,
+                Some(Event::Packet(body)) => {
+                    if self.ack {
+                        self.output.write_all(b"+")?;
+                    }
+                    let body = String::from_utf8_lossy(&body).into_owned();
+                    match self.handle(&body)? {
+                        Action::Reply(reply) => self.send(&reply)?,
+                        Action::Detach => {
+                            self.send("OK")?;
+                            return Ok(());
+                        }
+                        Action::Kill => return Ok(()),
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &str) -> Result<Action> {
+        let (command, args) = packet.split_at(cmp::min(1, packet.len()));
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => to_hex(&self.registers()),
+            "G" => {
+                match from_hex(args) {
+                    Some(ref bytes) if self.set_registers(bytes) => ok(),
+                    _ => error(),
+                }
+            }
+            "p" => {
+                match hex_number(args).and_then(|n| self.register(n as usize)) {
+                    Some(bytes) => to_hex(&bytes),
+                    None => error(),
+                }
+            }
+            "P" => {
+                let mut parts = args.splitn(2, '=');
+                let n = parts.next().and_then(hex_number);
+                let value = parts.next().and_then(from_hex);
+                match (n, value) {
+                    (Some(n), Some(value)) if self.set_register(n as usize, &value) => ok(),
+                    _ => error(),
+                }
+            }
+            "m" => {
+                match addr_len(args) {
+                    Some((addr, len)) => {
+                        let bytes: Result<Vec<u8>> = (0..len)
+                            .map(|i| self.chip8.read_memory(addr + i as MemAddr))
+                            .collect();
+                        bytes.map(|b| to_hex(&b)).unwrap_or_else(|_| error())
+                    }
+                    None => error(),
+                }
+            }
+            "M" => {
+                let mut parts = args.splitn(2, ':');
+                let range = parts.next().and_then(addr_len);
+                let data = parts.next().and_then(from_hex);
+                match (range, data) {
+                    (Some((addr, len)), Some(ref data)) if data.len() == len => {
+                        let written: Result<Vec<()>> = data.iter()
+                            .enumerate()
+                            .map(|(i, &b)| self.chip8.write_memory(addr + i as MemAddr, b))
+                            .collect();
+                        written.map(|_| ok()).unwrap_or_else(|_| error())
+                    }
+                    _ => error(),
+                }
+            }
+            "Z" => self.breakpoint(true, args),
+            "z" => self.breakpoint(false, args),
+            "s" | "c" => {
+                if let Some(addr) = hex_number(args) {
+                    self.chip8.set_pc(addr as MemAddr);
+                }
+                match self.resume(command == "s") {
+                    Some(reply) => reply,
+                    None => return Ok(Action::Kill),
+                }
+            }
+            "v" => {
+                if packet == "vCont?" {
+                    "vCont;c;s".to_string()
+                } else if let Some(actions) = packet.strip_prefix("vCont;") {
+                    // Only one thread, so the first action is the one for it
+                    let single = actions.starts_with('s');
+                    match self.resume(single) {
+                        Some(reply) => reply,
+                        None => return Ok(Action::Kill),
+                    }
+                } else if packet.starts_with("vKill") {
+                    return Ok(Action::Kill);
+                } else {
+                    String::new()
+                }
+            }
+            "q" => self.query(packet),
+            "Q" => {
+                if packet == "QStartNoAckMode" {
+                    self.ack = false;
+                    ok()
+                } else {
+                    String::new()
+                }
+            }
+            "H" | "T" => ok(),
+            "D" => return Ok(Action::Detach),
+            "k" => return Ok(Action::Kill),
+            _ => String::new(),
+        };
+        Ok(Action::Reply(reply))
+    }
+
+    fn query(&self, packet: &str) -> String {
+        let xfer = "qXfer:features:read:target.xml:";
+        if packet.starts_with("qSupported") {
+            format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;vContSupported+",
+                    PACKET_SIZE)
+        } else if let Some(range) = packet.strip_prefix(xfer) {
+            let mut parts = range.splitn(2, ',');
+            let offset = parts.next().and_then(hex_number);
+            let len = parts.next().and_then(hex_number);
+            match (offset, len) {
+                (Some(offset), Some(len)) => {
+                    let start = cmp::min(offset as usize, TARGET_XML.len());
+                    let end = cmp::min(start + len as usize, TARGET_XML.len());
+                    let more = if end < TARGET_XML.len() { "m" } else { "l" };
+                    format!("{}{}", more, &TARGET_XML[start..end])
+                }
+                _ => error(),
+            }
+        } else if packet == "qAttached" {
+            "1".to_string()
+        } else if packet == "qC" {
+            "QC1".to_string()
+        } else if packet == "qfThreadInfo" {
+            "m1".to_string()
+        } else if packet == "qsThreadInfo" {
+            "l".to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    fn register(&self, n: usize) -> Option<Vec<u8>> {
+        let (delay, sound) = self.chip8.timers();
+        Some(match n {
+            n if n < 16 => vec![self.chip8.register(reg(n as u8).ok()?)],
+            REG_I => vec![self.chip8.index() as u8, (self.chip8.index() >> 8) as u8],
+            REG_PC => vec![self.chip8.pc() as u8, (self.chip8.pc() >> 8) as u8],
+            REG_SP => vec![self.chip8.stack().len() as u8],
+            REG_DT => vec![delay],
+            REG_ST => vec![sound],
+            _ => return None,
+        })
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        (0..REGISTERS).flat_map(|n| self.register(n).unwrap_or_default()).collect()
+    }
+
+    fn set_register(&mut self, n: usize, value: &[u8]) -> bool {
+        match self.register(n) {
+            Some(ref current) if current.len() == value.len() => {}
+            _ => return false,
+        }
+        let (delay, sound) = self.chip8.timers();
+        let wide = value[0] as u16 | (*value.get(1).unwrap_or(&0) as u16) << 8;
+        match n {
+            n if n < 16 => {
+                match reg(n as u8) {
+                    Ok(x) => self.chip8.set_register(x, value[0]),
+                    Err(_) => return false,
+                }
+            }
+            REG_I => self.chip8.set_index(wide),
+            REG_PC => self.chip8.set_pc(wide),
+            // The stack depth can only be changed by calling and returning
+            REG_SP => return value[0] as usize == self.chip8.stack().len(),
+            REG_DT => self.chip8.set_timers(value[0], sound),
+            REG_ST => self.chip8.set_timers(delay, value[0]),
+            _ => return false,
+        }
+        true
+    }
+
+    fn set_registers(&mut self, bytes: &[u8]) -> bool {
+        let mut offset = 0;
+        for n in 0..REGISTERS {
+            let len = self.register(n).map_or(0, |r| r.len());
+            if offset + len > bytes.len() || !self.set_register(n, &bytes[offset..offset + len]) {
+                return false;
+            }
+            offset += len;
+        }
+        offset == bytes.len()
+    }
+
+    // `Z type,addr,kind` and `z type,addr,kind`
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let parts: Vec<u32> = args.split(',').filter_map(hex_number).collect();
+        if parts.len() != 3 || parts[1] > 0xFFFF {
+            return error();
+        }
+        let (kind, addr, len) = (parts[0], parts[1] as MemAddr, parts[2]);
+        match kind {
+            // Software and hardware breakpoints are the same thing here
+            0 | 1 => {
+                if insert {
+                    if !self.breakpoints.contains_key(&addr) {
+                        let id = self.chip8.add_breakpoint(Breakpoint::Addr(addr));
+                        self.breakpoints.insert(addr, id);
+                    }
+                } else if let Some(id) = self.breakpoints.remove(&addr) {
+                    self.chip8.remove_breakpoint(id);
+                }
+                ok()
+            }
+            2..=4 => {
+                let key = (kind, addr, len);
+                if insert {
+                    match (addr as u32).checked_add(len) {
+                        Some(end) if len != 0 && end <= 0x10000 => {}
+                        _ => return error(),
+                    }
+                    let watch_kind = match kind {
+                        2 => WatchKind::Write,
+                        3 => WatchKind::Read,
+                        _ => WatchKind::Access,
+                    };
+                    if !self.watchpoints.contains_key(&key) {
+                        let id = self.chip8.add_watchpoint(Watchpoint {
+                            start: addr,
+                            end: (addr as u32 + len - 1) as MemAddr,
+                            kind: watch_kind,
+                        });
+                        self.watchpoints.insert(key, id);
+                    }
+                } else if let Some(id) = self.watchpoints.remove(&key) {
+                    self.chip8.remove_watchpoint(id);
+                }
+                ok()
+            }
+            _ => String::new(),
+        }
+    }
+
+    fn watch_reply(&self, hit: WatchHit) -> String {
+        let kind = self.chip8
+            .watchpoints()
+            .iter()
+            .find(|(id, _)| *id == hit.id)
+            .map_or(hit.kind, |(_, watchpoint)| watchpoint.kind);
+        let name = match kind {
+            WatchKind::Write => "watch",
+            WatchKind::Read => "rwatch",
+            WatchKind::Access => "awatch",
+        };
+        format!("T{:02x}{}:{:x};", SIGTRAP, name, hit.addr)
+    }
+
+    /// Run until something stops the program, returning the stop reply
+    ///
+    /// `None` means the debugger went away.
+    fn resume(&mut self, single: bool) -> Option<String> {
+        let mut pacer = FramePacer::new();
+        let mut first = true;
+        loop {
+            loop {
+                match self.input.try_recv() {
+                    Ok(INTERRUPT) => return Some(format!("S{:02x}", SIGINT)),
+                    // Anything else waits until the program has stopped
+                    Ok(byte) => self.pending.push_back(byte),
+                    Err(TryRecvError::Empty) => break,
+                    // Finish what was already sent before giving up
+                    Err(TryRecvError::Disconnected) if self.pending.is_empty() => return None,
+                    Err(TryRecvError::Disconnected) => break,
+                }
+            }
+            if !self.frontend.is_open() {
+                return Some(format!("X{:02x}", SIGKILL));
+            }
+            let keys = self.frontend.keys();
+            self.chip8.set_keys(&keys);
+
+            let frame = self.chip8.frames();
+            while self.chip8.frames() == frame {
+                // Always make progress, even when continuing from a breakpoint
+                if !first && self.chip8.breakpoint_hit().is_some() {
+                    return Some(format!("S{:02x}", SIGTRAP));
+                }
+                first = false;
+                let step = match self.chip8.step() {
+                    Ok(step) => step,
+                    Err(e) => return Some(format!("S{:02x}", fault_signal(&e))),
+                };
+                if step.exited {
+                    return Some("W00".to_string());
+                }
+                if let Some(hit) = step.watch {
+                    return Some(self.watch_reply(hit));
+                }
+                if single {
+                    return Some(format!("S{:02x}", SIGTRAP));
+                }
+            }
+            self.chip8.present(self.frontend);
+            pacer.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+fn gdb_session(rom: &[u8], packets: &[&str]) -> Vec<String> {
+    use frontend::Headless;
+    let mut chip8 = Chip8::init(None);
+    chip8.load_bytes(rom).unwrap();
+    let (tx, rx) = mpsc::channel();
+    for packet in packets {
+        for b in encode(packet.as_bytes()) {
+            tx.send(b).unwrap();
+        }
+    }
+    drop(tx);
+    let mut output = Vec::new();
+    Session::new(&mut chip8, &mut Headless, rx, &mut output).run().unwrap();
+
+    // Pull the bodies back out of the framed replies
+    let output = String::from_utf8(output).unwrap();
+    output.split('$').skip(1).map(|p| p.split('#').next().unwrap().to_string()).collect()
+}
+
+#[test]
+fn gdb_protocol() {
+    // LOAD V0 0x7b; LOAD VI 0x0300; BCD V0; JUMP 0x0206
+    let rom = [0x60, 0x7b, 0xa3, 0x00, 0xf0, 0x33, 0x12, 0x06];
+    let replies = gdb_session(&rom,
+                              &["qSupported:swbreak+",
+                                "?",
+                                "g",
+                                "Z0,204,2",
+                                "c",
+                                "p11",
+                                "Z2,301,1",
+                                "s",
+                                "m300,3",
+                                "P0=ff",
+                                "p0",
+                                "M300,1:aa",
+                                "m300,1",
+                                "p12",
+                                "P12=01",
+                                "qXfer:features:read:target.xml:0,5",
+                                "vCont?",
+                                "bogus",
+                                "D"]);
+    let mut registers = "00".repeat(16);
+    registers.push_str("00000002000000");
+    assert_eq!(replies,
+               vec!["PacketSize=1000;qXfer:features:read+;QStartNoAckMode+;vContSupported+",
+                    "S05",
+                    &registers,
+                    "OK",
+                    "S05",
+                    "0402",
+                    "OK",
+                    "T05watch:301;",
+                    "010203",
+                    "OK",
+                    "ff",
+                    "OK",
+                    "aa",
+                    "00",
+                    "E01",
+                    "m<?xml",
+                    "vCont;c;s",
+                    "",
+                    "OK"]);
+}
+
+#[test]
+fn gdb_rejects_overflowing_ranges() {
+    let replies = gdb_session(&[0x12, 0x00],
+                              &["mffffffff,1",
+                                "Mffffffff,1:00",
+                                "m200,ffffffff",
+                                "Z2,ffff,ffffffff",
+                                "Z2,ffff,1",
+                                "D"]);
+    assert_eq!(replies, vec!["E01", "E01", "E01", "E01", "OK", "OK"]);
+}
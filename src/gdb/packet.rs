@@ -0,0 +1,169 @@
+//
+// Rust Core Imports
+//
+use std::fmt::Write;
+
+pub const INTERRUPT: u8 = 0x03;
+const ESCAPE: u8 = b'}';
+
+/// Something read from the debugger
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The body of a packet with a good checksum
+    Packet(Vec<u8>),
+    /// A packet was damaged on the way, and should be sent again
+    BadChecksum,
+    /// Ctrl-C, stop the program
+    Interrupt,
+}
+
+enum State {
+    Idle,
+    Body,
+    Checksum(Option<u8>),
+}
+
+/// Splits the incoming bytes into `Event`s, one byte at a time
+pub struct PacketReader {
+    state: State,
+    body: Vec<u8>,
+}
+
+fn checksum(body: &[u8]) -> u8 {
+    body.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
+
+impl PacketReader {
+    pub fn new() -> Self {
+        PacketReader {
+            state: State::Idle,
+            body: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<Event> {
+        match self.state {
+            State::Idle => {
+                match byte {
+                    b'$' => {
+                        self.body.clear();
+                        self.state = State::Body;
+                    }
+                    INTERRUPT => return Some(Event::Interrupt),
+                    // Acks for what we sent, and line noise
+                    _ => {}
+                }
+                None
+            }
+            State::Body => {
+                if byte == b'#' {
+                    self.state = State::Checksum(None);
+                } else {
+                    self.body.push(byte);
+                }
+                None
+            }
+            State::Checksum(None) => {
+                self.state = State::Checksum(Some(byte));
+                None
+            }
+            State::Checksum(Some(high)) => {
+                self.state = State::Idle;
+                let sum = match (hex_digit(high), hex_digit(byte)) {
+                    (Some(high), Some(low)) => high << 4 | low,
+                    _ => return Some(Event::BadChecksum),
+                };
+                if sum != checksum(&self.body) {
+                    return Some(Event::BadChecksum);
+                }
+                Some(Event::Packet(unescape(&self.body)))
+            }
+        }
+    }
+}
+
+fn unescape(body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len());
+    let mut iter = body.iter();
+    while let Some(&b) = iter.next() {
+        if b == ESCAPE {
+            if let Some(&next) = iter.next() {
+                out.push(next ^ 0x20);
+            }
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
+
+/// Frame `body` as `$body#checksum`, escaping anything special
+pub fn encode(body: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(body.len());
+    for &b in body {
+        match b {
+            b'$' | b'#' | b'}' | b'*' => {
+                escaped.push(ESCAPE);
+                escaped.push(b ^ 0x20);
+            }
+            _ => escaped.push(b),
+        }
+    }
+    let mut packet = vec![b'$'];
+    packet.extend_from_slice(&escaped);
+    packet.extend_from_slice(format!("#{:02x}", checksum(&escaped)).as_bytes());
+    packet
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(out, "{:02x}", b).unwrap();
+    }
+    out
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    let digits = hex.as_bytes();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits.chunks(2)
+        .map(|pair| match (hex_digit(pair[0]), hex_digit(pair[1])) {
+            (Some(high), Some(low)) => Some(high << 4 | low),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn packet_round_trip() {
+    let mut reader = PacketReader::new();
+    let mut events = Vec::new();
+    let mut input = b"+".to_vec();
+    input.extend(encode(b"m200,4"));
+    input.extend(encode(b"X200,1:}#"));
+    input.push(INTERRUPT);
+    input.extend(b"$g#00");
+    for b in input {
+        events.extend(reader.push(b));
+    }
+    assert_eq!(events,
+               vec![Event::Packet(b"m200,4".to_vec()),
+                    Event::Packet(b"X200,1:}#".to_vec()),
+                    Event::Interrupt,
+                    Event::BadChecksum]);
+    assert_eq!(encode(b"OK"), b"$OK#9a".to_vec());
+}
+
+#[test]
+fn hex() {
+    assert_eq!(to_hex(&[0x00, 0xab, 0x10]), "00ab10");
+    assert_eq!(from_hex("00AB10"), Some(vec![0x00, 0xab, 0x10]));
+    assert_eq!(from_hex("0"), None);
+    assert_eq!(from_hex("zz"), None);
+}
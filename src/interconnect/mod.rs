@@ -1,7 +1,9 @@
 //
 // Rust Core Imports
 //
+use std::cell::{Cell, RefCell};
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::fs;
 use std::io::Read;
 
@@ -14,22 +16,37 @@ use slog;
 // This Crate Imports
 //
 use errors::*;
-use emulator::{MemAddr, PROGRAM_START};
+use coverage::{Access, Coverage};
+use emulator::{Config, MemAddr, PROGRAM_START};
+use quirks::Machine;
+use state::{StateReader, StateWriter};
+use trace::MemWrite;
 
 //
 // Declare sub modules
 //
+mod watch;
 
 //
 // Public Exports
 //
+pub use self::watch::{Watchpoint, WatchKind, WatchHit};
 
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
-const GRAPHICS_SIZE: usize = (SCREEN_WIDTH * SCREEN_HEIGHT);
-const MEM_SIZE: usize = (1024 * 4);
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+const GRAPHICS_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+const MEM_SIZE: usize = 1024 * 4;
+const XO_MEM_SIZE: usize = 1024 * 64;
+const PLANES: usize = 2;
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+const DEFAULT_PITCH: u8 = 64; // 4000Hz
 const FONTS_START: usize = 0;
 const FONT_SIZE: usize = 5;
+const BIG_FONTS_START: usize = FONTS_START + 16 * FONT_SIZE;
+const BIG_FONT_SIZE: usize = 10;
+const SCROLL_SIDEWAYS: usize = 4;
 
 const CHAR_SPRITES: [u8; 80] = [// 0
                                 0b11110000,
@@ -143,11 +160,67 @@ const CHAR_SPRITES: [u8; 80] = [// 0
                                 0b10000000,
                                 0b10000000];
 
+/// What to do when the ROM touches memory outside of RAM
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MemoryPolicy {
+    /// Wrap the address around at the end of RAM
+    Wrap,
+    /// Stop with `ErrorKind::MemoryOutOfBounds`
+    #[default]
+    Fault,
+    /// Log the access, reads return 0 and writes are dropped
+    Ignore,
+}
+
+impl FromStr for MemoryPolicy {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "wrap" => Ok(MemoryPolicy::Wrap),
+            "fault" => Ok(MemoryPolicy::Fault),
+            "ignore" => Ok(MemoryPolicy::Ignore),
+            _ => bail!(ErrorKind::UnknownOption("memory policy", s.to_string())),
+        }
+    }
+}
+
+// SUPER-CHIP 8x10 digits, with A-F added to match
+const BIG_CHAR_SPRITES: [u8; 160] = [0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+                                     0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+                                     0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+                                     0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+                                     0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+                                     0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+                                     0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+                                     0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+                                     0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+                                     0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+                                     0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+                                     0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+                                     0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+                                     0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+                                     0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+                                     0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0]; // F
+
+#[derive(Clone)]
 pub struct Interconnect {
     keys: [bool; 16],
     ram: Vec<u8>,
-    pub graphics: [bool; GRAPHICS_SIZE],
+    memory_policy: MemoryPolicy,
+    clip_sprites: bool,
+    hires: bool,
+    planes: u8,
+    /// Each pixel holds one bit per plane, so 0-3 with both planes
+    pub graphics: Vec<u8>,
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
     block_key: Option<u8>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    watch_hit: Cell<Option<WatchHit>>,
+    // Only collected while an instruction is being traced
+    writes: Option<Vec<MemWrite>>,
+    // Only collected while coverage is being recorded
+    coverage: RefCell<Option<Coverage>>,
     logger: slog::Logger,
 }
 
@@ -158,71 +231,156 @@ fn bit_index(byte: u8, index: usize) -> bool {
 }
 
 impl Interconnect {
-    pub fn init(logger: slog::Logger) -> Self {
+    pub fn init(logger: slog::Logger, config: &Config) -> Self {
         let mut ic = Interconnect {
             keys: [false; 16],
-            ram: vec![0; MEM_SIZE],
-            graphics: [false; GRAPHICS_SIZE],
+            ram: vec![0;
+                      if config.machine == Machine::XoChip {
+                          XO_MEM_SIZE
+                      } else {
+                          MEM_SIZE
+                      }],
+            memory_policy: config.memory_policy,
+            clip_sprites: config.quirks.clip_sprites,
+            hires: false,
+            planes: 0x1,
+            graphics: vec![0; GRAPHICS_SIZE],
+            audio_pattern: [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0],
+            pitch: DEFAULT_PITCH,
             block_key: None,
+            watchpoints: Vec::new(),
+            watch_hit: Cell::new(None),
+            writes: None,
+            coverage: RefCell::new(None),
             logger: logger,
         };
-        for idx in 0..CHAR_SPRITES.len() {
-            ic.ram[idx + FONTS_START] = CHAR_SPRITES[idx];
-        }
+        ic.ram[FONTS_START..FONTS_START + CHAR_SPRITES.len()].copy_from_slice(&CHAR_SPRITES);
+        ic.ram[BIG_FONTS_START..BIG_FONTS_START + BIG_CHAR_SPRITES.len()]
+            .copy_from_slice(&BIG_CHAR_SPRITES);
         ic
     }
 
-    pub fn display_keys(&self) -> String {
-        let mut result = "Keys [".to_string();
-        for i in 0..16 {
-            if self.keys[i] {
-                result.push_str(&format!("{:x}, ", i));
+    pub fn keys(&self) -> [bool; 16] {
+        self.keys
+    }
+
+    pub fn get_font(&self, char: u8) -> usize {
+        FONTS_START + (char as usize & 0xF) * FONT_SIZE
+    }
+
+    pub fn get_big_font(&self, char: u8) -> usize {
+        BIG_FONTS_START + (char as usize & 0xF) * BIG_FONT_SIZE
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_SCREEN_WIDTH } else { SCREEN_WIDTH }
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_SCREEN_HEIGHT } else { SCREEN_HEIGHT }
+    }
+
+    /// Switch between the 64x32 and 128x64 screen, this clears the screen
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.graphics = vec![0; self.width() * self.height()];
+    }
+
+    /// Select which planes are drawn to, cleared and scrolled
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & ((1 << PLANES) - 1);
+    }
+
+    /// Move everything on the selected planes, pixels pushed off the edge are lost
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let old = self.graphics.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let moved = if sx < 0 || sx >= width || sy < 0 || sy >= height {
+                    0
+                } else {
+                    old[(sy * width + sx) as usize] & self.planes
+                };
+                let pixel = &mut self.graphics[(y * width + x) as usize];
+                *pixel = (*pixel & !self.planes) | moved;
             }
         }
-        result.push_str(&format!("]"));
-        result
     }
 
-    pub fn get_font(&self, char: u8) -> usize {
-        FONTS_START + char as usize * FONT_SIZE
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.scroll(0, rows as isize);
+    }
+
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.scroll(0, -(rows as isize));
+    }
+
+    pub fn scroll_right(&mut self) {
+        self.scroll(SCROLL_SIDEWAYS as isize, 0);
+    }
+
+    pub fn scroll_left(&mut self) {
+        self.scroll(-(SCROLL_SIDEWAYS as isize), 0);
     }
 
     #[inline]
     fn map_screen(&self, idx: usize, idy: usize) -> Option<usize> {
-        // if idx > SCREEN_WIDTH || idy > SCREEN_HEIGHT {
-        //     return None
-        // }
-        //debug!(self.logger, "map_screen"; "loc" => format!("({}, {})", idx, idy));
-        Some((idy % SCREEN_HEIGHT) * SCREEN_WIDTH + (idx % SCREEN_WIDTH))
+        let (width, height) = (self.width(), self.height());
+        if self.clip_sprites && (idx >= width || idy >= height) {
+            return None;
+        }
+        Some((idy % height) * width + (idx % width))
     }
 
-    pub fn draw_sprite(&mut self, loc: usize, idx: usize, idy: usize, sprite_size: usize) -> bool {
+    /// XOR a sprite onto the screen, returning `true` if any pixel was erased
+    ///
+    /// Sprites are `sprite_width` pixels wide (8 or 16), with each row stored
+    /// in `sprite_width / 8` consecutive bytes. Each selected plane takes its
+    /// own copy of the sprite, one after the other in memory.
+    pub fn draw_sprite(&mut self,
+                       loc: usize,
+                       idx: usize,
+                       idy: usize,
+                       sprite_width: usize,
+                       sprite_size: usize)
+                       -> Result<bool> {
+        // The starting position always wraps, only the sprite body is clipped
+        let idx = idx % self.width();
+        let idy = idy % self.height();
         let mut sprite_ptr = loc;
         let mut pixel_collision = false;
-        for row in 0..sprite_size {
-            let sprite_byte = self.ram[sprite_ptr];
-            sprite_ptr += 1;
-            for col in 0..8 {
-                let sprite_pixel = bit_index(sprite_byte, col);
-                if let Some(coord) = self.map_screen(idx + col, idy + row) {
-                    let current_pixel = self.graphics[coord];
-                    let new_pixel = match (current_pixel, sprite_pixel) {
-                        (true, true) => {
+        for plane in 0..PLANES {
+            let plane_bit = 1 << plane;
+            if self.planes & plane_bit == 0 {
+                continue;
+            }
+            for row in 0..sprite_size {
+                let mut sprite_row = Vec::with_capacity(sprite_width / 8);
+                for _ in 0..(sprite_width / 8) {
+                    sprite_row.push(self.covered_byte(sprite_ptr, Access::Draw)?);
+                    sprite_ptr += 1;
+                }
+                for col in 0..sprite_width {
+                    if !bit_index(sprite_row[col / 8], col % 8) {
+                        continue;
+                    }
+                    if let Some(coord) = self.map_screen(idx + col, idy + row) {
+                        if self.graphics[coord] & plane_bit != 0 {
                             pixel_collision = true;
-                            false
                         }
-                        (false, true) => true,
-                        (true, false) => true,
-                        (false, false) => false,
-                    };
-                    self.graphics[coord] = new_pixel;
-                } else {
-                    debug!(self.logger, "draw_sprite";
-                           "out of bounds" => format!("({}, {})", idx+col , idy+row));
+                        self.graphics[coord] ^= plane_bit;
+                    } else {
+                        debug!(self.logger, "draw_sprite";
+                               "out of bounds" => format!("({}, {})", idx+col , idy+row));
+                    }
                 }
             }
         }
-        pixel_collision
+        Ok(pixel_collision)
     }
 
     pub fn reset_keys(&mut self) {
@@ -236,8 +394,11 @@ impl Interconnect {
         self.keys[key] = true;
     }
 
-    pub fn check_key(&self, key: usize) -> bool {
-        self.keys[key]
+    pub fn check_key(&self, key: u8) -> Result<bool> {
+        match self.keys.get(key as usize) {
+            Some(&down) => Ok(down),
+            None => bail!(ErrorKind::InvalidKey(key)),
+        }
     }
 
     pub fn get_key(&mut self) -> Option<u8> {
@@ -246,26 +407,360 @@ impl Interconnect {
 
     pub fn load_rom(&mut self, path: PathBuf) -> Result<usize> {
         let mut file = fs::File::open(&path)?;
-        let bytes = file.read(&mut self.ram[PROGRAM_START..])?;
+        let mut rom = Vec::new();
+        file.read_to_end(&mut rom)?;
+        let bytes = self.load_bytes(&rom)?;
         info!(self.logger, "load_rom"; "file" => path.as_path().to_str(), "size" => bytes);
         Ok(bytes)
     }
 
-    pub fn write_byte(&mut self, addr: MemAddr, byte: u8) {
-        self.ram[addr as usize] = byte;
+    pub fn load_bytes(&mut self, rom: &[u8]) -> Result<usize> {
+        let space = self.ram.len() - PROGRAM_START;
+        if rom.len() > space {
+            bail!(ErrorKind::RomTooLarge(rom.len(), space));
+        }
+        self.ram[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+        Ok(rom.len())
+    }
+
+    /// Find where in RAM `addr` lives, according to the `MemoryPolicy`
+    ///
+    /// `None` means the access should be ignored.
+    #[inline]
+    fn map_addr(&self, addr: usize) -> Result<Option<usize>> {
+        if addr < self.ram.len() {
+            return Ok(Some(addr));
+        }
+        match self.memory_policy {
+            MemoryPolicy::Wrap => Ok(Some(addr % self.ram.len())),
+            MemoryPolicy::Fault => bail!(ErrorKind::MemoryOutOfBounds(addr)),
+            MemoryPolicy::Ignore => {
+                warn!(self.logger, "map_addr"; "out of bounds" => format!("0x{:04x}", addr));
+                Ok(None)
+            }
+        }
+    }
+
+    #[inline]
+    fn ram_byte(&self, addr: usize) -> Result<u8> {
+        Ok(match self.map_addr(addr)? {
+            Some(addr) => self.ram[addr],
+            None => 0,
+        })
+    }
+
+    // A byte used by an instruction, noted in the coverage map
+    #[inline]
+    fn covered_byte(&self, addr: usize, access: Access) -> Result<u8> {
+        Ok(match self.map_addr(addr)? {
+            Some(addr) => {
+                self.cover(addr, access);
+                self.ram[addr]
+            }
+            None => 0,
+        })
+    }
+
+    fn cover(&self, addr: usize, access: Access) {
+        if let Some(ref mut coverage) = *self.coverage.borrow_mut() {
+            coverage.record(addr, access);
+        }
+    }
+
+    /// Note the `len` bytes of the instruction at `addr` as run
+    pub fn cover_instruction(&self, addr: MemAddr, len: u16) {
+        for offset in 0..len {
+            let access = if offset == 0 { Access::Fetch } else { Access::Operand };
+            if let Ok(Some(addr)) = self.map_addr(addr as usize + offset as usize) {
+                self.cover(addr, access);
+            }
+        }
+    }
+
+    /// Start noting how memory is used, keeping any map already started
+    pub fn record_coverage(&mut self) {
+        let size = self.ram.len();
+        self.coverage.get_mut().get_or_insert_with(|| Coverage::new(size));
+    }
+
+    /// Stop noting how memory is used, returning the map so far
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.get_mut().take()
+    }
+
+    /// Store a byte for the CPU, checking the watchpoints
+    pub fn write_byte(&mut self, addr: MemAddr, byte: u8) -> Result<()> {
+        if let Some(addr) = self.map_addr(addr as usize)? {
+            let old = self.ram[addr];
+            self.ram[addr] = byte;
+            self.watch(addr, WatchKind::Write, old, byte);
+            self.cover(addr, Access::Write);
+            if let Some(ref mut writes) = self.writes {
+                writes.push(MemWrite {
+                    addr: addr as MemAddr,
+                    old: old,
+                    new: byte,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a byte for the CPU, checking the watchpoints
+    pub fn read_byte(&self, addr: MemAddr) -> Result<u8> {
+        Ok(match self.map_addr(addr as usize)? {
+            Some(addr) => {
+                let value = self.ram[addr];
+                self.watch(addr, WatchKind::Read, value, value);
+                self.cover(addr, Access::Read);
+                value
+            }
+            None => 0,
+        })
+    }
+
+    /// Read memory without setting off any watchpoints
+    pub fn peek(&self, addr: MemAddr) -> Result<u8> {
+        self.ram_byte(addr as usize)
+    }
+
+    /// Write memory without setting off any watchpoints
+    pub fn poke(&mut self, addr: MemAddr, byte: u8) -> Result<()> {
+        if let Some(addr) = self.map_addr(addr as usize)? {
+            self.ram[addr] = byte;
+        }
+        Ok(())
     }
 
-    pub fn read_byte(&self, addr: MemAddr) -> u8 {
-        self.ram[addr as usize]
+    // Only the first hit is kept, until it is taken
+    fn watch(&self, addr: usize, kind: WatchKind, old: u8, new: u8) {
+        if self.watch_hit.get().is_some() {
+            return;
+        }
+        if let Some(&(id, _)) = self.watchpoints.iter().find(|(_, w)| w.watches(addr, kind)) {
+            self.watch_hit.set(Some(WatchHit {
+                id: id,
+                addr: addr as MemAddr,
+                kind: kind,
+                old: old,
+                new: new,
+            }));
+        }
+    }
+
+    pub fn add_watchpoint(&mut self, id: usize, watchpoint: Watchpoint) {
+        self.watchpoints.push((id, watchpoint));
+    }
+
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|&(w_id, _)| w_id != id);
+        self.watchpoints.len() != before
+    }
+
+    pub fn watchpoints(&self) -> &[(usize, Watchpoint)] {
+        &self.watchpoints
+    }
+
+    /// The first watchpoint hit since this was last called
+    pub fn take_watch_hit(&self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    /// Start collecting the writes made by the CPU
+    pub fn record_writes(&mut self) {
+        self.writes = Some(Vec::new());
+    }
+
+    /// Stop collecting writes, and return what was written
+    pub fn take_writes(&mut self) -> Vec<MemWrite> {
+        self.writes.take().unwrap_or_default()
     }
 
     pub fn clear_sceen(&mut self) {
-        self.graphics = [false; GRAPHICS_SIZE];
+        for pixel in self.graphics.iter_mut() {
+            *pixel &= !self.planes;
+        }
+    }
+
+    /// Fill the audio pattern buffer from memory
+    pub fn load_audio(&mut self, addr: MemAddr) -> Result<()> {
+        for idx in 0..AUDIO_PATTERN_SIZE {
+            self.audio_pattern[idx] = self.covered_byte(addr as usize + idx, Access::Read)?;
+        }
+        Ok(())
     }
 
-    pub fn read_halfword(&self, addr: MemAddr) -> u16 {
-        let x = self.ram[addr as usize];
-        let y = self.ram[(addr + 1) as usize];
-        (x as u16) << 8 | y as u16
+    pub fn audio_pattern(&self) -> &[u8; AUDIO_PATTERN_SIZE] {
+        &self.audio_pattern
     }
+
+    pub fn set_pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn read_halfword(&self, addr: MemAddr) -> Result<u16> {
+        let x = self.ram_byte(addr as usize)?;
+        let y = self.ram_byte(addr as usize + 1)?;
+        Ok((x as u16) << 8 | y as u16)
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.ram);
+        for &key in self.keys.iter() {
+            state.bool(key);
+        }
+        state.bool(self.hires);
+        state.u8(self.planes);
+        state.bytes(&self.graphics);
+        state.bytes(&self.audio_pattern);
+        state.u8(self.pitch);
+        state.bool(self.block_key.is_some());
+        state.u8(self.block_key.unwrap_or(0));
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<()> {
+        let ram_size = self.ram.len();
+        self.ram.copy_from_slice(state.bytes(ram_size)?);
+        for key in self.keys.iter_mut() {
+            *key = state.bool()?;
+        }
+        self.set_hires(state.bool()?);
+        self.select_planes(state.u8()?);
+        let graphics_size = self.graphics.len();
+        self.graphics.copy_from_slice(state.bytes(graphics_size)?);
+        self.audio_pattern.copy_from_slice(state.bytes(AUDIO_PATTERN_SIZE)?);
+        self.pitch = state.u8()?;
+        let blocked = state.bool()?;
+        let key = state.u8()?;
+        self.block_key = if blocked { Some(key) } else { None };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn test_interconnect_config(config: &Config) -> Interconnect {
+    use slog::DrainExt;
+    let logger = slog::Logger::root(::slog_stdlog::StdLog.fuse(), o!());
+    Interconnect::init(logger, config)
+}
+
+#[cfg(test)]
+fn test_interconnect(memory_policy: MemoryPolicy) -> Interconnect {
+    let config = Config { memory_policy: memory_policy, ..Config::default() };
+    test_interconnect_config(&config)
+}
+
+#[test]
+fn memory_policy_wrap() {
+    let mut ic = test_interconnect(MemoryPolicy::Wrap);
+    ic.write_byte(0x1001, 0xab).unwrap();
+    assert_eq!(ic.read_byte(0x0001).unwrap(), 0xab);
+    assert_eq!(ic.read_halfword(0x0fff).unwrap(), 0x00f0);
+    // A 15 row sprite straddling the end of memory
+    assert!(ic.draw_sprite(0xff8, 0, 0, 8, 15).is_ok());
+}
+
+#[test]
+fn memory_policy_fault() {
+    let mut ic = test_interconnect(MemoryPolicy::Fault);
+    assert!(ic.write_byte(0x1000, 0xab).is_err());
+    assert!(ic.read_byte(0x1000).is_err());
+    assert!(ic.read_halfword(0x0fff).is_err());
+    assert!(ic.draw_sprite(0xff8, 0, 0, 8, 15).is_err());
+}
+
+#[test]
+fn memory_policy_ignore() {
+    let mut ic = test_interconnect(MemoryPolicy::Ignore);
+    ic.write_byte(0x1001, 0xab).unwrap();
+    assert_eq!(ic.read_byte(0x0001).unwrap(), 0x90);
+    assert_eq!(ic.read_byte(0x1001).unwrap(), 0);
+}
+
+#[test]
+fn parse_memory_policy() {
+    assert_eq!("wrap".parse::<MemoryPolicy>().unwrap(), MemoryPolicy::Wrap);
+    assert!("panic".parse::<MemoryPolicy>().is_err());
+}
+
+#[test]
+fn sprite_clip_and_wrap() {
+    let mut ic = test_interconnect(MemoryPolicy::Fault);
+    // Font "0" drawn two pixels from the bottom right corner
+    let wrapped = (SCREEN_HEIGHT - 2) * SCREEN_WIDTH;
+    ic.draw_sprite(0, SCREEN_WIDTH - 2, SCREEN_HEIGHT - 2, 8, 5).unwrap();
+    assert_eq!(ic.graphics[wrapped], 1);
+
+    ic.clear_sceen();
+    ic.clip_sprites = true;
+    ic.draw_sprite(0, SCREEN_WIDTH - 2, SCREEN_HEIGHT - 2, 8, 5).unwrap();
+    assert_eq!(ic.graphics[wrapped], 0);
+    assert_eq!(ic.graphics[(SCREEN_HEIGHT - 2) * SCREEN_WIDTH + SCREEN_WIDTH - 2], 1);
+
+    // The start position still wraps when clipping
+    ic.clear_sceen();
+    ic.draw_sprite(0, SCREEN_WIDTH, SCREEN_HEIGHT, 8, 5).unwrap();
+    assert_eq!(ic.graphics[0], 1);
+}
+
+#[test]
+fn bitplanes() {
+    let config = Config { machine: Machine::XoChip, ..Config::default() };
+    let mut ic = test_interconnect_config(&config);
+    assert_eq!(ic.ram.len(), XO_MEM_SIZE);
+
+    // Both planes at once take consecutive sprites, so "0" and then "1"
+    ic.select_planes(3);
+    assert!(!ic.draw_sprite(0, 0, 0, 8, 5).unwrap());
+    assert_eq!(ic.graphics[0], 1);
+    assert_eq!(ic.graphics[2], 3);
+    ic.select_planes(2);
+    assert!(ic.draw_sprite(5, 0, 0, 8, 5).unwrap());
+    assert_eq!(ic.graphics[2], 1);
+    assert!(!ic.draw_sprite(0, 0, 0, 8, 5).unwrap());
+    assert_eq!(ic.graphics[1], 3);
+
+    // Clearing and scrolling only touch the selected planes
+    ic.select_planes(1);
+    ic.scroll_up(1);
+    assert_eq!(ic.graphics[1], 2);
+    ic.clear_sceen();
+    assert!(ic.graphics.iter().all(|&p| p & 1 == 0));
+    assert!(ic.graphics.iter().any(|&p| p & 2 != 0));
+}
+
+#[test]
+fn watchpoints() {
+    let mut ic = test_interconnect(MemoryPolicy::Wrap);
+    ic.add_watchpoint(1, "0x300+4".parse().unwrap());
+    ic.add_watchpoint(2, Watchpoint::parse("0x302", WatchKind::Read).unwrap());
+    ic.read_byte(0x301).unwrap();
+    assert_eq!(ic.take_watch_hit(), None);
+
+    ic.write_byte(0x1301, 0xab).unwrap();
+    ic.write_byte(0x302, 0xcd).unwrap();
+    assert_eq!(ic.take_watch_hit(),
+               Some(WatchHit {
+                   id: 1,
+                   addr: 0x301,
+                   kind: WatchKind::Write,
+                   old: 0,
+                   new: 0xab,
+               }));
+    assert_eq!(ic.take_watch_hit(), None);
+
+    assert_eq!(ic.read_byte(0x302).unwrap(), 0xcd);
+    assert_eq!(ic.take_watch_hit().map(|hit| hit.id), Some(2));
+    ic.poke(0x300, 1).unwrap();
+    assert_eq!(ic.peek(0x300).unwrap(), 1);
+    assert_eq!(ic.take_watch_hit(), None);
+
+    assert!(ic.remove_watchpoint(1));
+    ic.write_byte(0x301, 0).unwrap();
+    assert_eq!(ic.take_watch_hit(), None);
 }
@@ -0,0 +1,143 @@
+//
+// Rust Core Imports
+//
+use std::fmt;
+use std::str::FromStr;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use emulator::MemAddr;
+
+/// Which memory accesses a `Watchpoint` stops on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatchKind {
+    /// Loads from memory, like `REG.LOAD`
+    Read,
+    /// Stores to memory, like `REG.DUMP` and `BCD`
+    Write,
+    /// Both reads and writes
+    Access,
+}
+
+/// Stop the program when an instruction touches memory from `start` to `end`
+///
+/// Parsed from `0x300`, `0x300-0x30f` or `0x300+16`, with the range inclusive.
+/// Only data accesses by instructions are watched, not fetching instructions,
+/// drawing sprites or the debugger itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Watchpoint {
+    /// First address watched
+    pub start: MemAddr,
+    /// Last address watched
+    pub end: MemAddr,
+    /// The accesses to stop on
+    pub kind: WatchKind,
+}
+
+/// The memory access that set off a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit {
+    /// The id of the watchpoint
+    pub id: usize,
+    /// Where in RAM was accessed
+    pub addr: MemAddr,
+    /// Either `WatchKind::Read` or `WatchKind::Write`
+    pub kind: WatchKind,
+    /// The value before the access
+    pub old: u8,
+    /// The value after the access, the same as `old` for a read
+    pub new: u8,
+}
+
+impl Watchpoint {
+    /// Parse a range in the form `0x300`, `0x300-0x30f` or `0x300+16`
+    pub fn parse(range: &str, kind: WatchKind) -> Result<Self> {
+        let bad = || Error::from(ErrorKind::UnknownOption("address range", range.to_string()));
+        let number = |s: &str| -> Result<u32> {
+            let parsed = if let Some(hex) = s.strip_prefix("0x") {
+                u32::from_str_radix(hex, 16)
+            } else {
+                s.parse()
+            };
+            parsed.map_err(|_| bad())
+        };
+        let (start, end) = if let Some(idx) = range.find('-') {
+            (number(&range[..idx])?, number(&range[idx + 1..])?)
+        } else if let Some(idx) = range.find('+') {
+            let start = number(&range[..idx])?;
+            let len = number(&range[idx + 1..])?;
+            if len == 0 {
+                return Err(bad());
+            }
+            (start, start.checked_add(len - 1).ok_or_else(bad)?)
+        } else {
+            let addr = number(range)?;
+            (addr, addr)
+        };
+        if start > end || end > 0xFFFF {
+            return Err(bad());
+        }
+        Ok(Watchpoint {
+            start: start as MemAddr,
+            end: end as MemAddr,
+            kind: kind,
+        })
+    }
+
+    /// Does this watch a `kind` access to `addr`?
+    pub fn watches(&self, addr: usize, kind: WatchKind) -> bool {
+        let kind_matches = self.kind == WatchKind::Access || self.kind == kind;
+        kind_matches && self.start as usize <= addr && addr <= self.end as usize
+    }
+}
+
+impl FromStr for Watchpoint {
+    type Err = Error;
+    /// A write watchpoint, which is what is usually wanted
+    fn from_str(s: &str) -> Result<Self> {
+        Watchpoint::parse(s, WatchKind::Write)
+    }
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{}",
+               match *self {
+                   WatchKind::Read => "read",
+                   WatchKind::Write => "write",
+                   WatchKind::Access => "access",
+               })
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{} 0x{:04x}", self.kind, self.start)
+        } else {
+            write!(f, "{} 0x{:04x}-0x{:04x}", self.kind, self.start, self.end)
+        }
+    }
+}
+
+#[test]
+fn parse_watchpoints() {
+    let watch = |s: &str| Watchpoint::parse(s, WatchKind::Read).map(|w| (w.start, w.end));
+    assert_eq!(watch("0x300").unwrap(), (0x300, 0x300));
+    assert_eq!(watch("0x300-0x30f").unwrap(), (0x300, 0x30f));
+    assert_eq!(watch("0x300+16").unwrap(), (0x300, 0x30f));
+    assert!(watch("0x300+0").is_err());
+    assert!(watch("0x30f-0x300").is_err());
+    assert!(watch("0xffff+2").is_err());
+    assert!(watch("0xffffffff+2").is_err());
+    assert!(watch("v0").is_err());
+
+    let write: Watchpoint = "0x300+2".parse().unwrap();
+    assert!(write.watches(0x301, WatchKind::Write));
+    assert!(!write.watches(0x301, WatchKind::Read));
+    assert!(!write.watches(0x302, WatchKind::Write));
+    assert_eq!(format!("{}", write), "write 0x0300-0x0301");
+}
@@ -1,5 +1,9 @@
 #![warn(missing_docs)]
 #![recursion_limit = "1024"]
+// Idioms this crate keeps on purpose
+#![allow(clippy::redundant_field_names,
+         clippy::redundant_static_lifetimes,
+         clippy::match_ref_pats)]
 
 //! Everything needed to build a Chip8 System
 
@@ -10,17 +14,47 @@ extern crate error_chain;
 pub extern crate slog;
 extern crate slog_stdlog;
 
+#[cfg(feature = "minifb")]
 extern crate minifb;
 extern crate rand;
+#[macro_use]
+extern crate serde_json;
 
+mod asm;
+mod coverage;
 mod cpu;
+mod dap;
+mod debugger;
+mod disasm;
 mod emulator;
+#[allow(missing_docs, deprecated)] // error_chain! expansion
 mod errors;
+mod frontend;
+mod gdb;
 mod interconnect;
-
-
-pub use emulator::Chip8;
+mod quirks;
+mod rewind;
+mod state;
+mod trace;
+
+
+pub use asm::{Program, assemble, assemble_octo, disassemble};
+pub use coverage::{Coverage, Access};
+pub use cpu::{Opcode, Reg};
+pub use debugger::{Debugger, Breakpoint};
+pub use disasm::{Disassembly, Usage, CoverageSummary};
+pub use disasm::{ControlFlow, Block, Edge, Subroutine, GraphFormat};
+pub use emulator::{Chip8, Config, Step, Halt, MemAddr};
 pub use errors::*;
+pub use interconnect::{MemoryPolicy, Watchpoint, WatchKind, WatchHit};
+pub use quirks::{Machine, Quirks, IndexIncrement};
+pub use trace::{Tracer, TraceFormat, TracePoint, TraceRecord, TraceRegs, TraceScreen, MemWrite};
+pub use trace::{TraceReader, Divergence, diff_trace};
+pub use dap::DebugAdapter;
+pub use gdb::GdbServer;
+pub use frontend::{VideoSink, InputSource, AudioSink, NullAudio, Command};
+#[cfg(feature = "minifb")]
+pub use frontend::WindowFrontend;
 
 
 #[test]
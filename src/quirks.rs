@@ -0,0 +1,145 @@
+//
+// Rust Core Imports
+//
+use std::str::FromStr;
+
+//
+// This Crate Imports
+//
+use errors::*;
+
+/// The instruction set and hardware the ROM was written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Machine {
+    /// The original 35 instructions, on a 64x32 screen
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1, adding scrolling, a 128x64 screen and large sprites
+    SuperChip,
+    /// XO-CHIP, adding 64K of memory, two bitplanes and sampled audio
+    XoChip,
+}
+
+impl Machine {
+    /// The quirks ROMs for this machine usually expect
+    pub fn quirks(&self) -> Quirks {
+        match *self {
+            Machine::Chip8 => Quirks::default(),
+            Machine::SuperChip => Quirks::schip(),
+            Machine::XoChip => Quirks::xochip(),
+        }
+    }
+}
+
+impl FromStr for Machine {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "chip8" => Ok(Machine::Chip8),
+            "schip" => Ok(Machine::SuperChip),
+            "xochip" => Ok(Machine::XoChip),
+            _ => bail!(ErrorKind::UnknownOption("machine", s.to_string())),
+        }
+    }
+}
+
+/// How `RegDump` (FX55) and `RegLoad` (FX65) leave the `I` register
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndexIncrement {
+    /// `I` is not changed
+    Unchanged,
+    /// `I += X`
+    ByX,
+    /// `I += X + 1`, pointing just past the last register
+    ByXPlusOne,
+}
+
+/// Behaviors which differ between the CHIP-8 interpreters ROMs were written for
+///
+/// `Quirks::default()` is how this emulator has always behaved, the named
+/// presets match the original interpreters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    /// `ShiftRight`/`ShiftLeft` shift `VY` into `VX`, instead of shifting `VX` in place
+    pub shift_uses_vy: bool,
+    /// What `RegDump`/`RegLoad` do to `I`
+    pub index_increment: IndexIncrement,
+    /// `JumpAddV0` (BXNN) jumps to `XNN + VX` instead of `XNN + V0`
+    pub jump_uses_vx: bool,
+    /// `BitOr`/`BitAnd`/`BitXor` clear `VF`
+    pub logic_resets_vf: bool,
+    /// Sprites are cut off at the edge of the screen instead of wrapping around
+    pub clip_sprites: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter
+    pub fn vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::ByXPlusOne,
+            jump_uses_vx: false,
+            logic_resets_vf: true,
+            clip_sprites: true,
+        }
+    }
+
+    /// CHIP-48 on the HP-48 calculators
+    pub fn chip48() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::ByX,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+        }
+    }
+
+    /// SUPER-CHIP 1.1
+    pub fn schip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::Unchanged,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+        }
+    }
+
+    /// XO-CHIP, as implemented by Octo
+    pub fn xochip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::ByXPlusOne,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::Unchanged,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+        }
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "default" => Ok(Quirks::default()),
+            "vip" => Ok(Quirks::vip()),
+            "chip48" => Ok(Quirks::chip48()),
+            "schip" => Ok(Quirks::schip()),
+            "xochip" => Ok(Quirks::xochip()),
+            _ => bail!(ErrorKind::UnknownOption("quirks preset", s.to_string())),
+        }
+    }
+}
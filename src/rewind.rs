@@ -0,0 +1,177 @@
+//
+// Rust Core Imports
+//
+use std::collections::VecDeque;
+
+/// Run length encode `data` as `(count, byte)` pairs
+///
+/// Save states are mostly empty memory and screen, so this is enough to keep
+/// a long rewind buffer small.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut iter = data.iter().peekable();
+    while let Some(&byte) = iter.next() {
+        let mut count = 1u8;
+        while count < 0xFF && iter.peek() == Some(&&byte) {
+            iter.next();
+            count += 1;
+        }
+        out.push(count);
+        out.push(byte);
+    }
+    out
+}
+
+pub fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for pair in data.chunks(2) {
+        for _ in 0..pair[0] {
+            out.push(pair[1]);
+        }
+    }
+    out
+}
+
+/// A ring buffer of past states, to step the emulator back in time
+///
+/// A snapshot is kept every `interval` frames, along with the keys held in
+/// every frame since the oldest snapshot. Any frame in between can be rebuilt
+/// by loading the snapshot before it, and replaying the keys.
+pub struct Rewind {
+    interval: u64,
+    capacity: usize,
+    snapshots: VecDeque<(u64, Vec<u8>)>,
+    first_key_frame: u64,
+    keys: VecDeque<[bool; 16]>,
+}
+
+impl Rewind {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        Rewind {
+            interval: if interval == 0 { 1 } else { interval },
+            capacity: capacity,
+            snapshots: VecDeque::new(),
+            first_key_frame: 0,
+            keys: VecDeque::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.keys.clear();
+    }
+
+    /// The earliest frame that can still be rewound to
+    pub fn oldest(&self) -> Option<u64> {
+        self.snapshots.front().map(|&(frame, _)| frame)
+    }
+
+    fn next_frame(&self) -> u64 {
+        self.first_key_frame + self.keys.len() as u64
+    }
+
+    /// Does `record` need a snapshot for this frame?
+    pub fn wants_snapshot(&self, frame: u64) -> bool {
+        self.capacity > 0 &&
+        (self.snapshots.is_empty() || frame != self.next_frame() || frame.is_multiple_of(self.interval))
+    }
+
+    /// Log the keys for `frame`, which is about to be run
+    ///
+    /// `snapshot` is the state at the start of the frame, and is only kept
+    /// if `wants_snapshot` asked for it.
+    pub fn record(&mut self, frame: u64, keys: &[bool; 16], snapshot: Option<Vec<u8>>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.snapshots.is_empty() || frame != self.next_frame() {
+            // A gap in the history can't be replayed across
+            self.clear();
+            self.first_key_frame = frame;
+        }
+        if let Some(state) = snapshot {
+            if self.snapshots.is_empty() || frame.is_multiple_of(self.interval) {
+                self.snapshots.push_back((frame, compress(&state)));
+            }
+        }
+        if self.snapshots.is_empty() {
+            return;
+        }
+        self.keys.push_back(*keys);
+
+        while self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+        }
+        let oldest = self.oldest().unwrap_or(frame);
+        while self.first_key_frame < oldest {
+            self.keys.pop_front();
+            self.first_key_frame += 1;
+        }
+    }
+
+    /// The latest snapshot at or before `frame`
+    pub fn snapshot(&self, frame: u64) -> Option<(u64, Vec<u8>)> {
+        self.snapshots
+            .iter()
+            .rev()
+            .find(|&&(start, _)| start <= frame)
+            .map(|&(start, ref state)| (start, decompress(state)))
+    }
+
+    /// The keys that were held during `frame`
+    pub fn keys(&self, frame: u64) -> Option<[bool; 16]> {
+        if frame < self.first_key_frame {
+            return None;
+        }
+        self.keys.get((frame - self.first_key_frame) as usize).cloned()
+    }
+
+    /// Forget everything from `frame` onwards
+    pub fn truncate(&mut self, frame: u64) {
+        while self.snapshots.back().is_some_and(|&(start, _)| start > frame) {
+            self.snapshots.pop_back();
+        }
+        let keep = frame.saturating_sub(self.first_key_frame) as usize;
+        self.keys.truncate(keep);
+    }
+}
+
+#[test]
+fn rle_round_trip() {
+    let mut data = vec![0u8; 600];
+    data.extend_from_slice(&[1, 2, 2, 3]);
+    data.extend(vec![0xff; 300]);
+    let packed = compress(&data);
+    assert!(packed.len() < 20);
+    assert_eq!(decompress(&packed), data);
+    assert_eq!(decompress(&compress(&[])), Vec::<u8>::new());
+}
+
+#[test]
+fn rewind_ring_buffer() {
+    let mut rewind = Rewind::new(10, 3);
+    let keys = [false; 16];
+    for frame in 5..50 {
+        let snapshot = if rewind.wants_snapshot(frame) {
+            Some(vec![frame as u8])
+        } else {
+            None
+        };
+        rewind.record(frame, &keys, snapshot);
+    }
+    // 5, 10, 20, 30, 40 were taken, the oldest two fell off
+    assert_eq!(rewind.oldest(), Some(20));
+    assert_eq!(rewind.snapshot(35), Some((30, vec![30])));
+    assert_eq!(rewind.snapshot(19), None);
+    assert!(rewind.keys(19).is_none() && rewind.keys(20).is_some());
+    assert!(rewind.keys(49).is_some() && rewind.keys(50).is_none());
+
+    rewind.truncate(32);
+    assert_eq!(rewind.snapshot(45), Some((30, vec![30])));
+    assert!(rewind.keys(31).is_some() && rewind.keys(32).is_none());
+
+    // Skipping frames starts the history over
+    assert!(rewind.wants_snapshot(33));
+    rewind.record(33, &keys, Some(vec![33]));
+    assert_eq!(rewind.oldest(), Some(33));
+}
@@ -0,0 +1,141 @@
+//
+// This Crate Imports
+//
+use errors::*;
+
+const MAGIC: &'static [u8; 4] = b"C8SS";
+const VERSION: u8 = 1;
+
+/// Builds up a save state, see `Chip8::save_state`
+///
+/// Numbers are stored big endian, the same as Chip8 memory.
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        StateWriter { buf: buf }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.buf.push((value >> 8) as u8);
+        self.buf.push(value as u8);
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        for shift in (0..8).rev() {
+            self.buf.push((value >> (shift * 8)) as u8);
+        }
+    }
+
+    /// Write a length prefixed block of bytes
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.u64(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads back a save state made by `StateWriter`
+pub struct StateReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Check the header, and get ready to read the rest
+    pub fn new(buf: &'a [u8]) -> Result<Self> {
+        if buf.len() < MAGIC.len() + 1 || &buf[..MAGIC.len()] != MAGIC {
+            bail!(ErrorKind::BadSaveState("not a save state"));
+        }
+        if buf[MAGIC.len()] != VERSION {
+            bail!(ErrorKind::BadSaveState("unsupported version"));
+        }
+        Ok(StateReader { buf: &buf[MAGIC.len() + 1..] })
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.buf.len() {
+            bail!(ErrorKind::BadSaveState("truncated"));
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => bail!(ErrorKind::BadSaveState("corrupt")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(self.take(8)?.iter().fold(0, |acc, &b| acc << 8 | b as u64))
+    }
+
+    /// Read a block written by `StateWriter::bytes`, which must be `len` long
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.u64()? != len as u64 {
+            bail!(ErrorKind::BadSaveState("saved from a different machine"));
+        }
+        self.take(len)
+    }
+
+    /// Make sure nothing was left unread
+    pub fn finish(self) -> Result<()> {
+        if !self.buf.is_empty() {
+            bail!(ErrorKind::BadSaveState("trailing data"));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn state_round_trip() {
+    let mut writer = StateWriter::new();
+    writer.u8(0xab);
+    writer.bool(true);
+    writer.u16(0x1234);
+    writer.u64(0x0102030405060708);
+    writer.bytes(&[1, 2, 3]);
+    let buf = writer.finish();
+
+    let mut reader = StateReader::new(&buf).unwrap();
+    assert_eq!(reader.u8().unwrap(), 0xab);
+    assert!(reader.bool().unwrap());
+    assert_eq!(reader.u16().unwrap(), 0x1234);
+    assert_eq!(reader.u64().unwrap(), 0x0102030405060708);
+    assert_eq!(reader.bytes(3).unwrap(), &[1, 2, 3]);
+    reader.finish().unwrap();
+
+    let mut short = StateReader::new(&buf[..7]).unwrap();
+    short.u8().unwrap();
+    assert!(short.u16().is_err());
+    assert!(StateReader::new(b"C8SX\x01").is_err());
+    assert!(StateReader::new(b"C8SS\x02").is_err());
+}
@@ -0,0 +1,211 @@
+//
+// Rust Core Imports
+//
+use std::fmt;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use emulator::Chip8;
+use super::{TraceRecord, TraceRegs, TraceScreen};
+
+const PIXELS: [char; 4] = ['.', '#', '+', '@'];
+const REG_NAMES: [&'static str; 20] = ["V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7", "V8", "V9",
+                                       "VA", "VB", "VC", "VD", "VE", "VF", "I", "SP", "DT", "ST"];
+
+// The registers as they are shown, in the order of `REG_NAMES`
+fn reg_values(regs: &TraceRegs) -> Vec<String> {
+    let mut values: Vec<String> = regs.v.iter().map(|v| format!("0x{:02x}", v)).collect();
+    values.push(format!("0x{:04x}", regs.i));
+    values.push(regs.sp.to_string());
+    values.push(regs.dt.to_string());
+    values.push(regs.st.to_string());
+    values
+}
+
+/// Where a run first disagreed with a reference trace
+///
+/// The `Display` shows the two states side by side, with `*` by each row
+/// that differs. Registers that were already different before the
+/// instruction ran are listed after them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// What the reference trace recorded
+    pub expected: TraceRecord,
+    /// What this emulator did
+    pub actual: TraceRecord,
+    /// The names of the fields that differ, e.g. `PC`, `V3` or `screen`, each
+    /// listed once in the order they are shown
+    pub fields: Vec<String>,
+}
+
+/// Run `chip8` alongside a `reference` trace, stopping at the first
+/// instruction where they disagree
+///
+/// The run is fast forwarded to the first record's cycle. After each
+/// instruction the PC, registers, `I`, timers, and the stack and screen where
+/// the reference has them, are compared. Returns how many instructions matched,
+/// and the divergence if there was one.
+pub fn diff_trace<I>(chip8: &mut Chip8, reference: I) -> Result<(u64, Option<Divergence>)>
+    where I: IntoIterator<Item = Result<TraceRecord>>
+{
+    let mut matched = 0;
+    for expected in reference {
+        let expected = expected?;
+        if matched == 0 {
+            while chip8.cycles() < expected.cycle {
+                chip8.step()?;
+            }
+        }
+        let (_, mut actual) = chip8.step_traced()?;
+        let mut fields = Vec::new();
+        if expected.pc != actual.pc {
+            fields.push("PC".to_string());
+        }
+        // Only the first record can start out different, after that any
+        // difference has already been caught
+        let after = (reg_values(&expected.after), reg_values(&actual.after));
+        let before = (reg_values(&expected.before), reg_values(&actual.before));
+        for (n, name) in REG_NAMES.iter().enumerate() {
+            if after.0[n] != after.1[n] || (matched == 0 && before.0[n] != before.1[n]) {
+                fields.push(name.to_string());
+            }
+        }
+        if expected.stack.is_some() && expected.stack != actual.stack {
+            fields.push("stack".to_string());
+        }
+        if expected.screen.is_some() {
+            // The reference may record the screen where this emulator didn't
+            let (width, height) = chip8.screen_size();
+            actual.screen = Some(TraceScreen::new(width, height, chip8.graphics()));
+            if expected.screen != actual.screen {
+                fields.push("screen".to_string());
+            }
+        }
+        if !fields.is_empty() {
+            return Ok((matched,
+                       Some(Divergence {
+                expected: expected,
+                actual: actual,
+                fields: fields,
+            })));
+        }
+        matched += 1;
+    }
+    Ok((matched, None))
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (want, got) = (&self.expected, &self.actual);
+        writeln!(f,
+                 "Diverged at cycle {}: {}",
+                 want.cycle,
+                 self.fields.join(", "))?;
+        writeln!(f, "  {:8} {:24} actual", "", "expected")?;
+        let stack = |record: &TraceRecord| match record.stack {
+            Some(ref stack) if stack.is_empty() => "(empty)".to_string(),
+            Some(ref stack) => {
+                let addrs: Vec<String> = stack.iter().map(|a| format!("0x{:04x}", a)).collect();
+                addrs.join(" ")
+            }
+            None => "-".to_string(),
+        };
+        let mut rows = vec![("PC", format!("0x{:04x}", want.pc), format!("0x{:04x}", got.pc)),
+                            ("opcode", want.opcode.to_string(), got.opcode.to_string())];
+        rows.extend(REG_NAMES.iter()
+            .cloned()
+            .zip(reg_values(&want.after).into_iter().zip(reg_values(&got.after)))
+            .map(|(name, (want, got))| (name, want, got)));
+        rows.push(("stack", stack(want), stack(got)));
+        let row = |f: &mut fmt::Formatter, name: &str, want: &str, got: &str| {
+            let marker = if want != got { '*' } else { ' ' };
+            writeln!(f, "{} {:8} {:24} {}", marker, name, want, got)
+        };
+        for &(name, ref want, ref got) in &rows {
+            row(f, name, want, got)?;
+        }
+        if want.before != got.before {
+            writeln!(f, "before the instruction:")?;
+            let values = reg_values(&want.before).into_iter().zip(reg_values(&got.before));
+            for (name, (want, got)) in REG_NAMES.iter().zip(values) {
+                if want != got {
+                    row(f, name, &want, &got)?;
+                }
+            }
+        }
+        if let (Some(want), Some(got)) = (want.screen.as_ref(), got.screen.as_ref()) {
+            if want != got {
+                writeln!(f, "screen, expected then actual:")?;
+                let rows = |screen: &TraceScreen| -> Vec<String> {
+                    screen.pixels
+                        .chunks(screen.width)
+                        .map(|row| row.iter().map(|&p| PIXELS[p as usize & 3]).collect())
+                        .collect()
+                };
+                let (want, got) = (rows(want), rows(got));
+                for n in 0..want.len().max(got.len()) {
+                    writeln!(f,
+                             "  {}  {}",
+                             want.get(n).map_or("", |r| r.as_str()),
+                             got.get(n).map_or("", |r| r.as_str()))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn diff_against_reference() {
+    // LOAD V0 0x7b; LOAD VI 0x0300; BCD V0; CALL 0x020a; JUMP 0x0208; RETURN
+    let rom = [0x60, 0x7b, 0xa3, 0x00, 0xf0, 0x33, 0x22, 0x0a, 0x12, 0x08, 0x00, 0xee];
+    let mut reference = Chip8::init(None);
+    reference.load_bytes(&rom).unwrap();
+    let records: Vec<TraceRecord> =
+        (0..6).map(|_| reference.step_traced().unwrap().1).collect();
+
+    let mut chip8 = Chip8::init(None);
+    chip8.load_bytes(&rom).unwrap();
+    let (matched, divergence) = diff_trace(&mut chip8, records.iter().cloned().map(Ok)).unwrap();
+    assert_eq!((matched, divergence), (6, None));
+
+    // Starting part way through
+    let mut chip8 = Chip8::init(None);
+    chip8.load_bytes(&rom).unwrap();
+    let (matched, divergence) = diff_trace(&mut chip8, records[2..].iter().cloned().map(Ok))
+        .unwrap();
+    assert_eq!((matched, divergence), (4, None));
+
+    let mut wrong = records.clone();
+    wrong[3].after.v[3] = 1;
+    wrong[3].stack = Some(vec![]);
+    let mut chip8 = Chip8::init(None);
+    chip8.load_bytes(&rom).unwrap();
+    let (matched, divergence) = diff_trace(&mut chip8, wrong.into_iter().map(Ok)).unwrap();
+    let divergence = divergence.unwrap();
+    assert_eq!(matched, 3);
+    assert_eq!(divergence.fields, vec!["V3", "stack"]);
+    let shown = divergence.to_string();
+    assert!(shown.starts_with("Diverged at cycle 3: V3, stack\n"));
+    assert!(shown.contains("* V3       0x01                     0x00\n"));
+    assert!(shown.contains("  V0       0x7b                     0x7b\n"));
+    assert!(shown.contains("* stack    (empty)                  0x0208\n"));
+    assert!(!shown.contains("before the instruction"));
+
+    // A register that starts out different is only listed once
+    let mut wrong = records.clone();
+    wrong[0].before.v[0] = 1;
+    wrong[0].after.v[0] = 1;
+    wrong[0].before.i = 0x300;
+    let mut chip8 = Chip8::init(None);
+    chip8.load_bytes(&rom).unwrap();
+    let divergence = diff_trace(&mut chip8, wrong.into_iter().map(Ok)).unwrap().1.unwrap();
+    assert_eq!(divergence.fields, vec!["V0", "I"]);
+    let shown = divergence.to_string();
+    assert!(shown.contains("  I        0x0000                   0x0000\n"));
+    assert!(shown.contains("before the instruction:\n\
+                            * V0       0x01                     0x00\n\
+                            * I        0x0300                   0x0000\n"));
+}
@@ -0,0 +1,412 @@
+//
+// Rust Core Imports
+//
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+//
+// This Crate Imports
+//
+use errors::*;
+use cpu::Opcode;
+use emulator::MemAddr;
+
+//
+// Declare sub modules
+//
+mod diff;
+mod reader;
+
+//
+// Public Exports
+//
+pub use self::diff::{Divergence, diff_trace};
+pub use self::reader::TraceReader;
+
+const MAGIC: &'static [u8; 4] = b"C8TR";
+const VERSION: u8 = 2;
+// Written in place of the stack depth when the stack wasn't recorded
+const NO_STACK: u8 = 0xFF;
+
+/// How a `Tracer` writes its records
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    /// One JSON object per line
+    JsonLines,
+    /// A `C8TR` header, then fixed size big endian records
+    Binary,
+}
+
+impl FromStr for TraceFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jsonl" | "json" => Ok(TraceFormat::JsonLines),
+            "binary" | "bin" => Ok(TraceFormat::Binary),
+            _ => bail!(ErrorKind::UnknownOption("trace format", s.to_string())),
+        }
+    }
+}
+
+/// Where a `Tracer` starts or stops recording
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TracePoint {
+    /// The instruction with this cycle number, counting from 0
+    Cycle(u64),
+    /// The first time the instruction at this address is run
+    Addr(MemAddr),
+}
+
+impl TracePoint {
+    fn reached(&self, cycle: u64, pc: MemAddr) -> bool {
+        match *self {
+            TracePoint::Cycle(n) => cycle >= n,
+            TracePoint::Addr(addr) => pc == addr,
+        }
+    }
+}
+
+/// Parses a cycle number like `1000`, or an address like `pc=0x0234`
+impl FromStr for TracePoint {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let number = |s: &str| if let Some(hex) = s.strip_prefix("0x") {
+            u64::from_str_radix(hex, 16).ok()
+        } else {
+            s.parse().ok()
+        };
+        let point = if let Some(addr) = s.strip_prefix("pc=") {
+            number(addr).and_then(|addr| if addr <= 0xFFFF {
+                Some(TracePoint::Addr(addr as MemAddr))
+            } else {
+                None
+            })
+        } else {
+            number(s).map(TracePoint::Cycle)
+        };
+        point.ok_or_else(|| ErrorKind::UnknownOption("trace point", s.to_string()).into())
+    }
+}
+
+/// The registers on one side of a traced instruction
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TraceRegs {
+    /// `V0`-`VF`
+    pub v: [u8; 16],
+    /// The index register
+    pub i: MemAddr,
+    /// The stack depth
+    pub sp: u8,
+    /// The delay timer
+    pub dt: u8,
+    /// The sound timer
+    pub st: u8,
+}
+
+/// A byte of memory changed by a traced instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemWrite {
+    /// Where the byte was written
+    pub addr: MemAddr,
+    /// The value it had before
+    pub old: u8,
+    /// The value written
+    pub new: u8,
+}
+
+/// The screen after a traced instruction that drew on it
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceScreen {
+    /// Width in pixels
+    pub width: usize,
+    /// Height in pixels
+    pub height: usize,
+    /// The palette index of each pixel, 0-3, in row major order
+    pub pixels: Vec<u8>,
+}
+
+impl TraceScreen {
+    /// Copy a screen, as returned by `Chip8::graphics`
+    pub fn new(width: usize, height: usize, pixels: &[u8]) -> Self {
+        TraceScreen {
+            width: width,
+            height: height,
+            pixels: pixels.to_vec(),
+        }
+    }
+
+    /// Four pixels to a byte, the first in the top two bits
+    fn packed(&self) -> Vec<u8> {
+        self.pixels
+            .chunks(4)
+            .map(|four| four.iter().enumerate().fold(0, |b, (n, &p)| b | (p & 3) << (6 - 2 * n)))
+            .collect()
+    }
+
+    fn unpack(width: usize, height: usize, packed: &[u8]) -> Result<Self> {
+        if packed.len() * 4 != width * height {
+            bail!(ErrorKind::BadTrace("screen is the wrong size".to_string()));
+        }
+        let pixels = packed.iter()
+            .flat_map(|&b| (0..4).map(move |n| b >> (6 - 2 * n) & 3))
+            .collect();
+        Ok(TraceScreen {
+            width: width,
+            height: height,
+            pixels: pixels,
+        })
+    }
+}
+
+/// One instruction, as written to a trace
+///
+/// `after` is taken straight after the instruction, before the timers tick at
+/// the end of a frame. A reference trace from another emulator may leave out
+/// the stack, and only has a screen where it chose to record one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    /// How many instructions ran before this one
+    pub cycle: u64,
+    /// Where the instruction is
+    pub pc: MemAddr,
+    /// The instruction as stored in memory, 4 bytes for XO-CHIP's long load
+    pub raw: u32,
+    /// The decoded instruction
+    pub opcode: Opcode,
+    /// The registers before the instruction ran
+    pub before: TraceRegs,
+    /// The registers after it ran
+    pub after: TraceRegs,
+    /// Every memory write it made, in order
+    pub writes: Vec<MemWrite>,
+    /// The return addresses on the stack afterwards, innermost last
+    pub stack: Option<Vec<MemAddr>>,
+    /// The screen, if the instruction changed it
+    pub screen: Option<TraceScreen>,
+}
+
+fn regs_json(regs: &TraceRegs) -> ::serde_json::Value {
+    json!({"v": regs.v, "i": regs.i, "sp": regs.sp, "dt": regs.dt, "st": regs.st})
+}
+
+fn regs_binary(regs: &TraceRegs, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&regs.v);
+    buf.push((regs.i >> 8) as u8);
+    buf.push(regs.i as u8);
+    buf.push(regs.sp);
+    buf.push(regs.dt);
+    buf.push(regs.st);
+}
+
+impl TraceRecord {
+    /// The record as a single line of JSON, without the newline
+    pub fn to_json(&self) -> String {
+        let writes: Vec<_> = self.writes
+            .iter()
+            .map(|w| json!({"addr": w.addr, "old": w.old, "new": w.new}))
+            .collect();
+        let mut record = json!({
+            "cycle": self.cycle,
+            "pc": self.pc,
+            "raw": self.raw,
+            "opcode": self.opcode.to_string(),
+            "before": regs_json(&self.before),
+            "after": regs_json(&self.after),
+            "writes": writes,
+        });
+        if let Some(ref stack) = self.stack {
+            record["stack"] = json!(stack);
+        }
+        if let Some(ref screen) = self.screen {
+            let pixels: String = screen.packed().iter().map(|b| format!("{:02x}", b)).collect();
+            record["screen"] = json!({
+                "width": screen.width,
+                "height": screen.height,
+                "pixels": pixels,
+            });
+        }
+        record.to_string()
+    }
+
+    /// Append the binary form of the record to `buf`
+    ///
+    /// That is the cycle (8 bytes), pc (2), raw instruction (4), the registers
+    /// before and after (21 each: `V0`-`VF`, `I` as 2 bytes, `SP`, `DT`, `ST`),
+    /// then a 2 byte count of writes, each an address (2), old and new value.
+    /// Then the stack depth (1, `0xFF` if not recorded) and return addresses
+    /// (2 each). Last is the screen width and height (1 each, both 0 if there
+    /// is no screen), and the pixels packed four to a byte.
+    pub fn to_binary(&self, buf: &mut Vec<u8>) {
+        for shift in (0..8).rev() {
+            buf.push((self.cycle >> (shift * 8)) as u8);
+        }
+        buf.push((self.pc >> 8) as u8);
+        buf.push(self.pc as u8);
+        for shift in (0..4).rev() {
+            buf.push((self.raw >> (shift * 8)) as u8);
+        }
+        regs_binary(&self.before, buf);
+        regs_binary(&self.after, buf);
+        buf.push((self.writes.len() >> 8) as u8);
+        buf.push(self.writes.len() as u8);
+        for write in &self.writes {
+            buf.push((write.addr >> 8) as u8);
+            buf.push(write.addr as u8);
+            buf.push(write.old);
+            buf.push(write.new);
+        }
+        match self.stack {
+            Some(ref stack) => {
+                buf.push(stack.len() as u8);
+                for &addr in stack {
+                    buf.push((addr >> 8) as u8);
+                    buf.push(addr as u8);
+                }
+            }
+            None => buf.push(NO_STACK),
+        }
+        match self.screen {
+            Some(ref screen) => {
+                buf.push(screen.width as u8);
+                buf.push(screen.height as u8);
+                buf.extend_from_slice(&screen.packed());
+            }
+            None => buf.extend_from_slice(&[0, 0]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TraceState {
+    Waiting,
+    Tracing,
+    Stopped,
+}
+
+/// Records every instruction a `Chip8` runs, see `Chip8::trace`
+///
+/// By default the whole run is traced. `start_at` and `stop_at` narrow it to
+/// the instructions from one point up to, but not including, another.
+pub struct Tracer {
+    out: Box<dyn Write + Send>,
+    format: TraceFormat,
+    start: Option<TracePoint>,
+    stop: Option<TracePoint>,
+    state: TraceState,
+    records: u64,
+    buf: Vec<u8>,
+}
+
+impl Tracer {
+    /// Trace to `out`
+    pub fn new<W: Write + Send + 'static>(out: W, format: TraceFormat) -> Result<Self> {
+        let mut out = Box::new(out);
+        if format == TraceFormat::Binary {
+            out.write_all(MAGIC)?;
+            out.write_all(&[VERSION])?;
+        }
+        Ok(Tracer {
+            out: out,
+            format: format,
+            start: None,
+            stop: None,
+            state: TraceState::Waiting,
+            records: 0,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Trace to a new file at `path`
+    pub fn create(path: &Path, format: TraceFormat) -> Result<Self> {
+        Tracer::new(BufWriter::new(File::create(path)?), format)
+    }
+
+    /// Don't record anything until `point`
+    pub fn start_at(&mut self, point: TracePoint) {
+        self.start = Some(point);
+    }
+
+    /// Stop recording for good at `point`
+    pub fn stop_at(&mut self, point: TracePoint) {
+        self.stop = Some(point);
+    }
+
+    /// How many instructions have been recorded
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Flush anything still buffered
+    pub fn finish(mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+
+    /// Whether the instruction about to run should be recorded
+    pub(crate) fn wants(&mut self, cycle: u64, pc: MemAddr) -> bool {
+        if self.state == TraceState::Waiting && self.start.is_none_or(|p| p.reached(cycle, pc)) {
+            self.state = TraceState::Tracing;
+        }
+        if self.state == TraceState::Tracing && self.stop.is_some_and(|p| p.reached(cycle, pc)) {
+            self.state = TraceState::Stopped;
+        }
+        self.state == TraceState::Tracing
+    }
+
+    pub(crate) fn record(&mut self, record: &TraceRecord) -> Result<()> {
+        match self.format {
+            TraceFormat::JsonLines => writeln!(self.out, "{}", record.to_json())?,
+            TraceFormat::Binary => {
+                self.buf.clear();
+                record.to_binary(&mut self.buf);
+                self.out.write_all(&self.buf)?;
+            }
+        }
+        self.records += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn trace_points() {
+    assert_eq!("1000".parse::<TracePoint>().unwrap(), TracePoint::Cycle(1000));
+    assert_eq!("0x10".parse::<TracePoint>().unwrap(), TracePoint::Cycle(16));
+    assert_eq!("pc=0x0234".parse::<TracePoint>().unwrap(), TracePoint::Addr(0x234));
+    assert!("pc=0x10000".parse::<TracePoint>().is_err());
+    assert!("soon".parse::<TracePoint>().is_err());
+
+    let mut tracer = Tracer::new(Vec::new(), TraceFormat::JsonLines).unwrap();
+    tracer.start_at(TracePoint::Addr(0x204));
+    tracer.stop_at(TracePoint::Cycle(5));
+    let traced: Vec<bool> = [0x200, 0x204, 0x206, 0x200, 0x204, 0x206]
+        .iter()
+        .enumerate()
+        .map(|(cycle, &pc)| tracer.wants(cycle as u64, pc))
+        .collect();
+    assert_eq!(traced, vec![false, true, true, true, true, false]);
+}
+
+#[test]
+fn binary_record() {
+    let mut tracer = Tracer::new(Vec::new(), TraceFormat::Binary).unwrap();
+    let record = TraceRecord {
+        cycle: 0x0102,
+        pc: 0x0204,
+        raw: 0xf033,
+        opcode: Opcode::BCD(::cpu::reg(0).unwrap()),
+        before: TraceRegs::default(),
+        after: TraceRegs { i: 0x0300, ..TraceRegs::default() },
+        writes: vec![MemWrite { addr: 0x0300, old: 0, new: 1 }],
+        stack: Some(vec![0x0202]),
+        screen: None,
+    };
+    tracer.record(&record).unwrap();
+    let mut buf = Vec::new();
+    record.to_binary(&mut buf);
+    assert_eq!(buf.len(), 8 + 2 + 4 + 21 + 21 + 2 + 4 + 3 + 2);
+    assert_eq!(&buf[..14], &[0, 0, 0, 0, 0, 0, 1, 2, 0x02, 0x04, 0, 0, 0xf0, 0x33]);
+    assert_eq!(&buf[51..53], &[0x03, 0x00]);
+    assert_eq!(&buf[56..], &[0, 1, 0x03, 0x00, 0, 1, 1, 0x02, 0x02, 0, 0]);
+}
@@ -0,0 +1,302 @@
+//
+// Rust Core Imports
+//
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+//
+// Third Party Imports
+//
+use serde_json::{self, Value};
+
+//
+// This Crate Imports
+//
+use errors::*;
+use cpu::decode;
+use emulator::MemAddr;
+use super::{TraceRecord, TraceRegs, TraceScreen, MemWrite, MAGIC, VERSION, NO_STACK};
+
+fn bad(reason: String) -> Error {
+    ErrorKind::BadTrace(reason).into()
+}
+
+/// Reads back the records written by a `Tracer`, in either format
+///
+/// The format is worked out from the first bytes. JSON lines from another
+/// emulator need `pc`, `raw`, `before` and `after`, with `v`, `i`, `sp`, `dt`
+/// and `st` in each. Without a `cycle` the records count up from 0, and
+/// `writes`, `stack` and `screen` can be left out.
+pub struct TraceReader<R> {
+    input: R,
+    binary: bool,
+    next_cycle: u64,
+    line: u64,
+}
+
+impl TraceReader<BufReader<File>> {
+    /// Read the trace at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        TraceReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> TraceReader<R> {
+    /// Read a trace from `input`
+    pub fn new(mut input: R) -> Result<Self> {
+        let binary = input.fill_buf()?.starts_with(MAGIC);
+        if binary {
+            let mut header = [0; 5];
+            input.read_exact(&mut header)?;
+            if header[4] != VERSION {
+                return Err(bad(format!("unsupported version {}", header[4])));
+            }
+        }
+        Ok(TraceReader {
+            input: input,
+            binary: binary,
+            next_cycle: 0,
+            line: 0,
+        })
+    }
+
+    fn read_bytes(&mut self, n: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0; n];
+        self.input.read_exact(&mut buf).chain_err(|| bad("truncated record".to_string()))?;
+        Ok(buf)
+    }
+
+    fn read_number(&mut self, n: usize) -> Result<u64> {
+        Ok(self.read_bytes(n)?.iter().fold(0, |value, &b| value << 8 | b as u64))
+    }
+
+    fn read_regs(&mut self) -> Result<TraceRegs> {
+        let bytes = self.read_bytes(21)?;
+        let mut regs = TraceRegs {
+            v: [0; 16],
+            i: (bytes[16] as MemAddr) << 8 | bytes[17] as MemAddr,
+            sp: bytes[18],
+            dt: bytes[19],
+            st: bytes[20],
+        };
+        regs.v.copy_from_slice(&bytes[..16]);
+        Ok(regs)
+    }
+
+    fn read_binary(&mut self) -> Result<TraceRecord> {
+        let cycle = self.read_number(8)?;
+        let pc = self.read_number(2)? as MemAddr;
+        let raw = self.read_number(4)? as u32;
+        let before = self.read_regs()?;
+        let after = self.read_regs()?;
+        let mut writes = Vec::new();
+        for _ in 0..self.read_number(2)? {
+            let bytes = self.read_bytes(4)?;
+            writes.push(MemWrite {
+                addr: (bytes[0] as MemAddr) << 8 | bytes[1] as MemAddr,
+                old: bytes[2],
+                new: bytes[3],
+            });
+        }
+        let stack = match self.read_number(1)? as u8 {
+            NO_STACK => None,
+            depth => {
+                let mut stack = Vec::new();
+                for _ in 0..depth {
+                    stack.push(self.read_number(2)? as MemAddr);
+                }
+                Some(stack)
+            }
+        };
+        let size = self.read_bytes(2)?;
+        let (width, height) = (size[0] as usize, size[1] as usize);
+        let screen = if width == 0 {
+            None
+        } else {
+            let packed = self.read_bytes(width * height / 4)?;
+            Some(TraceScreen::unpack(width, height, &packed)?)
+        };
+        Ok(TraceRecord {
+            cycle: cycle,
+            pc: pc,
+            raw: raw,
+            opcode: opcode(raw)?,
+            before: before,
+            after: after,
+            writes: writes,
+            stack: stack,
+            screen: screen,
+        })
+    }
+
+    fn read_json(&mut self, line: &str) -> Result<TraceRecord> {
+        let record: Value = serde_json::from_str(line)?;
+        let raw = number(&record, "raw")? as u32;
+        let writes = match record.get("writes").and_then(Value::as_array) {
+            Some(writes) => {
+                writes.iter()
+                    .map(|w| {
+                        Ok(MemWrite {
+                            addr: number(w, "addr")? as MemAddr,
+                            old: number(w, "old")? as u8,
+                            new: number(w, "new")? as u8,
+                        })
+                    })
+                    .collect::<Result<_>>()?
+            }
+            None => Vec::new(),
+        };
+        let stack = match record.get("stack").and_then(Value::as_array) {
+            Some(stack) => {
+                let addrs: Option<Vec<MemAddr>> =
+                    stack.iter().map(|a| a.as_u64().map(|a| a as MemAddr)).collect();
+                Some(addrs.ok_or_else(|| bad("stack is not a list of addresses".to_string()))?)
+            }
+            None => None,
+        };
+        let screen = match record.get("screen") {
+            Some(screen) => {
+                let hex = screen.get("pixels").and_then(Value::as_str).unwrap_or("");
+                let packed: Option<Vec<u8>> = (0..hex.len() / 2)
+                    .map(|n| hex.get(2 * n..2 * n + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+                    .collect();
+                let packed = packed.ok_or_else(|| bad("screen pixels are not hex".to_string()))?;
+                Some(TraceScreen::unpack(number(screen, "width")? as usize,
+                                         number(screen, "height")? as usize,
+                                         &packed)?)
+            }
+            None => None,
+        };
+        Ok(TraceRecord {
+            cycle: match record.get("cycle") {
+                Some(_) => number(&record, "cycle")?,
+                None => self.next_cycle,
+            },
+            pc: number(&record, "pc")? as MemAddr,
+            raw: raw,
+            opcode: opcode(raw)?,
+            before: regs(&record, "before")?,
+            after: regs(&record, "after")?,
+            writes: writes,
+            stack: stack,
+            screen: screen,
+        })
+    }
+}
+
+fn number(value: &Value, name: &str) -> Result<u64> {
+    value.get(name)
+        .and_then(Value::as_u64)
+        .ok_or_else(|| bad(format!("'{}' is missing or not a number", name)))
+}
+
+fn regs(record: &Value, name: &str) -> Result<TraceRegs> {
+    let regs = record.get(name).ok_or_else(|| bad(format!("'{}' is missing", name)))?;
+    let v = regs.get("v")
+        .and_then(Value::as_array)
+        .and_then(|v| v.iter().map(|x| x.as_u64().map(|x| x as u8)).collect::<Option<Vec<u8>>>())
+        .and_then(|v| if v.len() == 16 { Some(v) } else { None })
+        .ok_or_else(|| bad(format!("'{}' needs 16 registers in 'v'", name)))?;
+    let mut out = TraceRegs {
+        v: [0; 16],
+        i: number(regs, "i")? as MemAddr,
+        sp: number(regs, "sp")? as u8,
+        dt: number(regs, "dt")? as u8,
+        st: number(regs, "st")? as u8,
+    };
+    out.v.copy_from_slice(&v);
+    Ok(out)
+}
+
+// `raw` holds 4 bytes for a long load, and 2 for anything else
+fn opcode(raw: u32) -> Result<::cpu::Opcode> {
+    let decoded = if raw > 0xFFFF {
+        decode((raw >> 16) as u16, raw as u16)
+    } else {
+        decode(raw as u16, 0)
+    };
+    decoded.chain_err(|| bad(format!("can not decode instruction 0x{:04x}", raw)))
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Result<TraceRecord>> {
+        let record = if self.binary {
+            match self.input.fill_buf() {
+                Ok([]) => return None,
+                Ok(_) => self.read_binary(),
+                Err(e) => Err(e.into()),
+            }
+        } else {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                match self.input.read_line(&mut line) {
+                    Ok(0) => return None,
+                    Ok(_) if line.trim().is_empty() => {}
+                    Ok(_) => break,
+                    Err(e) => return Some(Err(e.into())),
+                }
+            }
+            self.line += 1;
+            let line_number = self.line;
+            self.read_json(&line)
+                .chain_err(|| bad(format!("record on line {}", line_number)))
+        };
+        if let Ok(ref record) = record {
+            self.next_cycle = record.cycle + 1;
+        }
+        Some(record)
+    }
+}
+
+#[test]
+fn read_back_traces() {
+    use std::io::Cursor;
+    use super::TraceFormat;
+    use emulator::Chip8;
+
+    // CLS; LOAD V0 0x7b; LOAD VI 0x0300; BCD V0; CALL 0x020c; JUMP 0x020a; RETURN
+    let rom = [0x00, 0xe0, 0x60, 0x7b, 0xa3, 0x00, 0xf0, 0x33, 0x22, 0x0c, 0x12, 0x0a, 0x00,
+               0xee];
+    let mut chip8 = Chip8::init(None);
+    chip8.load_bytes(&rom).unwrap();
+    let records: Vec<TraceRecord> =
+        (0..7).map(|_| chip8.step_traced().unwrap().1).collect();
+    assert!(records[0].screen.is_some());
+    assert_eq!(records[4].stack, Some(vec![0x020a]));
+    assert_eq!(records[3].writes.len(), 3);
+
+    for &format in &[TraceFormat::JsonLines, TraceFormat::Binary] {
+        let mut buf = Vec::new();
+        if format == TraceFormat::Binary {
+            buf.extend_from_slice(MAGIC);
+            buf.push(VERSION);
+        }
+        for record in &records {
+            match format {
+                TraceFormat::JsonLines => {
+                    buf.extend_from_slice(record.to_json().as_bytes());
+                    buf.push(b'\n');
+                }
+                TraceFormat::Binary => record.to_binary(&mut buf),
+            }
+        }
+        let read: Vec<TraceRecord> =
+            TraceReader::new(Cursor::new(buf)).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(read, records);
+    }
+
+    // Just the fields another emulator would need to provide
+    let minimal = r#"{"pc": 512, "raw": 24699, "before": {"v": [0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0], "i": 0, "sp": 0, "dt": 0, "st": 0}, "after": {"v": [123,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0], "i": 0, "sp": 0, "dt": 0, "st": 0}}
+
+{"pc": 514, "raw": 41728}"#;
+    let mut reader = TraceReader::new(Cursor::new(minimal)).unwrap();
+    let first = reader.next().unwrap().unwrap();
+    assert_eq!((first.cycle, first.after.v[0], first.stack), (0, 123, None));
+    let error = reader.next().unwrap().unwrap_err();
+    assert_eq!(error.to_string(), "Bad trace: record on line 2");
+    assert!(reader.next().is_none());
+}